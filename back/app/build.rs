@@ -0,0 +1,25 @@
+//! Embed the build commit, from `USERMAN_COMMIT` when building outside a git
+//! checkout.
+
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=USERMAN_COMMIT");
+    println!("cargo:rerun-if-changed=../../.git/HEAD");
+    println!("cargo:rerun-if-changed=../../.git/refs/heads");
+
+    let commit = std::env::var("USERMAN_COMMIT").ok().or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .output()
+            .ok()
+            .filter(|t| t.status.success())
+            .and_then(|t| String::from_utf8(t.stdout).ok())
+            .map(|t| t.trim().to_string())
+    });
+
+    println!(
+        "cargo:rustc-env=USERMAN_COMMIT={}",
+        commit.unwrap_or_else(|| String::from("unknown"))
+    );
+}
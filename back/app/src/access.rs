@@ -0,0 +1,158 @@
+//! Request ids and HTTP access log. The id is taken from `X-Request-Id` or
+//! generated, added to every log line written while serving the request and
+//! sent back in the response headers and error bodies.
+
+use axum::extract::{ConnectInfo, MatchedPath};
+use axum::http::header::HeaderName;
+use axum::http::{HeaderMap, HeaderValue, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use log::info;
+use rand::distributions::{Alphanumeric, DistString};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::Shared;
+
+pub static REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+const REQUEST_ID_LEN: usize = 20;
+const REQUEST_ID_MAX_LEN: usize = 128;
+
+#[derive(Debug, Default)]
+struct Context {
+    id: String,
+    ip: Option<String>,
+    user: Mutex<Option<String>>,
+}
+
+tokio::task_local! {
+    static CONTEXT: Arc<Context>;
+}
+
+/// Id of the request being served, if any.
+pub fn request_id() -> Option<String> {
+    CONTEXT.try_with(|t| t.id.clone()).ok()
+}
+
+/// Client IP of the request being served, if any.
+pub fn ip() -> Option<String> {
+    CONTEXT.try_with(|t| t.ip.clone()).ok().flatten()
+}
+
+/// User authenticated by the request being served, if any.
+pub fn user() -> Option<String> {
+    CONTEXT
+        .try_with(|t| t.user.lock().ok().and_then(|t| t.clone()))
+        .ok()
+        .flatten()
+}
+
+/// Record the user authenticated by the request being served.
+pub fn set_user(username: &str) {
+    let _ = CONTEXT.try_with(|t| {
+        if let Ok(mut user) = t.user.lock() {
+            *user = Some(username.to_string());
+        }
+    });
+}
+
+/// Keep the id given by the client or a proxy when it is sane.
+fn incoming_id(headers: &HeaderMap) -> Option<String> {
+    let id = headers.get(&REQUEST_ID)?.to_str().ok()?;
+
+    match !id.is_empty()
+        && id.len() <= REQUEST_ID_MAX_LEN
+        && id.chars().all(|t| t.is_ascii_graphic())
+    {
+        true => Some(id.to_string()),
+        false => None,
+    }
+}
+
+/// Client IP, read from the proxy headers only when the peer is trusted. The
+/// `X-Forwarded-For` chain is walked from the right up to the first untrusted
+/// hop.
+pub fn client_ip(peer: Option<IpAddr>, headers: &HeaderMap, trusted: &[IpAddr]) -> Option<IpAddr> {
+    match peer {
+        Some(t) if trusted.contains(&t) => {}
+        _ => return peer,
+    }
+
+    let forwarded = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|t| t.to_str().ok())
+        .flat_map(|t| t.split(','))
+        .filter_map(|t| t.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<_>>();
+
+    if let Some(t) = forwarded.iter().rev().find(|t| !trusted.contains(t)) {
+        return Some(*t);
+    }
+
+    if let Some(t) = headers
+        .get("x-real-ip")
+        .and_then(|t| t.to_str().ok())
+        .and_then(|t| t.trim().parse::<IpAddr>().ok())
+    {
+        return Some(t);
+    }
+
+    forwarded.first().copied().or(peer)
+}
+
+/// Serve the request with its id in scope, then write its access log line.
+pub async fn track<B>(request: Request<B>, next: Next<B>) -> Response {
+    let start = Instant::now();
+    let id = incoming_id(request.headers())
+        .unwrap_or_else(|| Alphanumeric.sample_string(&mut rand::thread_rng(), REQUEST_ID_LEN));
+    let method = request.method().to_string();
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(t) => t.as_str().to_string(),
+        None => String::from("unmatched"),
+    };
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|t| t.0.ip());
+    let ip = match request.extensions().get::<Shared>() {
+        Some(t) => client_ip(peer, request.headers(), &t.config_yaml.trusted_proxies),
+        None => peer,
+    }
+    .map(|t| t.to_string());
+
+    let context = Arc::new(Context {
+        id: id.clone(),
+        ip: ip.clone(),
+        ..Default::default()
+    });
+
+    let mut response = CONTEXT.scope(context.clone(), next.run(request)).await;
+
+    if let Ok(t) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID.clone(), t);
+    }
+
+    let status = response.status().as_u16();
+    let latency = start.elapsed().as_millis() as u64;
+    let ip = ip.unwrap_or_else(|| String::from("-"));
+    let user = match context.user.lock() {
+        Ok(t) => t.clone().unwrap_or_else(|| String::from("-")),
+        Err(_) => String::from("-"),
+    };
+
+    info!(
+        request_id = id.as_str(),
+        method = method.as_str(),
+        route = route.as_str(),
+        status = status,
+        latency_ms = latency,
+        ip = ip.as_str(),
+        user = user.as_str();
+        "{} {} \"{} {}\" {} {}ms", ip, user, method, route, status, latency
+    );
+
+    response
+}
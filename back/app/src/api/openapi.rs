@@ -5,7 +5,19 @@ use userman_auth::roles::{
 use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
 use utoipa::{Modify, OpenApi};
 
-use crate::users::{User, UsersVec};
+use crate::audit::{AuditChange, AuditEvent, AuditResult, AuditTarget};
+use crate::exports::ExportFormat;
+use crate::imports::{ImportFormat, ImportReport, RowError};
+use crate::logger::{LogLevels, LogsLevel};
+use crate::restore::{ConflictStrategy, RestoreCount, RestoreReport};
+use crate::pages::{
+    AppsPage, AuditPage, DeliveriesPage, RoleNamesPage, RolesPage, SortOrder, UsersPage,
+    WebhooksPage,
+};
+use crate::roles::RoleName;
+use crate::streams::{EntityKind, PermissionEvent};
+use crate::users::User;
+use crate::webhooks::{Delivery, DeliveryStatus, Webhook, WebhookEvent};
 
 use super::v1;
 
@@ -16,6 +28,7 @@ use super::v1;
         v1::sessions::refresh,
         v1::sessions::logout,
         v1::sessions::refresh,
+        v1::sessions::change_password,
         v1::apps::create,
         v1::apps::read,
         v1::apps::read_all,
@@ -36,17 +49,46 @@ use super::v1;
         v1::users::reset,
         v1::users::delete,
         v1::users::username,
+        v1::users::update_avatar,
+        v1::users::read_avatar,
+        v1::users::import,
+        v1::exports::export,
+        v1::exports::restore,
+        v1::audit::read_all,
+        v1::logs::read,
+        v1::logs::update,
+        v1::logs::reset,
+        v1::streams::permissions,
+        v1::webhooks::create,
+        v1::webhooks::read,
+        v1::webhooks::read_all,
+        v1::webhooks::update,
+        v1::webhooks::delete,
+        v1::webhooks::deliveries,
+        v1::webhooks::redeliver,
     ),
     components(
         schemas(
             App,
             AppsVec,
+            AppsPage,
             User,
-            UsersVec,
+            UsersPage,
+            SortOrder,
+            ImportFormat,
+            ImportReport,
+            ExportFormat,
+            ConflictStrategy,
+            RestoreCount,
+            RestoreReport,
+            RowError,
             Item,
             Value,
             Role,
             RolesVec,
+            RolesPage,
+            RoleName,
+            RoleNamesPage,
             RoleItems,
             RoleValues,
             DataValue,
@@ -61,13 +103,38 @@ use super::v1;
             v1::StatusRefreshRes,
             v1::sessions::LogoutReq,
             v1::sessions::ResetReq,
+            v1::sessions::ChangePasswordReq,
             v1::StatusApp,
             v1::StatusApps,
             v1::StatusRole,
             v1::StatusRoles,
+            v1::StatusRoleNames,
             v1::StatusStrings,
             v1::StatusUser,
             v1::StatusUsers,
+            v1::StatusImportReport,
+            v1::StatusRestoreReport,
+            AuditEvent,
+            AuditPage,
+            AuditResult,
+            AuditTarget,
+            AuditChange,
+            v1::StatusAudit,
+            LogsLevel,
+            LogLevels,
+            v1::logs::LogLevelReq,
+            v1::StatusLogLevels,
+            EntityKind,
+            PermissionEvent,
+            Webhook,
+            WebhookEvent,
+            WebhooksPage,
+            Delivery,
+            DeliveryStatus,
+            DeliveriesPage,
+            v1::StatusWebhook,
+            v1::StatusWebhooks,
+            v1::StatusDeliveries,
         )
     ),
     modifiers(&SecurityAddon),
@@ -1,18 +1,27 @@
+use std::collections::HashMap;
 use std::str::FromStr;
 
-use axum::extract::{Json, Path};
+use axum::extract::{Json, Path, Query};
 use axum::response::{Extension, IntoResponse};
 use mongodb::bson::oid::ObjectId;
+use serde::Deserialize;
+use tracing::instrument;
+use utoipa::IntoParams;
 
 use userman_auth::apps::{App, AppsVec};
 use userman_auth::roles::RoleItems;
 
 use super::{Output, Example, Status};
+use crate::audit::{self, AuditEvent};
 use crate::dao::Memory;
 use crate::error::UsermanError;
+use crate::pages::{Page, PageQuery};
 use crate::tokens::SessionToken;
 use crate::Shared;
 
+/// Fields the listing can be sorted by, the first one by default.
+pub(crate) const SORTS: &[&str] = &["name", "version", "createdAt", "updatedAt"];
+
 impl Example for App {
     fn example() -> Self {
         Self {
@@ -63,6 +72,7 @@ impl Example for AppsVec {
         ("token" = [])
     )
 )]
+#[instrument(name = "apps::create", skip_all)]
 pub(crate) async fn create(
     token: SessionToken,
     Extension(shared): Extension<Shared>,
@@ -73,10 +83,21 @@ pub(crate) async fn create(
 
     validate_bool!(create);
 
-    match shared.dao.create_app(&payload).await {
+    let output = match shared.dao.create_app(&payload).await {
         Ok(_) => Output::<()>::Done,
         Err(err) => Output::Failure(err),
-    }
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("apps.create")
+            .target("app", None, Some(&payload.name))
+            .changes(None::<&App>, Some(&payload))
+            .output(&output),
+    )
+    .await;
+
+    output
 }
 
 #[utoipa::path(
@@ -100,6 +121,7 @@ pub(crate) async fn create(
         ("token" = [])
     )
 )]
+#[instrument(name = "apps::read", skip_all)]
 pub(crate) async fn read(
     id: Path<String>,
     token: SessionToken,
@@ -121,38 +143,82 @@ pub(crate) async fn read(
     }
 }
 
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct AppsFilter {
+    /// Text searched in the app name.
+    name: Option<String>,
+    /// Only keep the highest version of every app.
+    latest: Option<bool>,
+}
+
+impl AppsFilter {
+    pub(crate) fn apply(&self, apps: Vec<App>) -> Vec<App> {
+        let name = self.name.as_ref().map(|t| t.to_lowercase());
+
+        let apps = apps
+            .into_iter()
+            .filter(|app| !matches!(name, Some(ref t) if !app.name.to_lowercase().contains(t)));
+
+        if self.latest != Some(true) {
+            return apps.collect();
+        }
+
+        let mut latest: HashMap<String, App> = HashMap::new();
+
+        for app in apps {
+            match latest.get(&app.name) {
+                Some(t) if t.version >= app.version => {}
+                _ => {
+                    latest.insert(app.name.clone(), app);
+                }
+            }
+        }
+
+        latest.into_values().collect()
+    }
+}
+
 #[utoipa::path(
     get, 
     path = "/api/v1/apps",
+    params(PageQuery, AppsFilter),
     responses(
         (
             status = StatusCode::OK, 
             description = "Read apps successfully", 
-            body = StatusApp,
-            example = json!(Status::<AppsVec>::example_ok())
+            body = StatusApps,
+            example = json!(Status::<Page<App>>::example_ok())
         ),
         (
             status = StatusCode::BAD_REQUEST,
             description = "Read apps with error",
-            body = StatusApp,
-            example = json!(Status::<AppsVec>::example_bad_request())
+            body = StatusApps,
+            example = json!(Status::<Page<App>>::example_bad_request())
         )
     ),
     security(
         ("token" = [])
     )
 )]
+#[instrument(name = "apps::read_all", skip_all)]
 pub(crate) async fn read_all(
     token: SessionToken,
     Extension(shared): Extension<Shared>,
+    Query(page): Query<PageQuery>,
+    Query(filter): Query<AppsFilter>,
 ) -> impl IntoResponse {
     let items = permissions!(shared, token);
     let read = value!(items, "/apps/read.boolean");
 
     validate_bool!(read);
 
-    let values = shared.apps.get_all().await;
-    Output::Success(values)
+    let values = filter.apply(shared.apps.get_all().await);
+
+    match page.paginate(values, SORTS, |t| t.id().to_hex()) {
+        Ok(t) => Output::Success(t),
+        Err(err) => Output::Failure(err),
+    }
 }
 
 #[utoipa::path(
@@ -177,6 +243,7 @@ pub(crate) async fn read_all(
         ("token" = [])
     )
 )]
+#[instrument(name = "apps::update", skip_all)]
 pub(crate) async fn update(
     id: Path<String>,
     token: SessionToken,
@@ -188,10 +255,23 @@ pub(crate) async fn update(
 
     validate_bool!(update);
 
-    match shared.dao.update_app_by_id(id.as_str(), &payload).await {
+    let before = audit::cached(&shared.apps, id.as_str()).await;
+
+    let output = match shared.dao.update_app_by_id(id.as_str(), &payload).await {
         Ok(_) => Output::<()>::Done,
         Err(err) => Output::Failure(err),
-    }
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("apps.update")
+            .target("app", Some(id.as_str()), Some(&payload.name))
+            .changes(before.as_ref(), Some(&payload))
+            .output(&output),
+    )
+    .await;
+
+    output
 }
 
 #[utoipa::path(
@@ -215,6 +295,7 @@ pub(crate) async fn update(
         ("token" = [])
     )
 )]
+#[instrument(name = "apps::delete", skip_all)]
 pub(crate) async fn delete(
     id: Path<String>,
     token: SessionToken,
@@ -225,8 +306,21 @@ pub(crate) async fn delete(
 
     validate_bool!(delete);
 
-    match shared.dao.delete_app_by_id(id.as_str()).await {
+    let before = audit::cached(&shared.apps, id.as_str()).await;
+
+    let output = match shared.dao.delete_app_by_id(id.as_str()).await {
         Ok(_) => Output::<()>::Done,
         Err(err) => Output::Failure(err),
-    }
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("apps.delete")
+            .target("app", Some(id.as_str()), before.as_ref().map(|t| t.name.as_str()))
+            .changes(before.as_ref(), None::<&App>)
+            .output(&output),
+    )
+    .await;
+
+    output
 }
@@ -0,0 +1,76 @@
+use axum::extract::Query;
+use axum::response::{Extension, IntoResponse};
+use mongodb::bson::DateTime;
+use serde_json::json;
+use tracing::instrument;
+
+use super::{Example, Output, Status};
+use crate::audit::{AuditChange, AuditEvent, AuditQuery, AuditResult, AuditTarget};
+use crate::error::UsermanError;
+use crate::pages::Page;
+use crate::tokens::SessionToken;
+use crate::Shared;
+
+impl Example for AuditEvent {
+    fn example() -> Self {
+        Self {
+            id: None,
+            actor: Some("admin".to_string()),
+            action: "users.update".to_string(),
+            target: Some(AuditTarget {
+                kind: "user".to_string(),
+                id: Some("ID".to_string()),
+                name: Some("USERNAME".to_string()),
+            }),
+            result: AuditResult::Success,
+            error: None,
+            changes: vec![AuditChange {
+                path: "enabled".to_string(),
+                before: Some(json!(true)),
+                after: Some(json!(false)),
+            }],
+            ip: Some("127.0.0.1".to_string()),
+            request_id: Some("REQUEST_ID".to_string()),
+            created_at: DateTime::now(),
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/audit",
+    params(AuditQuery),
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "Read audit events successfully",
+            body = StatusAudit,
+            example = json!(Status::<Page<AuditEvent>>::example_ok())
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Read audit events with error",
+            body = StatusAudit,
+            example = json!(Status::<Page<AuditEvent>>::example_bad_request())
+        )
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[instrument(name = "audit::read_all", skip_all)]
+pub(crate) async fn read_all(
+    token: SessionToken,
+    Extension(shared): Extension<Shared>,
+    Query(query): Query<AuditQuery>,
+) -> impl IntoResponse {
+    let items = permissions!(shared, token);
+    let read = value!(items, "/audit/read.boolean");
+
+    validate_bool!(read);
+
+    match query.read(&shared.dao).await {
+        Ok(t) => Output::Success(t),
+        Err(err) => Output::Failure(err),
+    }
+}
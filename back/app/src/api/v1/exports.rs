@@ -0,0 +1,242 @@
+use axum::extract::Query;
+use axum::http::{header, StatusCode};
+use axum::response::{Extension, IntoResponse, Response};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use userman_auth::roles::DataValue;
+use tracing::instrument;
+use utoipa::IntoParams;
+
+use super::{Example, Output, Status};
+use crate::audit::{self, AuditEvent};
+use crate::exports::{Archive, ExportFormat};
+use crate::restore::{self, ConflictStrategy, RestoreCount, RestoreOptions, RestoreReport};
+use crate::tokens::SessionToken;
+use crate::{Shared, UsermanError};
+
+impl Example for RestoreReport {
+    fn example() -> Self {
+        Self {
+            dry_run: true,
+            apps: RestoreCount {
+                created: 1,
+                ..Default::default()
+            },
+            roles: RestoreCount {
+                created: 1,
+                renamed: 1,
+                ..Default::default()
+            },
+            users: RestoreCount {
+                created: 2,
+                skipped: 1,
+                ..Default::default()
+            },
+            configs: RestoreCount {
+                skipped: 1,
+                ..Default::default()
+            },
+            passwords: RestoreCount {
+                skipped: 2,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct ExportQuery {
+    format: Option<ExportFormat>,
+    /// Include password hashes and token secrets, needs `/secrets/read`.
+    secrets: Option<bool>,
+}
+
+#[utoipa::path(
+    get, 
+    path = "/api/v1/export",
+    params(ExportQuery),
+    responses(
+        (
+            status = StatusCode::OK, 
+            description = "Export archive successfully", 
+            content_type = "application/json"
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Export archive with error",
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_bad_request())
+        )
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[instrument(name = "exports::export", skip_all)]
+pub(crate) async fn export(
+    token: SessionToken,
+    Extension(shared): Extension<Shared>,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    let items = match shared.permissions(token).await {
+        Ok(t) => t,
+        Err(err) => return Output::<()>::Unauthorized(err).into_response(),
+    };
+
+    let secrets = query.secrets.unwrap_or(false);
+
+    let mut paths = vec![
+        "/apps/read.boolean",
+        "/roles/read.boolean",
+        "/users/read.boolean",
+    ];
+
+    if secrets {
+        paths.push("/secrets/read.boolean");
+    }
+
+    for path in paths {
+        match items.find_value(path) {
+            Ok(DataValue::Boolean(true)) => {}
+            Ok(_) => return Output::<()>::Unauthorized(UsermanError::Unauthorized).into_response(),
+            Err(err) => return Output::<()>::Unauthorized(err.into()).into_response(),
+        }
+    }
+
+    let format = query.format.unwrap_or_default();
+
+    let result = match Archive::read(&shared.dao, secrets).await {
+        Ok(t) => {
+            let file_name = t.file_name(format);
+            t.into_string(format).map(|t| (file_name, t))
+        }
+        Err(err) => Err(err),
+    };
+
+    let (output, response) = match result {
+        Ok((file_name, t)) => (
+            Output::<()>::Done,
+            Some(
+                (
+                    StatusCode::OK,
+                    [
+                        (header::CONTENT_TYPE, format.mime().to_string()),
+                        (
+                            header::CONTENT_DISPOSITION,
+                            format!("attachment; filename=\"{}\"", file_name),
+                        ),
+                    ],
+                    t,
+                )
+                    .into_response(),
+            ),
+        ),
+        Err(err) => (Output::Failure(err), None),
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("exports.export")
+            .changes(
+                None::<&Value>,
+                Some(&json!({ "format": format, "secrets": secrets })),
+            )
+            .output(&output),
+    )
+    .await;
+
+    response.unwrap_or_else(|| output.into_response())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RestoreQuery {
+    /// Only `json` and `ndjson` archives can be restored.
+    format: Option<ExportFormat>,
+    strategy: Option<ConflictStrategy>,
+    /// Only resolve the records.
+    dry_run: Option<bool>,
+}
+
+#[utoipa::path(
+    post, 
+    path = "/api/v1/restore",
+    params(RestoreQuery),
+    request_body(content = String, description = "Export archive"),
+    responses(
+        (
+            status = StatusCode::OK, 
+            description = "Restore archive successfully", 
+            body = StatusRestoreReport,
+            example = json!(Status::<RestoreReport>::example_ok())
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Restore archive with error",
+            body = StatusRestoreReport,
+            example = json!(Status::<RestoreReport>::example_bad_request())
+        )
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[instrument(name = "exports::restore", skip_all)]
+pub(crate) async fn restore(
+    token: SessionToken,
+    Extension(shared): Extension<Shared>,
+    Query(query): Query<RestoreQuery>,
+    body: String,
+) -> impl IntoResponse {
+    let items = permissions!(shared, token);
+
+    for path in [
+        "/apps/create.boolean",
+        "/apps/update.boolean",
+        "/roles/create.boolean",
+        "/roles/update.boolean",
+        "/users/create.boolean",
+        "/users/update.boolean",
+    ] {
+        let value = value!(items, path);
+
+        validate_bool!(value);
+    }
+
+    let archive = match Archive::parse(&body, query.format.unwrap_or_default()) {
+        Ok(t) => t,
+        Err(err) => return Output::Failure(err),
+    };
+
+    let options = RestoreOptions {
+        strategy: query.strategy.unwrap_or_default(),
+        dry_run: query.dry_run.unwrap_or(false),
+        secrets: restore::secrets_granted(&items),
+    };
+    let dry_run = options.dry_run;
+
+    let output = match restore::restore(&shared.dao, archive, options).await {
+        Ok(t) => Output::Success(t),
+        Err(err) => Output::Failure(err),
+    };
+
+    // A dry run changes nothing.
+    if !dry_run {
+        let report = match &output {
+            Output::Success(t) => Some(t),
+            _ => None,
+        };
+
+        audit::record(
+            &shared,
+            AuditEvent::new("exports.restore")
+                .changes(None::<&RestoreReport>, report)
+                .output(&output),
+        )
+        .await;
+    }
+
+    output
+}
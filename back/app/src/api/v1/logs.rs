@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use axum::extract::Json;
+use axum::response::{Extension, IntoResponse};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use super::{Example, Output, Status};
+use crate::audit::{self, AuditEvent};
+use crate::error::UsermanError;
+use crate::logger::{LogLevels, LogsLevel};
+use crate::tokens::SessionToken;
+use crate::Shared;
+
+impl Example for LogLevels {
+    fn example() -> Self {
+        Self {
+            level: LogsLevel::Debug,
+            modules: BTreeMap::from([("mongodb".to_string(), LogsLevel::Warn)]),
+            revert_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLevelReq {
+    level: LogsLevel,
+    /// Module to set the level of, the global level when missing.
+    module: Option<String>,
+    /// Go back to the levels of `config.yaml` after this many minutes.
+    revert_after_minutes: Option<u64>,
+}
+
+impl Example for LogLevelReq {
+    fn example() -> Self {
+        Self {
+            level: LogsLevel::Debug,
+            module: Some("userman::dao".to_string()),
+            revert_after_minutes: Some(30),
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/logs",
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "Read log levels successfully",
+            body = StatusLogLevels,
+            example = json!(Status::<LogLevels>::example_ok())
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Read log levels with error",
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_bad_request())
+        )
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[instrument(name = "logs::read", skip_all)]
+pub(crate) async fn read(
+    token: SessionToken,
+    Extension(shared): Extension<Shared>,
+) -> impl IntoResponse {
+    let items = permissions!(shared, token);
+    let update = value!(items, "/logs/update.boolean");
+
+    validate_bool!(update);
+
+    Output::Success(shared.logger.levels())
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/logs",
+    request_body(content = LogLevelReq, example = json!(LogLevelReq::example())),
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "Update log level successfully",
+            body = StatusLogLevels,
+            example = json!(Status::<LogLevels>::example_ok())
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Update log level with error",
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_bad_request())
+        )
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[instrument(name = "logs::update", skip_all)]
+pub(crate) async fn update(
+    token: SessionToken,
+    Extension(shared): Extension<Shared>,
+    Json(payload): Json<LogLevelReq>,
+) -> impl IntoResponse {
+    let items = permissions!(shared, token);
+    let update = value!(items, "/logs/update.boolean");
+
+    validate_bool!(update);
+
+    let revert_after = payload
+        .revert_after_minutes
+        .map(|t| Duration::from_secs(t.saturating_mul(60)));
+    let before = shared.logger.levels();
+    let event = AuditEvent::new("logs.update").target("logs", None, payload.module.as_deref());
+
+    let output = match shared
+        .logger
+        .set_level(payload.level, payload.module, revert_after)
+    {
+        Ok(t) => Output::Success(t),
+        Err(err) => Output::Failure(err),
+    };
+
+    let after = match &output {
+        Output::Success(t) => Some(t),
+        _ => None,
+    };
+
+    audit::record(
+        &shared,
+        event.changes(Some(&before), after).output(&output),
+    )
+    .await;
+
+    output
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/logs",
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "Reset log levels successfully",
+            body = StatusLogLevels,
+            example = json!(Status::<LogLevels>::example_ok())
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Reset log levels with error",
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_bad_request())
+        )
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[instrument(name = "logs::reset", skip_all)]
+pub(crate) async fn reset(
+    token: SessionToken,
+    Extension(shared): Extension<Shared>,
+) -> impl IntoResponse {
+    let items = permissions!(shared, token);
+    let update = value!(items, "/logs/update.boolean");
+
+    validate_bool!(update);
+
+    let before = shared.logger.levels();
+
+    let output = match shared.logger.reset() {
+        Ok(t) => Output::Success(t),
+        Err(err) => Output::Failure(err),
+    };
+
+    let after = match &output {
+        Output::Success(t) => Some(t),
+        _ => None,
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("logs.reset")
+            .target("logs", None, None)
+            .changes(Some(&before), after)
+            .output(&output),
+    )
+    .await;
+
+    output
+}
@@ -1,8 +1,14 @@
 pub mod apps;
+pub mod audit;
+pub mod exports;
+pub mod logs;
 pub mod roles;
 pub mod sessions;
+pub mod streams;
 pub mod users;
+pub mod webhooks;
 
+use axum::extract::DefaultBodyLimit;
 use axum::response::{IntoResponse, Response};
 use axum::routing::{get, post, put};
 use axum::Router;
@@ -10,10 +16,20 @@ use reqwest::StatusCode;
 use serde::Serialize;
 use utoipa::ToSchema;
 
-use userman_auth::apps::{App, AppsVec};
-use userman_auth::roles::{Role, RolesVec};
+use userman_auth::apps::App;
+use userman_auth::roles::Role;
 
-use crate::users::{User, UsersVec};
+use crate::access;
+use crate::avatars::AVATAR_MAX_BYTES;
+use crate::imports::ImportReport;
+use crate::logger::LogLevels;
+use crate::metrics;
+use crate::pages::{
+    AppsPage, AuditPage, DeliveriesPage, Page, RoleNamesPage, RolesPage, UsersPage, WebhooksPage,
+};
+use crate::restore::RestoreReport;
+use crate::users::User;
+use crate::webhooks::Webhook;
 use crate::{Result, UsermanError};
 
 use sessions::{LoginRes, RefreshRes};
@@ -31,13 +47,22 @@ pub struct StringsVec(pub Vec<String>);
     StatusRefreshRes = Status<RefreshRes>,
     StatusStrings = Status<StringsVec>,
     StatusApp = Status<App>,
-    StatusApps = Status<AppsVec>,
+    StatusApps = Status<AppsPage>,
     StatusRole = Status<Role>,
-    StatusRoles = Status<RolesVec>,
+    StatusRoles = Status<RolesPage>,
+    StatusRoleNames = Status<RoleNamesPage>,
     StatusUser = Status<User>,
-    StatusUsers = Status<UsersVec>,
+    StatusUsers = Status<UsersPage>,
+    StatusImportReport = Status<ImportReport>,
+    StatusRestoreReport = Status<RestoreReport>,
+    StatusLogLevels = Status<LogLevels>,
+    StatusAudit = Status<AuditPage>,
+    StatusWebhook = Status<Webhook>,
+    StatusWebhooks = Status<WebhooksPage>,
+    StatusDeliveries = Status<DeliveriesPage>,
 )]
 #[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 pub(crate) struct Status<T>
 where
     T: Serialize,
@@ -49,6 +74,8 @@ where
     error: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     code: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
 }
 
 impl<T: Serialize> Status<T> {
@@ -64,6 +91,7 @@ impl<T: Serialize> Status<T> {
             data: None,
             error: None,
             code: None,
+            request_id: None,
         }
     }
 
@@ -73,6 +101,7 @@ impl<T: Serialize> Status<T> {
             data: None,
             error: Some("ERROR_MESSAGE".to_string()),
             code: None,
+            request_id: Some("REQUEST_ID".to_string()),
         }
     }
 }
@@ -81,6 +110,18 @@ pub trait Example {
     fn example() -> Self;
 }
 
+impl<T: Serialize + Example> Example for Page<T> {
+    fn example() -> Self {
+        Self {
+            items: vec![T::example()],
+            total: 1,
+            page: Some(1),
+            limit: Some(20),
+            next_cursor: None,
+        }
+    }
+}
+
 impl<T: Serialize + Example> Status<T> {
     fn example_ok() -> Self {
         Self {
@@ -88,6 +129,7 @@ impl<T: Serialize + Example> Status<T> {
             data: Some(T::example()),
             error: None,
             code: None,
+            request_id: None,
         }
     }
 }
@@ -108,6 +150,7 @@ impl<T: Serialize> IntoResponse for Output<T> {
                     data: None,
                     error: None,
                     code: None,
+                    request_id: None,
                 };
 
                 (StatusCode::OK, resp.into_string().unwrap()).into_response()
@@ -118,6 +161,7 @@ impl<T: Serialize> IntoResponse for Output<T> {
                     data: Some(s),
                     error: None,
                     code: None,
+                    request_id: None,
                 };
 
                 match resp.into_string() {
@@ -128,6 +172,7 @@ impl<T: Serialize> IntoResponse for Output<T> {
                             data: None,
                             error: Some(err.to_string()),
                             code: None,
+                            request_id: access::request_id(),
                         };
 
                         (
@@ -139,36 +184,44 @@ impl<T: Serialize> IntoResponse for Output<T> {
                 }
             }
             Output::Failure(ref f) => {
+                metrics::error(f);
+
                 let resp_err: Status<()> = match f.code_number() {
                     Some(t) => Status {
                         status: ERROR,
                         data: None,
                         error: Some(f.to_string()),
                         code: Some(t),
+                        request_id: access::request_id(),
                     },
                     None => Status {
                         status: ERROR,
                         data: None,
                         error: Some(f.to_string()),
                         code: None,
+                        request_id: access::request_id(),
                     },
                 };
 
                 (StatusCode::BAD_REQUEST, resp_err.into_string().unwrap()).into_response()
             }
             Output::Unauthorized(f) => {
+                metrics::error(&f);
+
                 let resp_err: Status<()> = match f.code_number() {
                     Some(t) => Status {
                         status: ERROR,
                         data: None,
                         error: Some(f.to_string()),
                         code: Some(t),
+                        request_id: access::request_id(),
                     },
                     None => Status {
                         status: ERROR,
                         data: None,
                         error: Some(f.to_string()),
                         code: None,
+                        request_id: access::request_id(),
                     },
                 };
 
@@ -184,6 +237,32 @@ pub fn routes() -> Router {
         .route("/refresh", post(sessions::refresh))
         .route("/logout", post(sessions::logout))
         .route("/reset", post(sessions::reset))
+        .route("/password", post(sessions::change_password))
+        // exports
+        .route("/export", get(exports::export))
+        .route("/restore", post(exports::restore))
+        // audit
+        .route("/audit", get(audit::read_all))
+        // logs
+        .route(
+            "/logs",
+            get(logs::read).put(logs::update).delete(logs::reset),
+        )
+        // streams
+        .route("/streams/permissions", get(streams::permissions))
+        // webhooks
+        .route("/webhooks", post(webhooks::create).get(webhooks::read_all))
+        .route(
+            "/webhooks/:id",
+            put(webhooks::update)
+                .delete(webhooks::delete)
+                .get(webhooks::read),
+        )
+        .route("/webhooks/:id/deliveries", get(webhooks::deliveries))
+        .route(
+            "/webhooks/:id/deliveries/:delivery/redeliver",
+            post(webhooks::redeliver),
+        )
         // apps
         .route("/apps", post(apps::create).get(apps::read_all))
         .route(
@@ -201,10 +280,17 @@ pub fn routes() -> Router {
         )
         // users
         .route("/users", post(users::create).get(users::read_all))
+        .route("/users/import", post(users::import))
         .route("/usernames/:username", get(users::username))
         .route(
             "/users/:id",
             put(users::update).delete(users::delete).get(users::read),
         )
         .route("/users/:id/reset", get(users::reset))
+        .route(
+            "/users/:id/avatar",
+            put(users::update_avatar)
+                .get(users::read_avatar)
+                .layer(DefaultBodyLimit::max(AVATAR_MAX_BYTES)),
+        )
 }
@@ -1,17 +1,25 @@
 use std::str::FromStr;
 
-use axum::extract::{Json, Path};
+use axum::extract::{Json, Path, Query};
 use axum::response::{Extension, IntoResponse};
 use mongodb::bson::oid::ObjectId;
+use serde::Deserialize;
+use tracing::instrument;
+use utoipa::IntoParams;
 
 use userman_auth::roles::{Role, RolesVec};
 
 use super::{Output, Example, Status, StringsVec};
+use crate::audit::{self, AuditEvent};
 use crate::dao::Memory;
 use crate::error::UsermanError;
+use crate::pages::{Page, PageQuery};
 use crate::roles::RoleName;
 use crate::tokens::SessionToken;
-use crate::Shared;
+use crate::{Result, Shared};
+
+/// Fields the listing can be sorted by, the first one by default.
+pub(crate) const SORTS: &[&str] = &["name", "app", "createdAt", "updatedAt"];
 
 impl Example for Role {
     fn example() -> Self {
@@ -25,6 +33,15 @@ impl Example for RolesVec {
     }
 }
 
+impl Example for RoleName {
+    fn example() -> Self {
+        Self {
+            id: None,
+            name: "NAME".to_string(),
+        }
+    }
+}
+
 impl Example for StringsVec {
     fn example() -> Self {
         Self(vec!["NAME1".to_string(), "NAME2".to_string()])
@@ -53,6 +70,7 @@ impl Example for StringsVec {
         ("token" = [])
     )
 )]
+#[instrument(name = "roles::create", skip_all)]
 pub(crate) async fn create(
     token: SessionToken,
     Extension(shared): Extension<Shared>,
@@ -63,10 +81,21 @@ pub(crate) async fn create(
 
     validate_bool!(create);
 
-    match shared.dao.create_role(&payload).await {
+    let output = match shared.dao.create_role(&payload).await {
         Ok(_) => Output::<()>::Done,
         Err(err) => Output::Failure(err),
-    }
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("roles.create")
+            .target("role", None, Some(&payload.name))
+            .changes(None::<&Role>, Some(&payload))
+            .output(&output),
+    )
+    .await;
+
+    output
 }
 
 #[utoipa::path(
@@ -90,6 +119,7 @@ pub(crate) async fn create(
         ("token" = [])
     )
 )]
+#[instrument(name = "roles::read", skip_all)]
 pub(crate) async fn read(
     id: Path<String>,
     token: SessionToken,
@@ -111,79 +141,122 @@ pub(crate) async fn read(
     }
 }
 
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct RolesFilter {
+    /// App id.
+    app: Option<String>,
+    /// Text searched in the role name.
+    name: Option<String>,
+}
+
+impl RolesFilter {
+    pub(crate) fn apply(&self, roles: Vec<Role>) -> Result<Vec<Role>> {
+        let app = match self.app {
+            Some(ref t) => Some(ObjectId::from_str(t).map_err(UsermanError::ParseObjectId)?),
+            None => None,
+        };
+
+        let name = self.name.as_ref().map(|t| t.to_lowercase());
+
+        Ok(roles
+            .into_iter()
+            .filter(|role| !matches!(app, Some(t) if role.app != t))
+            .filter(|role| !matches!(name, Some(ref t) if !role.name.to_lowercase().contains(t)))
+            .collect())
+    }
+}
+
 #[utoipa::path(
     get, 
     path = "/api/v1/roles",
+    params(PageQuery, RolesFilter),
     responses(
         (
             status = StatusCode::OK, 
             description = "Read roles successfully", 
             body = StatusRoles,
-            example = json!(Status::<RolesVec>::example_ok())
+            example = json!(Status::<Page<Role>>::example_ok())
         ),
         (
             status = StatusCode::BAD_REQUEST,
             description = "Read roles with error",
             body = StatusRoles,
-            example = json!(Status::<RolesVec>::example_bad_request())
+            example = json!(Status::<Page<Role>>::example_bad_request())
         )
     ),
     security(
         ("token" = [])
     )
 )]
+#[instrument(name = "roles::read_all", skip_all)]
 pub(crate) async fn read_all(
     token: SessionToken,
     Extension(shared): Extension<Shared>,
+    Query(page): Query<PageQuery>,
+    Query(filter): Query<RolesFilter>,
 ) -> impl IntoResponse {
     let items = permissions!(shared, token);
     let read = value!(items, "/roles/read.boolean");
 
     validate_bool!(read);
 
-    let values = shared.roles.get_all().await;
-    Output::Success(values)
+    let values = match filter.apply(shared.roles.get_all().await) {
+        Ok(t) => t,
+        Err(err) => return Output::Failure(err),
+    };
+
+    match page.paginate(values, SORTS, |t| t.id().to_hex()) {
+        Ok(t) => Output::Success(t),
+        Err(err) => Output::Failure(err),
+    }
 }
 
 #[utoipa::path(
     get, 
     path = "/api/v1/rolenames",
+    params(PageQuery, RolesFilter),
     responses(
         (
             status = StatusCode::OK, 
             description = "Read role rolenames successfully", 
-            body = StatusStrings,
-            example = json!(Status::<StringsVec>::example_ok())
+            body = StatusRoleNames,
+            example = json!(Status::<Page<RoleName>>::example_ok())
         ),
         (
             status = StatusCode::BAD_REQUEST,
             description = "Read role rolenames with error",
-            body = StatusStrings,
-            example = json!(Status::<StringsVec>::example_bad_request())
+            body = StatusRoleNames,
+            example = json!(Status::<Page<RoleName>>::example_bad_request())
         )
     ),
     security(
         ("token" = [])
     )
 )]
+#[instrument(name = "roles::read_all_names", skip_all)]
 pub(crate) async fn read_all_names(
     token: SessionToken,
     Extension(shared): Extension<Shared>,
+    Query(page): Query<PageQuery>,
+    Query(filter): Query<RolesFilter>,
 ) -> impl IntoResponse {
     let items = permissions!(shared, token);
     let read = value!(items, "/roles/read.boolean");
 
     validate_bool!(read);
 
-    let values: Vec<RoleName> = shared
-        .roles
-        .get_all()
-        .await
-        .into_iter()
-        .map(|ref t| RoleName::from(t))
-        .collect();
+    let values: Vec<RoleName> = match filter.apply(shared.roles.get_all().await) {
+        Ok(t) => t.iter().map(RoleName::from).collect(),
+        Err(err) => return Output::Failure(err),
+    };
 
-    Output::Success(values)
+    match page.paginate(values, &["name"], |t| {
+        t.id.map(|t| t.to_hex()).unwrap_or_default()
+    }) {
+        Ok(t) => Output::Success(t),
+        Err(err) => Output::Failure(err),
+    }
 }
 
 #[utoipa::path(
@@ -208,6 +281,7 @@ pub(crate) async fn read_all_names(
         ("token" = [])
     )
 )]
+#[instrument(name = "roles::update", skip_all)]
 pub(crate) async fn update(
     id: Path<String>,
     token: SessionToken,
@@ -219,10 +293,23 @@ pub(crate) async fn update(
 
     validate_bool!(update);
 
-    match shared.dao.update_role_by_id(id.as_str(), &payload).await {
+    let before = audit::cached(&shared.roles, id.as_str()).await;
+
+    let output = match shared.dao.update_role_by_id(id.as_str(), &payload).await {
         Ok(_) => Output::<()>::Done,
         Err(err) => Output::Failure(err),
-    }
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("roles.update")
+            .target("role", Some(id.as_str()), Some(&payload.name))
+            .changes(before.as_ref(), Some(&payload))
+            .output(&output),
+    )
+    .await;
+
+    output
 }
 
 #[utoipa::path(
@@ -246,6 +333,7 @@ pub(crate) async fn update(
         ("token" = [])
     )
 )]
+#[instrument(name = "roles::delete", skip_all)]
 pub(crate) async fn delete(
     id: Path<String>,
     token: SessionToken,
@@ -256,10 +344,23 @@ pub(crate) async fn delete(
 
     validate_bool!(delete);
 
-    match shared.dao.delete_role_by_id(id.as_str()).await {
+    let before = audit::cached(&shared.roles, id.as_str()).await;
+
+    let output = match shared.dao.delete_role_by_id(id.as_str()).await {
         Ok(_) => Output::<()>::Done,
         Err(err) => Output::Failure(err),
-    }
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("roles.delete")
+            .target("role", Some(id.as_str()), before.as_ref().map(|t| t.name.as_str()))
+            .changes(before.as_ref(), None::<&Role>)
+            .output(&output),
+    )
+    .await;
+
+    output
 }
 
 #[utoipa::path(
@@ -283,6 +384,7 @@ pub(crate) async fn delete(
         ("token" = [])
     )
 )]
+#[instrument(name = "roles::name", skip_all)]
 pub(crate) async fn name(
     name: Path<String>,
     token: SessionToken,
@@ -320,6 +422,7 @@ pub(crate) async fn name(
         ("token" = [])
     )
 )]
+#[instrument(name = "roles::sync", skip_all)]
 pub(crate) async fn sync(
     id: Path<String>,
     token: SessionToken,
@@ -340,6 +443,8 @@ pub(crate) async fn sync(
         None => return Output::Failure(UsermanError::RoleNotFound),
     };
 
+    let before = a_role.clone();
+
     let mut n_role_items = match shared.apps.get_by_id(&a_role.app).await {
         Some(t) => t.default_role,
         None => return Output::Failure(UsermanError::AppNotFound),
@@ -348,8 +453,19 @@ pub(crate) async fn sync(
     a_role.items.merge(&mut n_role_items);
     a_role.items = n_role_items;
 
-    match shared.dao.update_role_by_id(id.as_str(), &a_role).await {
+    let output = match shared.dao.update_role_by_id(id.as_str(), &a_role).await {
         Ok(_) => Output::<()>::Done,
         Err(err) => Output::Failure(err),
-    }
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("roles.sync")
+            .target("role", Some(id.as_str()), Some(&a_role.name))
+            .changes(Some(&before), Some(&a_role))
+            .output(&output),
+    )
+    .await;
+
+    output
 }
@@ -1,11 +1,18 @@
 use axum::{extract::Json, response::IntoResponse, Extension};
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use userman_auth::roles::RoleItems;
+use tracing::{info_span, instrument, Instrument};
 use utoipa::ToSchema;
 
 use super::{Output, Example, Status};
+use crate::access;
+use crate::audit::{self, AuditEvent};
 use crate::dao::Memory;
+use crate::metrics;
 use crate::tokens::{Claims, RefreshToken};
+use crate::webhooks::{self, WebhookEvent};
 use crate::{Shared, UsermanError};
 
 #[derive(Deserialize, ToSchema)]
@@ -54,14 +61,42 @@ impl Example for LoginRes {
         )
     )
 )]
+#[instrument(name = "sessions::login", skip_all)]
 pub(crate) async fn login(
     Extension(shared): Extension<Shared>,
     Json(payload): Json<LoginReq>,
 ) -> impl IntoResponse {
+    let username = payload.username.clone();
+    let output = login_output(&shared, payload).await;
+    metrics::login(&output);
+
+    match &output {
+        Output::Failure(err) => warn!(user = username.as_str(); "Login failed. {}", err),
+        _ => {
+            access::set_user(&username);
+            info!(user = username.as_str(); "Login succeeded.");
+        }
+    }
+
+    audit::record(
+        &shared,
+        AuditEvent::new("sessions.login").actor(&username).output(&output),
+    )
+    .await;
+
+    if let Output::Success(_) = &output {
+        let data = json!({ "username": username, "ip": access::ip() });
+        webhooks::emit(&shared, WebhookEvent::Login, data).await;
+    }
+
+    output
+}
+
+async fn login_output(shared: &Shared, payload: LoginReq) -> Output<LoginRes> {
     match shared.users.get(&payload.username).await {
         Some(t) => {
-            match t.verify(payload.password) {
-                Some(true) if t.enabled => {
+            match t.check_login(payload.password) {
+                Ok(()) => {
                     /* access token */
 
                     let duration = shared.keys.duration().await;
@@ -93,7 +128,11 @@ pub(crate) async fn login(
 
                     /* permissions */
 
-                    let permissions = shared.auth.permissions(roles_names).await;
+                    let permissions = shared
+                        .auth
+                        .permissions(roles_names)
+                        .instrument(info_span!("merge_permissions"))
+                        .await;
 
                     Output::Success(LoginRes {
                         access_token,
@@ -101,9 +140,7 @@ pub(crate) async fn login(
                         permissions,
                     })
                 },
-                Some(true) => Output::Failure(UsermanError::DisabledUser),
-                Some(false) => Output::Failure(UsermanError::InvalidCredentials),
-                None => Output::Failure(UsermanError::UninitializedPassword),
+                Err(err) => Output::Failure(err),
             }
         }
         _ => Output::Failure(UsermanError::InvalidCredentials),
@@ -150,10 +187,24 @@ impl Example for RefreshRes {
         )
     )
 )]
+#[instrument(name = "sessions::refresh", skip_all)]
 pub(crate) async fn refresh(
     Extension(shared): Extension<Shared>,
     Json(payload): Json<RefreshReq>,
 ) -> impl IntoResponse {
+    let username = payload.username.clone();
+    let output = refresh_output(&shared, payload).await;
+    metrics::session("refresh", &output);
+
+    audit::record(
+        &shared,
+        AuditEvent::new("sessions.refresh").actor(&username).output(&output),
+    )
+    .await;
+    output
+}
+
+async fn refresh_output(shared: &Shared, payload: RefreshReq) -> Output<RefreshRes> {
     match shared
         .dao
         .read_refresh_token(&payload.refresh_token, &payload.username)
@@ -161,7 +212,12 @@ pub(crate) async fn refresh(
     {
         Ok(None) => Output::Failure(UsermanError::InvalidToken),
         Ok(_) => match shared.users.get(&payload.username).await {
-            Some(t) if t.enabled => {
+            Some(t) => {
+                // Same as a login, a pending password change blocks the session.
+                if let Err(err) = t.check_refresh() {
+                    return Output::Failure(err);
+                }
+
                 let duration = shared.keys.duration().await;
                 let mut roles_names = vec![];
 
@@ -182,8 +238,7 @@ pub(crate) async fn refresh(
 
                 Output::Success(RefreshRes { access_token })
             }
-            Some(_) => Output::Failure(UsermanError::DisabledUser),
-            _ => Output::Failure(UsermanError::InvalidUsername),
+            None => Output::Failure(UsermanError::InvalidUsername),
         },
         Err(err) => Output::Failure(err),
     }
@@ -215,16 +270,30 @@ pub(crate) struct LogoutReq {
         )
     )
 )]
+#[instrument(name = "sessions::logout", skip_all)]
 pub(crate) async fn logout(
     Extension(shared): Extension<Shared>,
     Json(payload): Json<LogoutReq>,
 ) -> impl IntoResponse {
+    let username = payload.username.clone();
+    let output = logout_output(&shared, payload).await;
+    metrics::session("logout", &output);
+
+    audit::record(
+        &shared,
+        AuditEvent::new("sessions.logout").actor(&username).output(&output),
+    )
+    .await;
+    output
+}
+
+async fn logout_output(shared: &Shared, payload: LogoutReq) -> Output<()> {
     match shared
         .dao
         .delete_refresh_token(&payload.refresh_token, &payload.username)
         .await
     {
-        Ok(Some(_)) => Output::<()>::Done,
+        Ok(Some(_)) => Output::Done,
         Ok(None) => Output::Unauthorized(UsermanError::InvalidCredentials),
         Err(err) => Output::Failure(err),
     }
@@ -256,16 +325,108 @@ pub(crate) struct ResetReq {
         )
     )
 )]
+#[instrument(name = "sessions::reset", skip_all)]
 pub(crate) async fn reset(
     Extension(shared): Extension<Shared>,
     Json(payload): Json<ResetReq>,
 ) -> impl IntoResponse {
-    match shared
+    let user = audit::cached(&shared.users, &payload.id).await;
+
+    let (modified, output) = match shared
         .dao
         .update_user_password_by_id(&payload.id, &payload.password)
         .await
     {
+        // Missing user or password already set.
+        Ok(0) => (0, Output::<()>::Failure(UsermanError::PasswordNotReset)),
+        Ok(t) => (t, Output::Done),
+        Err(err) => (0, Output::Failure(err)),
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("sessions.reset")
+            .target("user", Some(&payload.id), user.as_ref().map(|t| t.username.as_str()))
+            .output(&output),
+    )
+    .await;
+
+    // Only once a password was actually set, the endpoint is public.
+    if modified > 0 {
+        let data = json!({ "id": payload.id, "username": user.map(|t| t.username) });
+        webhooks::emit(&shared, WebhookEvent::PasswordReset, data).await;
+    }
+
+    output
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ChangePasswordReq {
+    username: String,
+    password: String,
+    new_password: String,
+}
+
+#[utoipa::path(
+    post, 
+    path = "/api/v1/password",
+    request_body = ChangePasswordReq,
+    responses(
+        (
+            status = StatusCode::OK, 
+            description = "Change password successfully", 
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_empty_ok())
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Change password with error",
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_bad_request())
+        )
+    )
+)]
+#[instrument(name = "sessions::change_password", skip_all)]
+pub(crate) async fn change_password(
+    Extension(shared): Extension<Shared>,
+    Json(payload): Json<ChangePasswordReq>,
+) -> impl IntoResponse {
+    let output = change_password_output(&shared, &payload).await;
+
+    audit::record(
+        &shared,
+        AuditEvent::new("sessions.change_password")
+            .actor(&payload.username)
+            .target("user", None, Some(&payload.username))
+            .output(&output),
+    )
+    .await;
+
+    output
+}
+
+async fn change_password_output(shared: &Shared, payload: &ChangePasswordReq) -> Output<()> {
+    let user = match shared.users.get(&payload.username).await {
+        Some(t) => t,
+        None => return Output::Failure(UsermanError::InvalidCredentials),
+    };
+
+    if let Err(err) = user.check_password_change(&payload.password, &payload.new_password) {
+        return Output::Failure(err);
+    }
+
+    if let Err(err) = shared
+        .dao
+        .set_user_password_by_id(&user.id(), &payload.new_password, false)
+        .await
+    {
+        return Output::Failure(err);
+    }
+
+    // Do not wait for the watcher, the next login uses the new password.
+    match shared.users.reload(&shared.dao).await {
         Ok(()) => Output::<()>::Done,
         Err(err) => Output::Failure(err),
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,118 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use axum::extract::Query;
+use axum::response::sse::{KeepAlive, Sse};
+use axum::response::{Extension, IntoResponse, Response};
+use mongodb::bson::oid::ObjectId;
+use serde::Deserialize;
+use tracing::instrument;
+use utoipa::IntoParams;
+
+use userman_auth::roles::DataValue;
+
+use super::{Output, Status};
+use crate::access;
+use crate::error::UsermanError;
+use crate::streams::Subscription;
+use crate::tokens::SessionToken;
+use crate::Shared;
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct StreamQuery {
+    /// Comma separated ids of the users to follow besides the authenticated
+    /// one.
+    users: Option<String>,
+    /// Comma separated ids of the roles to follow.
+    roles: Option<String>,
+    /// Comma separated ids of the apps to follow, with their roles.
+    apps: Option<String>,
+}
+
+fn ids(raw: &Option<String>) -> Result<HashSet<ObjectId>, UsermanError> {
+    raw.iter()
+        .flat_map(|t| t.split(','))
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+        .map(|t| ObjectId::from_str(t).map_err(UsermanError::ParseObjectId))
+        .collect()
+}
+
+/// Subscription of the client, or the output refusing it.
+async fn subscription(
+    shared: &Shared,
+    token: SessionToken,
+    query: &StreamQuery,
+) -> Result<Subscription, Output<()>> {
+    let expires_at = token
+        .expires_at(&shared.keys)
+        .await
+        .map_err(Output::Unauthorized)?;
+
+    let items = shared
+        .permissions(token)
+        .await
+        .map_err(Output::Unauthorized)?;
+
+    let subscription = Subscription {
+        username: access::user(),
+        users: ids(&query.users).map_err(Output::Failure)?,
+        roles: ids(&query.roles).map_err(Output::Failure)?,
+        apps: ids(&query.apps).map_err(Output::Failure)?,
+        expires_at,
+    };
+
+    // Following the others needs a permission.
+    if subscription.users.is_empty()
+        && subscription.roles.is_empty()
+        && subscription.apps.is_empty()
+    {
+        return Ok(subscription);
+    }
+
+    match items.find_value("/streams/read.boolean") {
+        Ok(DataValue::Boolean(true)) => Ok(subscription),
+        Ok(_) => Err(Output::Unauthorized(UsermanError::Unauthorized)),
+        Err(err) => Err(Output::Unauthorized(err.into())),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/streams/permissions",
+    params(StreamQuery),
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "Server-sent events: `permissions` with a PermissionEvent \
+                on every change of a followed user, role or app, `lagged` when some \
+                were missed and every cached permission should be dropped. Ends \
+                when the token expires or the user is disabled",
+            content_type = "text/event-stream",
+            body = PermissionEvent
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Stream permission changes with error",
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_bad_request())
+        )
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[instrument(name = "streams::permissions", skip_all)]
+pub(crate) async fn permissions(
+    token: SessionToken,
+    Extension(shared): Extension<Shared>,
+    Query(query): Query<StreamQuery>,
+) -> Response {
+    match subscription(&shared, token, &query).await {
+        Ok(t) => Sse::new(shared.streams.subscribe(shared.clone(), t))
+            .keep_alive(KeepAlive::default())
+            .into_response(),
+        Err(output) => output.into_response(),
+    }
+}
@@ -1,24 +1,56 @@
 use std::str::FromStr;
 
-use axum::extract::{Json, Path};
-use axum::response::{Extension, IntoResponse};
+use axum::extract::{Json, Multipart, Path, Query};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{Extension, IntoResponse, Response};
 use mongodb::bson::oid::ObjectId;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::instrument;
+use utoipa::IntoParams;
 
 use super::{Output, Example, Status};
+use crate::audit::{self, AuditEvent};
+use crate::avatars::{self, AVATAR_MIME};
 use crate::dao::Memory;
+use crate::imports::{self, ImportFormat, ImportOptions, ImportReport, RowError};
+use crate::pages::{Page, PageQuery};
 use crate::tokens::SessionToken;
-use crate::users::{User, UsersVec};
+use crate::users::User;
+use crate::webhooks::{self, WebhookEvent};
 use crate::{Shared, UsermanError};
 
+/// Fields the listing can be sorted by, the first one by default.
+const SORTS: &[&str] = &[
+    "username",
+    "email",
+    "name",
+    "surname",
+    "department",
+    "description",
+    "enabled",
+    "createdAt",
+    "updatedAt",
+];
+
 impl Example for User {
     fn example() -> Self {
         Self::default()
     }
 }
 
-impl Example for UsersVec {
+impl Example for ImportReport {
     fn example() -> Self {
-        Self(vec![User::default()])
+        Self {
+            dry_run: true,
+            created: 1,
+            updated: 0,
+            errors: vec![RowError {
+                row: 2,
+                username: "USERNAME".to_string(),
+                error: "Username already exists.".to_string(),
+            }],
+        }
     }
 }
 
@@ -44,6 +76,7 @@ impl Example for UsersVec {
         ("token" = [])
     )
 )]
+#[instrument(name = "users::create", skip_all)]
 pub(crate) async fn create(
     token: SessionToken,
     Extension(shared): Extension<Shared>,
@@ -54,10 +87,23 @@ pub(crate) async fn create(
 
     validate_bool!(create);
 
-    match shared.dao.create_user(&payload.none_password()).await {
+    let payload = payload.none_password();
+
+    let output = match shared.dao.create_user(&payload).await {
         Ok(_) => Output::<()>::Done,
         Err(err) => Output::Failure(err),
-    }
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("users.create")
+            .target("user", None, Some(&payload.username))
+            .changes(None::<&User>, Some(&payload))
+            .output(&output),
+    )
+    .await;
+
+    output
 }
 
 #[utoipa::path(
@@ -81,6 +127,7 @@ pub(crate) async fn create(
         ("token" = [])
     )
 )]
+#[instrument(name = "users::read", skip_all)]
 pub(crate) async fn read(
     id: Path<String>,
     token: SessionToken,
@@ -102,41 +149,99 @@ pub(crate) async fn read(
     }
 }
 
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct UsersFilter {
+    enabled: Option<bool>,
+    department: Option<String>,
+    /// Role id.
+    role: Option<String>,
+    /// Text searched in username, name, surname and email.
+    search: Option<String>,
+}
+
+impl UsersFilter {
+    fn matches(&self, user: &User, role: Option<&ObjectId>) -> bool {
+        if matches!(self.enabled, Some(t) if t != user.enabled) {
+            return false;
+        }
+
+        if matches!(self.department, Some(ref t) if t != &user.department) {
+            return false;
+        }
+
+        if matches!(role, Some(t) if !user.roles.contains(t)) {
+            return false;
+        }
+
+        match self.search {
+            Some(ref t) => {
+                let search = t.to_lowercase();
+
+                [&user.username, &user.name, &user.surname, &user.email]
+                    .iter()
+                    .any(|t| t.to_lowercase().contains(&search))
+            }
+            None => true,
+        }
+    }
+}
+
 #[utoipa::path(
     get, 
     path = "/api/v1/users",
+    params(PageQuery, UsersFilter),
     responses(
         (
             status = StatusCode::OK, 
             description = "Read users successfully", 
             body = StatusUsers,
-            example = json!(Status::<UsersVec>::example_ok())
+            example = json!(Status::<Page<User>>::example_ok())
         ),
         (
             status = StatusCode::BAD_REQUEST,
             description = "Read users with error",
             body = StatusUsers,
-            example = json!(Status::<UsersVec>::example_bad_request())
+            example = json!(Status::<Page<User>>::example_bad_request())
         )
     ),
     security(
         ("token" = [])
     )
 )]
+#[instrument(name = "users::read_all", skip_all)]
 pub(crate) async fn read_all(
     token: SessionToken,
     Extension(shared): Extension<Shared>,
+    Query(page): Query<PageQuery>,
+    Query(filter): Query<UsersFilter>,
 ) -> impl IntoResponse {
     let items = permissions!(shared, token);
     let read = value!(items, "/users/read.boolean");
 
     validate_bool!(read);
 
-    let values: Vec<User> = shared.users.get_all().await.iter_mut().map(|user| {
-        user.to_owned().hide_password()
-    }).collect();
+    let role = match filter.role {
+        Some(ref t) => match ObjectId::from_str(t).map_err(UsermanError::ParseObjectId) {
+            Ok(t) => Some(t),
+            Err(err) => return Output::Failure(err),
+        },
+        None => None,
+    };
 
-    Output::Success(values)
+    let values: Vec<User> = shared
+        .users
+        .get_all()
+        .await
+        .into_iter()
+        .filter(|user| filter.matches(user, role.as_ref()))
+        .map(|user| user.hide_password())
+        .collect();
+
+    match page.paginate(values, SORTS, |t| t.id().to_hex()) {
+        Ok(t) => Output::Success(t),
+        Err(err) => Output::Failure(err),
+    }
 }
 
 #[utoipa::path(
@@ -161,6 +266,7 @@ pub(crate) async fn read_all(
         ("token" = [])
     )
 )]
+#[instrument(name = "users::update", skip_all)]
 pub(crate) async fn update(
     id: Path<String>,
     token: SessionToken,
@@ -172,14 +278,24 @@ pub(crate) async fn update(
 
     validate_bool!(update);
 
-    match shared
-        .dao
-        .update_user_by_id(id.as_str(), &payload.none_password())
-        .await
-    {
+    let before = audit::cached(&shared.users, id.as_str()).await;
+    let payload = payload.none_password();
+
+    let output = match shared.dao.update_user_by_id(id.as_str(), &payload).await {
         Ok(_) => Output::<()>::Done,
         Err(err) => Output::Failure(err),
-    }
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("users.update")
+            .target("user", Some(id.as_str()), Some(&payload.username))
+            .changes(before.as_ref(), Some(&payload))
+            .output(&output),
+    )
+    .await;
+
+    output
 }
 
 #[utoipa::path(
@@ -203,6 +319,7 @@ pub(crate) async fn update(
         ("token" = [])
     )
 )]
+#[instrument(name = "users::reset", skip_all)]
 pub(crate) async fn reset(
     id: Path<String>,
     token: SessionToken,
@@ -213,10 +330,27 @@ pub(crate) async fn reset(
 
     validate_bool!(update);
 
-    match shared.dao.reset_user_password_by_id(id.as_str()).await {
+    let user = audit::cached(&shared.users, id.as_str()).await;
+
+    let output = match shared.dao.reset_user_password_by_id(id.as_str()).await {
         Ok(_) => Output::<()>::Done,
         Err(err) => Output::Failure(err),
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("users.reset")
+            .target("user", Some(id.as_str()), user.as_ref().map(|t| t.username.as_str()))
+            .output(&output),
+    )
+    .await;
+
+    if let Output::Done = &output {
+        let data = json!({ "id": id.as_str(), "username": user.map(|t| t.username) });
+        webhooks::emit(&shared, WebhookEvent::PasswordReset, data).await;
     }
+
+    output
 }
 
 #[utoipa::path(
@@ -240,6 +374,7 @@ pub(crate) async fn reset(
         ("token" = [])
     )
 )]
+#[instrument(name = "users::delete", skip_all)]
 pub(crate) async fn delete(
     id: Path<String>,
     token: SessionToken,
@@ -250,10 +385,23 @@ pub(crate) async fn delete(
 
     validate_bool!(delete);
 
-    match shared.dao.delete_user_by_id(id.as_str()).await {
+    let before = audit::cached(&shared.users, id.as_str()).await;
+
+    let output = match shared.dao.delete_user_by_id(id.as_str()).await {
         Ok(_) => Output::<()>::Done,
         Err(err) => Output::Failure(err),
-    }
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("users.delete")
+            .target("user", Some(id.as_str()), before.as_ref().map(|t| t.username.as_str()))
+            .changes(before.as_ref(), None::<&User>)
+            .output(&output),
+    )
+    .await;
+
+    output
 }
 
 #[utoipa::path(
@@ -277,6 +425,7 @@ pub(crate) async fn delete(
         ("token" = [])
     )
 )]
+#[instrument(name = "users::username", skip_all)]
 pub(crate) async fn username(
     username: Path<String>,
     token: SessionToken,
@@ -292,3 +441,270 @@ pub(crate) async fn username(
         None => Output::Done,
     }
 }
+
+#[utoipa::path(
+    put, 
+    path = "/api/v1/users/<id>/avatar",
+    request_body(content = String, content_type = "multipart/form-data"),
+    responses(
+        (
+            status = StatusCode::OK, 
+            description = "Update user avatar successfully", 
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_empty_ok())
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Update user avatar with error",
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_bad_request())
+        )
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[instrument(name = "users::update_avatar", skip_all)]
+pub(crate) async fn update_avatar(
+    id: Path<String>,
+    token: SessionToken,
+    Extension(shared): Extension<Shared>,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    let items = permissions!(shared, token);
+    let update = value!(items, "/users/update.boolean");
+
+    validate_bool!(update);
+
+    let object_id = match ObjectId::from_str(id.as_str()).map_err(UsermanError::ParseObjectId) {
+        Ok(t) => t,
+        Err(err) => return Output::Failure(err),
+    };
+
+    let user = match shared.users.get_by_id(&object_id).await {
+        Some(t) => t,
+        None => return Output::Failure(UsermanError::UserNotFound),
+    };
+
+    let data = match multipart.next_field().await {
+        Ok(Some(field)) => match field.bytes().await {
+            Ok(t) => t,
+            Err(err) => return Output::Failure(UsermanError::Multipart(err.to_string())),
+        },
+        Ok(None) => return Output::Failure(UsermanError::MissingAvatar),
+        Err(err) => return Output::Failure(UsermanError::Multipart(err.to_string())),
+    };
+
+    // Decoding and resizing is CPU bound, keep it away from the async workers.
+    let resized = tokio::task::spawn_blocking(move || avatars::resize(&data))
+        .await
+        .map_err(|err| UsermanError::Image(err.to_string()))
+        .and_then(|t| t);
+
+    let values = match resized {
+        Ok(t) => t,
+        Err(err) => return Output::Failure(err),
+    };
+
+    let version = ObjectId::new().to_hex();
+
+    if let Err(err) = shared.dao.create_avatars(&object_id, &version, &values).await {
+        return Output::Failure(err);
+    }
+
+    if let Err(err) = shared.dao.update_user_avatar_by_id(&object_id, &version).await {
+        return Output::Failure(err);
+    }
+
+    let output = match shared.dao.delete_avatars(&object_id, Some(&version)).await {
+        Ok(_) => Output::<()>::Done,
+        Err(err) => Output::Failure(err),
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("users.update_avatar")
+            .target("user", Some(id.as_str()), Some(&user.username))
+            .output(&output),
+    )
+    .await;
+
+    output
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct AvatarQuery {
+    /// Requested size in pixels, rounded up to the nearest stored size.
+    size: Option<u32>,
+}
+
+#[utoipa::path(
+    get, 
+    path = "/api/v1/users/<id>/avatar",
+    params(AvatarQuery),
+    responses(
+        (
+            status = StatusCode::OK, 
+            description = "Read user avatar, or a generated one, successfully", 
+            content_type = "image/png"
+        ),
+        (
+            status = StatusCode::NOT_MODIFIED, 
+            description = "User avatar did not change"
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Read user avatar with error",
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_bad_request())
+        )
+    )
+)]
+#[instrument(name = "users::read_avatar", skip_all)]
+pub(crate) async fn read_avatar(
+    id: Path<String>,
+    Query(query): Query<AvatarQuery>,
+    headers: HeaderMap,
+    Extension(shared): Extension<Shared>,
+) -> Response {
+    let object_id = match ObjectId::from_str(id.as_str()).map_err(UsermanError::ParseObjectId) {
+        Ok(t) => t,
+        Err(err) => return Output::<()>::Failure(err).into_response(),
+    };
+
+    let user = match shared.users.get_by_id(&object_id).await {
+        Some(t) => t,
+        None => return Output::<()>::Failure(UsermanError::UserNotFound).into_response(),
+    };
+
+    let size = avatars::nearest_size(query.size);
+
+    let etag = match user.avatar {
+        Some(ref t) => avatars::etag(t, size),
+        None => avatars::identicon_etag(avatars::identicon_seed(&user.username), size),
+    };
+
+    let cached = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|t| t.to_str().ok())
+        .map(|t| t.split(',').any(|t| t.trim() == etag))
+        .unwrap_or(false);
+
+    if cached {
+        return (
+            StatusCode::NOT_MODIFIED,
+            [(header::ETAG, etag), (header::CACHE_CONTROL, "no-cache".to_string())],
+        )
+            .into_response();
+    }
+
+    let data = match user.avatar {
+        Some(ref t) => shared.dao.read_avatar(&object_id, t, size).await,
+        None => avatars::identicon(avatars::identicon_seed(&user.username), size).map(Some),
+    };
+
+    match data {
+        Ok(Some(data)) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, AVATAR_MIME.to_string()),
+                (header::ETAG, etag),
+                (header::CACHE_CONTROL, "no-cache".to_string()),
+            ],
+            data,
+        )
+            .into_response(),
+        Ok(None) => Output::<()>::Failure(UsermanError::MissingAvatar).into_response(),
+        Err(err) => Output::<()>::Failure(err).into_response(),
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ImportQuery {
+    format: Option<ImportFormat>,
+    /// Only validate the rows.
+    dry_run: Option<bool>,
+    /// Update existing users instead of reporting a conflict. Only the fields
+    /// given by the rows are changed.
+    upsert: Option<bool>,
+}
+
+#[utoipa::path(
+    post, 
+    path = "/api/v1/users/import",
+    params(ImportQuery),
+    request_body(content = String, description = "JSON array or CSV rows of users"),
+    responses(
+        (
+            status = StatusCode::OK, 
+            description = "Import users successfully", 
+            body = StatusImportReport,
+            example = json!(Status::<ImportReport>::example_ok())
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Import users with error",
+            body = StatusImportReport,
+            example = json!(Status::<ImportReport>::example_bad_request())
+        )
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[instrument(name = "users::import", skip_all)]
+pub(crate) async fn import(
+    token: SessionToken,
+    Extension(shared): Extension<Shared>,
+    Query(query): Query<ImportQuery>,
+    body: String,
+) -> impl IntoResponse {
+    let items = permissions!(shared, token);
+    let create = value!(items, "/users/create.boolean");
+
+    validate_bool!(create);
+
+    let upsert = query.upsert.unwrap_or(false);
+
+    if upsert {
+        let update = value!(items, "/users/update.boolean");
+
+        validate_bool!(update);
+    }
+
+    let rows = match imports::parse_rows(&body, query.format.unwrap_or_default()) {
+        Ok(t) => t,
+        Err(err) => return Output::Failure(err),
+    };
+
+    let options = ImportOptions {
+        dry_run: query.dry_run.unwrap_or(false),
+        upsert,
+    };
+
+    let output = match imports::import_users(&shared.dao, rows, options).await {
+        Ok(t) => Output::Success(t),
+        Err(err) => Output::Failure(err),
+    };
+
+    // A dry run changes nothing.
+    if !options.dry_run {
+        let report = match &output {
+            Output::Success(t) => Some(t),
+            _ => None,
+        };
+
+        audit::record(
+            &shared,
+            AuditEvent::new("users.import")
+                .changes(None::<&ImportReport>, report)
+                .output(&output),
+        )
+        .await;
+    }
+
+    output
+}
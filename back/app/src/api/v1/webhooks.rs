@@ -0,0 +1,414 @@
+use std::str::FromStr;
+
+use axum::extract::{Json, Path, Query};
+use axum::response::{Extension, IntoResponse};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde_json::json;
+use tracing::instrument;
+
+use super::{Example, Output, Status};
+use crate::audit::{self, AuditEvent};
+use crate::error::UsermanError;
+use crate::pages::{Page, PageQuery};
+use crate::tokens::SessionToken;
+use crate::webhooks::{Delivery, DeliveryQuery, DeliveryStatus, Webhook, WebhookEvent};
+use crate::Shared;
+
+/// Fields the listing can be sorted by, the first one by default.
+const SORTS: &[&str] = &["url", "enabled", "description", "createdAt", "updatedAt"];
+
+impl Example for Webhook {
+    fn example() -> Self {
+        Self {
+            id: None,
+            url: "https://example.com/hooks/userman".to_string(),
+            secret: Some("SECRET".to_string()),
+            events: vec![WebhookEvent::UserCreated, WebhookEvent::UserDeleted],
+            enabled: true,
+            description: "DESCRIPTION".to_string(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+}
+
+impl Example for Delivery {
+    fn example() -> Self {
+        Self {
+            id: None,
+            webhook_id: ObjectId::new(),
+            event: WebhookEvent::UserDeleted,
+            data: json!({ "id": "ID" }),
+            status: DeliveryStatus::Pending,
+            attempts: 1,
+            response_status: Some(503),
+            error: Some("ERROR_MESSAGE".to_string()),
+            next_attempt_at: Some(DateTime::now()),
+            created_at: DateTime::now(),
+            updated_at: Some(DateTime::now()),
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks",
+    request_body(content = Webhook, example = json!(Webhook::example())),
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "Create webhook successfully",
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_empty_ok())
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Create webhook with error",
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_bad_request())
+        )
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[instrument(name = "webhooks::create", skip_all)]
+pub(crate) async fn create(
+    token: SessionToken,
+    Extension(shared): Extension<Shared>,
+    Json(payload): Json<Webhook>,
+) -> impl IntoResponse {
+    let items = permissions!(shared, token);
+    let update = value!(items, "/webhooks/update.boolean");
+
+    validate_bool!(update);
+
+    let output = match payload.validate(true) {
+        Ok(()) => match shared.dao.create_webhook(&payload).await {
+            Ok(_) => Output::<()>::Done,
+            Err(err) => Output::Failure(err),
+        },
+        Err(err) => Output::Failure(err),
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("webhooks.create")
+            .target("webhook", None, Some(&payload.url))
+            .changes(None::<&Webhook>, Some(&payload))
+            .output(&output),
+    )
+    .await;
+
+    output
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/<id>",
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "Read webhook successfully",
+            body = StatusWebhook,
+            example = json!(Status::<Webhook>::example_ok())
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Read webhook with error",
+            body = StatusWebhook,
+            example = json!(Status::<Webhook>::example_bad_request())
+        )
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[instrument(name = "webhooks::read", skip_all)]
+pub(crate) async fn read(
+    id: Path<String>,
+    token: SessionToken,
+    Extension(shared): Extension<Shared>,
+) -> impl IntoResponse {
+    let items = permissions!(shared, token);
+    let read = value!(items, "/webhooks/read.boolean");
+
+    validate_bool!(read);
+
+    let object_id = match ObjectId::from_str(id.as_str()).map_err(UsermanError::ParseObjectId) {
+        Ok(t) => t,
+        Err(err) => return Output::Failure(err),
+    };
+
+    match shared.dao.read_webhook_by_id(&object_id).await {
+        Ok(Some(t)) => Output::Success(t),
+        Ok(None) => Output::Done,
+        Err(err) => Output::Failure(err),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks",
+    params(PageQuery),
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "Read webhooks successfully",
+            body = StatusWebhooks,
+            example = json!(Status::<Page<Webhook>>::example_ok())
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Read webhooks with error",
+            body = StatusWebhooks,
+            example = json!(Status::<Page<Webhook>>::example_bad_request())
+        )
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[instrument(name = "webhooks::read_all", skip_all)]
+pub(crate) async fn read_all(
+    token: SessionToken,
+    Extension(shared): Extension<Shared>,
+    Query(page): Query<PageQuery>,
+) -> impl IntoResponse {
+    let items = permissions!(shared, token);
+    let read = value!(items, "/webhooks/read.boolean");
+
+    validate_bool!(read);
+
+    // The cursor needs a stable id.
+    let values = match shared.dao.read_all_webhooks().await {
+        Ok(t) => t.into_iter().filter(|t| t.id.is_some()).collect(),
+        Err(err) => return Output::Failure(err),
+    };
+
+    match page.paginate(values, SORTS, |t| t.id.unwrap_or_default().to_hex()) {
+        Ok(t) => Output::Success(t),
+        Err(err) => Output::Failure(err),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/webhooks/<id>",
+    request_body(content = Webhook, example = json!(Webhook::example())),
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "Update webhook successfully",
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_empty_ok())
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Update webhook with error",
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_bad_request())
+        )
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[instrument(name = "webhooks::update", skip_all)]
+pub(crate) async fn update(
+    id: Path<String>,
+    token: SessionToken,
+    Extension(shared): Extension<Shared>,
+    Json(payload): Json<Webhook>,
+) -> impl IntoResponse {
+    let items = permissions!(shared, token);
+    let update = value!(items, "/webhooks/update.boolean");
+
+    validate_bool!(update);
+
+    let before = match ObjectId::from_str(id.as_str()) {
+        Ok(t) => shared.dao.read_webhook_by_id(&t).await.ok().flatten(),
+        Err(_) => None,
+    };
+
+    let output = match payload.validate(false) {
+        Ok(()) => match shared.dao.update_webhook_by_id(id.as_str(), &payload).await {
+            Ok(_) => Output::<()>::Done,
+            Err(err) => Output::Failure(err),
+        },
+        Err(err) => Output::Failure(err),
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("webhooks.update")
+            .target("webhook", Some(id.as_str()), Some(&payload.url))
+            .changes(before.as_ref(), Some(&payload))
+            .output(&output),
+    )
+    .await;
+
+    output
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/webhooks/<id>",
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "Delete webhook successfully",
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_empty_ok())
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Delete webhook with error",
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_bad_request())
+        )
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[instrument(name = "webhooks::delete", skip_all)]
+pub(crate) async fn delete(
+    id: Path<String>,
+    token: SessionToken,
+    Extension(shared): Extension<Shared>,
+) -> impl IntoResponse {
+    let items = permissions!(shared, token);
+    let update = value!(items, "/webhooks/update.boolean");
+
+    validate_bool!(update);
+
+    let before = match ObjectId::from_str(id.as_str()) {
+        Ok(t) => shared.dao.read_webhook_by_id(&t).await.ok().flatten(),
+        Err(_) => None,
+    };
+
+    let output = match shared.dao.delete_webhook_by_id(id.as_str()).await {
+        Ok(_) => Output::<()>::Done,
+        Err(err) => Output::Failure(err),
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("webhooks.delete")
+            .target(
+                "webhook",
+                Some(id.as_str()),
+                before.as_ref().map(|t| t.url.as_str()),
+            )
+            .changes(before.as_ref(), None::<&Webhook>)
+            .output(&output),
+    )
+    .await;
+
+    output
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/<id>/deliveries",
+    params(DeliveryQuery),
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "Read webhook deliveries successfully",
+            body = StatusDeliveries,
+            example = json!(Status::<Page<Delivery>>::example_ok())
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Read webhook deliveries with error",
+            body = StatusDeliveries,
+            example = json!(Status::<Page<Delivery>>::example_bad_request())
+        )
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[instrument(name = "webhooks::deliveries", skip_all)]
+pub(crate) async fn deliveries(
+    id: Path<String>,
+    token: SessionToken,
+    Extension(shared): Extension<Shared>,
+    Query(query): Query<DeliveryQuery>,
+) -> impl IntoResponse {
+    let items = permissions!(shared, token);
+    let read = value!(items, "/webhooks/read.boolean");
+
+    validate_bool!(read);
+
+    let object_id = match ObjectId::from_str(id.as_str()).map_err(UsermanError::ParseObjectId) {
+        Ok(t) => t,
+        Err(err) => return Output::Failure(err),
+    };
+
+    match query.read(&shared.dao, object_id).await {
+        Ok(t) => Output::Success(t),
+        Err(err) => Output::Failure(err),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks/<id>/deliveries/<delivery>/redeliver",
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "Redeliver webhook delivery successfully",
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_empty_ok())
+        ),
+        (
+            status = StatusCode::BAD_REQUEST,
+            description = "Redeliver webhook delivery with error",
+            body = StatusGeneric,
+            example = json!(Status::<()>::example_bad_request())
+        )
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+#[instrument(name = "webhooks::redeliver", skip_all)]
+pub(crate) async fn redeliver(
+    Path((id, delivery)): Path<(String, String)>,
+    token: SessionToken,
+    Extension(shared): Extension<Shared>,
+) -> impl IntoResponse {
+    let items = permissions!(shared, token);
+    let update = value!(items, "/webhooks/update.boolean");
+
+    validate_bool!(update);
+
+    let ids = ObjectId::from_str(&id)
+        .and_then(|a| ObjectId::from_str(&delivery).map(|b| (a, b)))
+        .map_err(UsermanError::ParseObjectId);
+
+    let output = match ids {
+        Ok((a, b)) => match shared.dao.redeliver(&a, &b).await {
+            Ok(()) => {
+                shared.webhooks.wake();
+                Output::<()>::Done
+            }
+            Err(err) => Output::Failure(err),
+        },
+        Err(err) => Output::Failure(err),
+    };
+
+    audit::record(
+        &shared,
+        AuditEvent::new("webhooks.redeliver")
+            .target("delivery", Some(&delivery), None)
+            .output(&output),
+    )
+    .await;
+
+    output
+}
@@ -0,0 +1,380 @@
+//! Append-only log of the administrative and security events, stored in the
+//! `audit` collection. Events are recorded by the handlers once they know the
+//! outcome, writing one never fails the request.
+
+use chrono::{DateTime as ChronoDateTime, Utc};
+use log::error;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::serde_helpers::serialize_bson_datetime_as_rfc3339_string;
+use mongodb::bson::{doc, DateTime, Document};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::str::FromStr;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::access;
+use crate::api::v1::Output;
+use crate::dao::{Dao, Memory};
+use crate::pages::Page;
+use crate::{serialize_option_oid_as_string, Result, Shared, UsermanError};
+
+const AUDIT_LIMIT: usize = 50;
+const AUDIT_MAX_LIMIT: usize = 500;
+
+/// Fields left out of the diffs. Passwords have their own actions and the rest
+/// is set by the DAO.
+const IGNORED: [&str; 4] = ["id", "password", "createdAt", "updatedAt"];
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditResult {
+    Success,
+    Failure,
+    Unauthorized,
+}
+
+impl std::fmt::Display for AuditResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Success => write!(f, "success"),
+            Self::Failure => write!(f, "failure"),
+            Self::Unauthorized => write!(f, "unauthorized"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditTarget {
+    /// `app`, `role`, `user`...
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Field changed by an event, `path` joins the nested keys with dots.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditChange {
+    pub path: String,
+    #[schema(value_type = Object)]
+    pub before: Option<Value>,
+    #[schema(value_type = Object)]
+    pub after: Option<Value>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEvent {
+    #[serde(
+        rename(serialize = "id", deserialize = "_id"),
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_option_oid_as_string"
+    )]
+    #[schema(value_type = String)]
+    pub id: Option<ObjectId>,
+    /// User who made the change, or who tried to log in.
+    pub actor: Option<String>,
+    /// `<module>.<handler>`, e.g. `users.update`.
+    pub action: String,
+    pub target: Option<AuditTarget>,
+    pub result: AuditResult,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub changes: Vec<AuditChange>,
+    pub ip: Option<String>,
+    pub request_id: Option<String>,
+    #[serde(serialize_with = "serialize_bson_datetime_as_rfc3339_string")]
+    #[schema(value_type = String)]
+    pub created_at: DateTime,
+}
+
+/// [`AuditEvent`] as stored, keeping the native date to filter on.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEventDB<'a> {
+    actor: &'a Option<String>,
+    action: &'a str,
+    target: &'a Option<AuditTarget>,
+    result: AuditResult,
+    error: &'a Option<String>,
+    changes: &'a Vec<AuditChange>,
+    ip: &'a Option<String>,
+    request_id: &'a Option<String>,
+    created_at: DateTime,
+}
+
+impl<'a> From<&'a AuditEvent> for AuditEventDB<'a> {
+    fn from(event: &'a AuditEvent) -> Self {
+        Self {
+            actor: &event.actor,
+            action: &event.action,
+            target: &event.target,
+            result: event.result,
+            error: &event.error,
+            changes: &event.changes,
+            ip: &event.ip,
+            request_id: &event.request_id,
+            created_at: event.created_at,
+        }
+    }
+}
+
+impl AuditEvent {
+    /// Event of the request being served, its actor is the authenticated user.
+    pub fn new(action: &str) -> Self {
+        Self {
+            id: None,
+            actor: access::user(),
+            action: action.to_string(),
+            target: None,
+            result: AuditResult::Success,
+            error: None,
+            changes: vec![],
+            ip: access::ip(),
+            request_id: access::request_id(),
+            created_at: DateTime::now(),
+        }
+    }
+
+    pub fn actor(mut self, username: &str) -> Self {
+        self.actor = Some(username.to_string());
+        self
+    }
+
+    pub fn target(mut self, kind: &str, id: Option<&str>, name: Option<&str>) -> Self {
+        self.target = Some(AuditTarget {
+            kind: kind.to_string(),
+            id: id.map(|t| t.to_string()),
+            name: name.map(|t| t.to_string()),
+        });
+        self
+    }
+
+    /// Fields differing between the target before and after the change.
+    pub fn changes<B: Serialize, A: Serialize>(
+        mut self,
+        before: Option<&B>,
+        after: Option<&A>,
+    ) -> Self {
+        let before = before.and_then(|t| serde_json::to_value(t).ok());
+        let after = after.and_then(|t| serde_json::to_value(t).ok());
+
+        self.changes = diff(before.as_ref(), after.as_ref());
+        self
+    }
+
+    pub fn output<T: Serialize>(mut self, output: &Output<T>) -> Self {
+        let (result, error) = match output {
+            Output::Done | Output::Success(_) => (AuditResult::Success, None),
+            Output::Failure(err) => (AuditResult::Failure, Some(err.to_string())),
+            Output::Unauthorized(err) => (AuditResult::Unauthorized, Some(err.to_string())),
+        };
+
+        self.result = result;
+        self.error = error;
+        self
+    }
+}
+
+fn flatten(prefix: &str, value: &Value, out: &mut Map<String, Value>) {
+    match value {
+        Value::Object(t) => {
+            for (key, value) in t {
+                if IGNORED.contains(&key.as_str()) {
+                    continue;
+                }
+
+                let path = match prefix.is_empty() {
+                    true => key.to_string(),
+                    false => format!("{}.{}", prefix, key),
+                };
+
+                flatten(&path, value, out);
+            }
+        }
+        _ => {
+            out.insert(prefix.to_string(), value.clone());
+        }
+    }
+}
+
+/// Compare two JSON values field by field. Arrays are compared as a whole.
+pub fn diff(before: Option<&Value>, after: Option<&Value>) -> Vec<AuditChange> {
+    let mut a = Map::new();
+    let mut b = Map::new();
+
+    if let Some(t) = before {
+        flatten("", t, &mut a);
+    }
+
+    if let Some(t) = after {
+        flatten("", t, &mut b);
+    }
+
+    let mut changes: Vec<AuditChange> = a
+        .iter()
+        .filter(|(path, value)| b.get(*path) != Some(*value))
+        .map(|(path, value)| AuditChange {
+            path: path.to_string(),
+            before: Some(value.clone()),
+            after: b.get(path).cloned(),
+        })
+        .collect();
+
+    changes.extend(
+        b.iter()
+            .filter(|(path, _)| !a.contains_key(*path))
+            .map(|(path, value)| AuditChange {
+                path: path.to_string(),
+                before: None,
+                after: Some(value.clone()),
+            }),
+    );
+
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    changes
+}
+
+/// Cached value of `id`, the state before a change.
+pub async fn cached<T, I, M: Memory<T, I> + Sync>(memory: &M, id: &str) -> Option<T> {
+    match ObjectId::from_str(id) {
+        Ok(t) => memory.get_by_id(&t).await,
+        Err(_) => None,
+    }
+}
+
+/// Store `event` and forward it to the sinks, logging the error if it can't
+/// be stored.
+pub async fn record(shared: &Shared, mut event: AuditEvent) {
+    match shared.dao.create_audit_event(&event).await {
+        Ok(t) => event.id = t,
+        Err(err) => error!("Audit event {} not recorded. {}", event.action, err),
+    }
+
+    shared.sinks.send(event);
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub target_kind: Option<String>,
+    pub target_id: Option<String>,
+    pub result: Option<AuditResult>,
+    /// RFC 3339 date of the oldest event.
+    pub from: Option<String>,
+    /// RFC 3339 date of the newest event.
+    pub to: Option<String>,
+    /// Page number starting at 1. Ignored when `cursor` is set.
+    pub page: Option<usize>,
+    /// Events per page, 50 by default and 500 at most.
+    pub limit: Option<usize>,
+    /// Id of the last event of the previous page.
+    pub cursor: Option<String>,
+}
+
+fn parse_date(raw: &str) -> Result<DateTime> {
+    ChronoDateTime::parse_from_rfc3339(raw)
+        .map(|t| DateTime::from_chrono(t.with_timezone(&Utc)))
+        .map_err(|err| UsermanError::InvalidAuditQuery(format!("{}: {}", raw, err)))
+}
+
+impl AuditQuery {
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(AUDIT_LIMIT).clamp(1, AUDIT_MAX_LIMIT)
+    }
+
+    /// MongoDB filter of the query, without the cursor.
+    pub fn filter(&self) -> Result<Document> {
+        let mut filter = doc! {};
+
+        if let Some(t) = &self.actor {
+            filter.insert("actor", t);
+        }
+
+        if let Some(t) = &self.action {
+            filter.insert("action", t);
+        }
+
+        if let Some(t) = &self.target_kind {
+            filter.insert("target.kind", t);
+        }
+
+        if let Some(t) = &self.target_id {
+            filter.insert("target.id", t);
+        }
+
+        if let Some(t) = &self.result {
+            let result = mongodb::bson::to_bson(t)
+                .map_err(|err| UsermanError::InvalidAuditQuery(err.to_string()))?;
+            filter.insert("result", result);
+        }
+
+        let mut created_at = doc! {};
+
+        if let Some(t) = &self.from {
+            created_at.insert("$gte", parse_date(t)?);
+        }
+
+        if let Some(t) = &self.to {
+            created_at.insert("$lte", parse_date(t)?);
+        }
+
+        if !created_at.is_empty() {
+            filter.insert("createdAt", created_at);
+        }
+
+        Ok(filter)
+    }
+
+    /// Read the requested page, newest events first.
+    pub async fn read(&self, dao: &Dao) -> Result<Page<AuditEvent>> {
+        let filter = self.filter()?;
+        let limit = self.limit();
+
+        let (page, skip, cursor) = match &self.cursor {
+            Some(t) => {
+                let id = ObjectId::from_str(t).map_err(|_| UsermanError::InvalidCursor)?;
+                (None, 0, Some(id))
+            }
+            None => {
+                let page = self.page.unwrap_or(1).max(1);
+                (Some(page), (page - 1).saturating_mul(limit), None)
+            }
+        };
+
+        let total = dao.count_audit_events(filter.clone()).await?;
+
+        let mut query = filter;
+
+        if let Some(t) = cursor {
+            query.insert("_id", doc! { "$lt": t });
+        }
+
+        // One more event tells whether there is a next page.
+        let mut items = dao
+            .read_audit_events(query, skip as u64, limit as i64 + 1)
+            .await?;
+
+        let next_cursor = match items.len() > limit {
+            true => {
+                items.truncate(limit);
+                items.last().and_then(|t| t.id).map(|t| t.to_hex())
+            }
+            false => None,
+        };
+
+        Ok(Page {
+            items,
+            total: total as usize,
+            page,
+            limit: Some(limit),
+            next_cursor,
+        })
+    }
+}
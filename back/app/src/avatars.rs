@@ -0,0 +1,153 @@
+//! User avatars processing. Uploaded images are re-encoded as PNG, which drops
+//! any embedded metadata, cropped square and resized to a few fixed sizes.
+//! Users without an uploaded avatar get a generated identicon.
+use image::imageops::FilterType;
+use image::io::{Limits, Reader};
+use image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+use std::io::Cursor;
+
+use crate::{Result, UsermanError};
+
+pub const AVATAR_SIZES: [u32; 4] = [32, 64, 128, 256];
+pub const DEFAULT_AVATAR_SIZE: u32 = 128;
+pub const AVATAR_MIME: &str = "image/png";
+pub const AVATAR_MAX_BYTES: usize = 10 * 1024 * 1024;
+/// Largest width or height of an uploaded image. A small file can declare huge
+/// dimensions, so they are checked before decoding.
+pub const AVATAR_MAX_SIDE: u32 = 4096;
+const AVATAR_MAX_ALLOC: u64 = 128 * 1024 * 1024;
+
+/// Identicon colors. Keep the order, every app derives the same color from it.
+const PALETTE: [[u8; 3]; 10] = [
+    [0x1e, 0x88, 0xe5],
+    [0x43, 0xa0, 0x47],
+    [0xe5, 0x39, 0x35],
+    [0xfb, 0x8c, 0x00],
+    [0x8e, 0x24, 0xaa],
+    [0x00, 0x89, 0x7b],
+    [0x6d, 0x4c, 0x41],
+    [0x3f, 0x51, 0xb5],
+    [0xd8, 0x1b, 0x60],
+    [0x54, 0x6e, 0x7a],
+];
+const BACKGROUND: [u8; 3] = [0xf0, 0xf0, 0xf0];
+const GRID: u32 = 5;
+
+#[derive(Clone, Debug)]
+pub struct Avatar {
+    pub size: u32,
+    pub data: Vec<u8>,
+}
+
+/// Decode `src` within the avatar limits.
+fn decode(src: &[u8]) -> Result<DynamicImage> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(AVATAR_MAX_SIDE);
+    limits.max_image_height = Some(AVATAR_MAX_SIDE);
+    limits.max_alloc = Some(AVATAR_MAX_ALLOC);
+
+    let mut reader = Reader::new(Cursor::new(src))
+        .with_guessed_format()
+        .map_err(|err| UsermanError::Image(err.to_string()))?;
+
+    reader.limits(limits);
+
+    reader
+        .decode()
+        .map_err(|err| UsermanError::Image(err.to_string()))
+}
+
+/// Decode an uploaded image and build one PNG for every size in `AVATAR_SIZES`.
+pub fn resize(src: &[u8]) -> Result<Vec<Avatar>> {
+    let image = decode(src)?;
+
+    let side = image.width().min(image.height());
+    let x = (image.width() - side) / 2;
+    let y = (image.height() - side) / 2;
+
+    let square = image.crop_imm(x, y, side, side);
+
+    let mut avatars = vec![];
+
+    for size in AVATAR_SIZES {
+        let mut data = Cursor::new(vec![]);
+
+        square
+            .resize_exact(size, size, FilterType::Lanczos3)
+            .write_to(&mut data, ImageOutputFormat::Png)
+            .map_err(|err| UsermanError::Image(err.to_string()))?;
+
+        avatars.push(Avatar {
+            size,
+            data: data.into_inner(),
+        });
+    }
+
+    Ok(avatars)
+}
+
+/// Smallest available size that is not lower than the requested one.
+pub fn nearest_size(size: Option<u32>) -> u32 {
+    match size {
+        Some(t) => AVATAR_SIZES
+            .into_iter()
+            .find(|s| *s >= t)
+            .unwrap_or(AVATAR_SIZES[AVATAR_SIZES.len() - 1]),
+        None => DEFAULT_AVATAR_SIZE,
+    }
+}
+
+pub fn etag(version: &str, size: u32) -> String {
+    format!("\"{}-{}\"", version, size)
+}
+
+/// 64 bits FNV-1a. Unlike `DefaultHasher` its output is fixed, so identicons
+/// do not change between builds.
+fn fnv1a(src: &str) -> u64 {
+    src.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Seed used to generate the default avatar of a user. It only depends on the
+/// username, so renaming a user keeps the same picture.
+pub fn identicon_seed(username: &str) -> u64 {
+    fnv1a(&username.to_lowercase())
+}
+
+/// Render a symmetric 5x5 identicon PNG.
+pub fn identicon(seed: u64, size: u32) -> Result<Vec<u8>> {
+    let color = Rgb(PALETTE[(seed >> 32) as usize % PALETTE.len()]);
+    let cell = size / (GRID + 1);
+    let margin = (size - cell * GRID) / 2;
+
+    let mut image = RgbImage::from_pixel(size, size, Rgb(BACKGROUND));
+
+    for row in 0..GRID {
+        for col in 0..GRID.div_ceil(2) {
+            if (seed >> (row * 3 + col)) & 1 == 0 {
+                continue;
+            }
+
+            for x in [col, GRID - 1 - col] {
+                for dy in 0..cell {
+                    for dx in 0..cell {
+                        image.put_pixel(margin + x * cell + dx, margin + row * cell + dy, color);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut data = Cursor::new(vec![]);
+
+    DynamicImage::ImageRgb8(image)
+        .write_to(&mut data, ImageOutputFormat::Png)
+        .map_err(|err| UsermanError::Image(err.to_string()))?;
+
+    Ok(data.into_inner())
+}
+
+pub fn identicon_etag(seed: u64, size: u32) -> String {
+    format!("\"{:016x}-{}\"", seed, size)
+}
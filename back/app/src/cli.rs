@@ -0,0 +1,400 @@
+//! Command line interface of the userman binary.
+use clap::{Parser, Subcommand};
+use log::info;
+use std::io::Write;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::config_yaml::{ConfigYAML, CONFIG_ENV};
+use crate::configs::{Config, ConfigData, TokenConfig, TOKEN_CONFIG};
+use crate::exports::{Archive, ExportFormat};
+use crate::imports::{self, ImportFormat, ImportOptions};
+use crate::manifest::{Manifest, Plan, State};
+use crate::restore::{self, ConflictStrategy, RestoreOptions};
+use crate::users::{self, User, ADMIN_USERNAME};
+use crate::{Result, UsermanError, YAML_FILE};
+
+use userman_auth::apps::LOCAL_APP;
+use userman_auth::roles::LOCAL_ROLE;
+use userman_auth::Auth;
+
+#[derive(Parser)]
+#[command(name = "userman", version, about = "Proteus Userman")]
+pub struct Cli {
+    /// Path of the config file, created with defaults when missing.
+    #[arg(long, global = true, env = CONFIG_ENV, default_value = YAML_FILE)]
+    pub config: String,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the web server and the database watchers. Default command.
+    Serve,
+    /// Create a user with the userman role, or give it back to an existing one.
+    CreateAdmin {
+        #[arg(long, default_value = ADMIN_USERNAME)]
+        username: String,
+        #[arg(long)]
+        email: Option<String>,
+        /// Random when missing, then printed once.
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Set a new password for a user.
+    ResetPassword {
+        username: String,
+        /// Random when missing, then printed once.
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Enable a disabled user.
+    Unlock { username: String },
+    /// Replace the secret that signs access tokens. Tokens signed with the
+    /// previous one are rejected once the servers reload it.
+    RotateKeys,
+    /// Create the indexes and the built in app, role and user.
+    Migrate,
+    /// Validate the config file and connect to MongoDB, exits with 1 on any
+    /// problem.
+    CheckConfig,
+    /// Create users from a CSV or JSON file.
+    ImportUsers {
+        /// Path of the file, CSV when it ends with `.csv`.
+        file: String,
+        /// Only validate the rows.
+        #[arg(long)]
+        dry_run: bool,
+        /// Update existing users instead of reporting a conflict. Only the
+        /// fields given by the rows are changed.
+        #[arg(long)]
+        upsert: bool,
+    },
+    /// Dump apps, roles, users and configs.
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+        /// Path of the archive. Printed to stdout when missing.
+        #[arg(long)]
+        output: Option<String>,
+        /// Include password hashes and token secrets.
+        #[arg(long)]
+        include_secrets: bool,
+    },
+    /// Load an export archive, JSON or NDJSON when it ends with `.ndjson`.
+    #[command(alias = "import")]
+    Restore {
+        file: String,
+        /// How to handle apps, roles and users that already exist.
+        #[arg(long, value_enum, default_value_t = ConflictStrategy::Skip)]
+        strategy: ConflictStrategy,
+        /// Only resolve the records.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Bring apps, roles and users to the state described by a YAML or JSON
+    /// manifest.
+    Apply {
+        file: String,
+        /// Only print the plan.
+        #[arg(long)]
+        dry_run: bool,
+        /// Apply the plan without asking for confirmation.
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+pub async fn import_users(
+    config_yaml: &ConfigYAML,
+    file: &str,
+    options: ImportOptions,
+) -> Result<()> {
+    let content = tokio::fs::read_to_string(file)
+        .await
+        .map_err(|err| UsermanError::StdIoError(err.to_string()))?;
+
+    let rows = imports::parse_rows(&content, ImportFormat::from_path(file))?;
+
+    let dao = config_yaml.dao().await?;
+    let report = imports::import_users(&dao, rows, options).await?;
+
+    info!(
+        "Imported {} users: {} created, {} updated, {} errors.",
+        file,
+        report.created,
+        report.updated,
+        report.errors.len()
+    );
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report).map_err(UsermanError::CreateJSON)?
+    );
+
+    Ok(())
+}
+
+pub async fn export(
+    config_yaml: &ConfigYAML,
+    format: ExportFormat,
+    output: Option<&str>,
+    include_secrets: bool,
+) -> Result<()> {
+    let dao = config_yaml.dao().await?;
+    let content = Archive::read(&dao, include_secrets)
+        .await?
+        .into_string(format)?;
+
+    match output {
+        Some(t) => {
+            tokio::fs::write(t, content)
+                .await
+                .map_err(|err| UsermanError::StdIoError(err.to_string()))?;
+
+            info!("Exported to {}.", t);
+        }
+        None => print!("{}", content),
+    }
+
+    Ok(())
+}
+
+pub async fn restore(config_yaml: &ConfigYAML, file: &str, options: RestoreOptions) -> Result<()> {
+    let content = tokio::fs::read_to_string(file)
+        .await
+        .map_err(|err| UsermanError::StdIoError(err.to_string()))?;
+
+    let archive = Archive::parse(&content, ExportFormat::from_path(file))?;
+
+    let dao = config_yaml.dao().await?;
+    let report = restore::restore(&dao, archive, options).await?;
+
+    info!("Restored {}.", file);
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report).map_err(UsermanError::CreateJSON)?
+    );
+
+    Ok(())
+}
+
+async fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    std::io::stdout()
+        .flush()
+        .map_err(|err| UsermanError::StdIoError(err.to_string()))?;
+
+    let mut answer = String::new();
+
+    BufReader::new(tokio::io::stdin())
+        .read_line(&mut answer)
+        .await
+        .map_err(|err| UsermanError::StdIoError(err.to_string()))?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+pub async fn apply(config_yaml: &ConfigYAML, file: &str, dry_run: bool, yes: bool) -> Result<()> {
+    let content = tokio::fs::read_to_string(file)
+        .await
+        .map_err(|err| UsermanError::StdIoError(err.to_string()))?;
+
+    let manifest = Manifest::parse(&content)?;
+
+    let dao = config_yaml.dao().await?;
+    let plan = Plan::new(manifest, &State::read(&dao).await?)?;
+
+    println!("{}", plan);
+
+    if plan.is_empty() || dry_run {
+        return Ok(());
+    }
+
+    if !yes && !confirm("Apply these changes?").await? {
+        info!("Apply of {} cancelled.", file);
+        return Ok(());
+    }
+
+    plan.apply(&dao).await?;
+
+    info!("Applied {} changes from {}.", plan.steps.len(), file);
+
+    Ok(())
+}
+
+/// Use `password` or a random one, which is printed since nobody knows it.
+/// Random passwords have to be changed on next login.
+fn password_or_random(username: &str, password: Option<String>) -> (String, bool) {
+    match password {
+        Some(t) => (t, false),
+        None => {
+            let password = users::random_password();
+            println!("Password of {}: {}", username, password);
+            (password, true)
+        }
+    }
+}
+
+pub async fn create_admin(
+    config_yaml: &ConfigYAML,
+    username: &str,
+    email: Option<String>,
+    password: Option<String>,
+) -> Result<()> {
+    let dao = config_yaml.dao().await?;
+
+    let role = dao
+        .read_role_by_name(LOCAL_ROLE)
+        .await?
+        .ok_or(UsermanError::RoleNotFound)?;
+
+    let id = match dao.read_user_by_username(username).await? {
+        Some(mut user) => {
+            if !user.roles.contains(&role.id()) {
+                user.roles.push(role.id());
+            }
+
+            if let Some(t) = email {
+                user.email = t;
+            }
+
+            user.enabled = true;
+
+            dao.update_user_by_id(user.id().to_hex(), &user.clone().none_password())
+                .await?;
+
+            info!("Granted {} role to user {}.", LOCAL_ROLE, username);
+            user.id()
+        }
+        None => {
+            let user = User {
+                id: None,
+                username: username.to_string(),
+                password: None,
+                email: email.unwrap_or_else(|| User::default().email),
+                name: username.to_string(),
+                surname: username.to_string(),
+                description: String::new(),
+                department: String::new(),
+                roles: vec![role.id()],
+                avatar: None,
+                enabled: true,
+                change_password: false,
+                created_at: None,
+                updated_at: None,
+            };
+
+            let id = dao
+                .create_user(&user)
+                .await?
+                .ok_or(UsermanError::UserNotFound)?;
+
+            info!("Created user {}.", username);
+            id
+        }
+    };
+
+    let (password, change) = password_or_random(username, password);
+    dao.set_user_password_by_id(&id, &password, change).await
+}
+
+pub async fn reset_password(
+    config_yaml: &ConfigYAML,
+    username: &str,
+    password: Option<String>,
+) -> Result<()> {
+    let dao = config_yaml.dao().await?;
+
+    let user = dao
+        .read_user_by_username(username)
+        .await?
+        .ok_or(UsermanError::UserNotFound)?;
+
+    let (password, change) = password_or_random(username, password);
+    dao.set_user_password_by_id(&user.id(), &password, change)
+        .await?;
+
+    info!("Password of {} changed.", username);
+
+    Ok(())
+}
+
+pub async fn unlock(config_yaml: &ConfigYAML, username: &str) -> Result<()> {
+    let dao = config_yaml.dao().await?;
+
+    let user = dao
+        .read_user_by_username(username)
+        .await?
+        .ok_or(UsermanError::UserNotFound)?;
+
+    if user.enabled {
+        info!("User {} is already enabled.", username);
+        return Ok(());
+    }
+
+    let user = User {
+        enabled: true,
+        ..user.none_password()
+    };
+
+    dao.update_user_by_id(user.id().to_hex(), &user).await?;
+
+    info!("User {} enabled.", username);
+
+    Ok(())
+}
+
+pub async fn rotate_keys(config_yaml: &ConfigYAML) -> Result<()> {
+    let dao = config_yaml.dao().await?;
+
+    let config = dao
+        .read_config(TOKEN_CONFIG)
+        .await?
+        .ok_or(UsermanError::GetConfig(TOKEN_CONFIG))?;
+
+    let token = config.unwrap_token()?;
+
+    dao.update_config(&Config {
+        id: config.id(),
+        data: ConfigData::Token(TokenConfig {
+            secret: TokenConfig::default().secret,
+            ..token
+        }),
+    })
+    .await?;
+
+    info!("Token secret rotated.");
+
+    Ok(())
+}
+
+pub async fn migrate(config_yaml: &ConfigYAML) -> Result<()> {
+    let dao = config_yaml.dao().await?;
+    dao.init().await?;
+
+    Auth::builder(LOCAL_APP)
+        .mongodb(config_yaml.mongo_db.clone())
+        .build()
+        .await?
+        .init()
+        .await?;
+
+    info!("Database {} is up to date.", config_yaml.mongo_db.db_name);
+
+    Ok(())
+}
+
+/// Check that the config file, already validated when read, reaches the
+/// database.
+pub async fn check_config(config_yaml: &ConfigYAML) -> Result<()> {
+    config_yaml.dao().await?.ping().await?;
+
+    info!(
+        "Configuration is valid, connected to {}.",
+        config_yaml.mongo_db.db_name
+    );
+
+    Ok(())
+}
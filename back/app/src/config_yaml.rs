@@ -1,9 +1,13 @@
-//! Main app configuration readed from config.yaml.
+//! Main app configuration readed from config.yaml. Any field can be
+//! overridden with a `USERMAN_*` environment variable, and read from a file
+//! with a `*File` field, e.g. `mongoDb.uriFile`.
 use haikunator::Haikunator;
-use log::{debug, info};
+use log::{debug, info, warn};
 use mongodb::bson::doc;
 use mongodb::{options::ClientOptions, Client};
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::net::IpAddr;
 use std::path::Path;
@@ -11,9 +15,19 @@ use tokio::fs::File;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 use crate::dao::Dao;
-use crate::logger::LogsLevel;
+use crate::logger::{LogsFormat, LogsLevel};
+use crate::telemetry::Exporter;
+use crate::users::{ADMIN_PASSWORD_ENV, ADMIN_PASSWORD_FILE_ENV, ADMIN_USERNAME};
 use crate::{Result, UsermanError};
 
+/// Prefix of the variables overriding config fields. Words are separated with
+/// `_` and nested fields with `__`, e.g. `USERMAN_MONGO_DB__URI`.
+pub const ENV_PREFIX: &str = "USERMAN_";
+/// Variable with the path of the config file.
+pub const CONFIG_ENV: &str = "USERMAN_CONFIG";
+/// Suffix of the fields whose value is read from a file.
+const FILE_SUFFIX: &str = "File";
+
 fn default_mongo_db_uri() -> String {
     String::from("mongodb://localhost:27017")
 }
@@ -23,7 +37,7 @@ fn default_mongo_db_db_name() -> String {
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct MongoDB {
     #[serde(default = "default_mongo_db_uri")]
     pub uri: String,
@@ -52,7 +66,7 @@ impl From<MongoDB> for userman_auth::MongoDB {
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct Tls {
     #[serde(default)]
     pub enabled: bool,
@@ -62,6 +76,19 @@ pub struct Tls {
 
     #[serde(default)]
     pub key: String,
+
+    /// CA bundle verifying client certificates, empty to disable mutual TLS.
+    #[serde(default)]
+    pub client_ca: String,
+
+    /// Reject the connections without a client certificate.
+    #[serde(default)]
+    pub client_auth_required: bool,
+
+    /// Common names of the client certificates and the service users they
+    /// authenticate as. Other certificates are only accepted for TLS.
+    #[serde(default)]
+    pub client_users: BTreeMap<String, String>,
 }
 
 fn default_front_public_url() -> String {
@@ -69,7 +96,7 @@ fn default_front_public_url() -> String {
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct Front {
     #[serde(default = "default_front_public_url")]
     pub public_url: String,
@@ -83,6 +110,280 @@ impl Default for Front {
     }
 }
 
+fn default_logs_directory() -> String {
+    String::from("logs")
+}
+
+fn default_rotation_max_size_mb() -> u64 {
+    250
+}
+
+fn default_rotation_archives() -> u32 {
+    50
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Rotation {
+    /// Size of `main.log` that triggers a rotation.
+    #[serde(default = "default_rotation_max_size_mb")]
+    pub max_size_mb: u64,
+
+    /// Gzip archives kept.
+    #[serde(default = "default_rotation_archives")]
+    pub archives: u32,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Self {
+            max_size_mb: default_rotation_max_size_mb(),
+            archives: default_rotation_archives(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Syslog {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// `host:port` of a remote daemon over UDP, empty for the local socket.
+    #[serde(default)]
+    pub address: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Logs {
+    #[serde(default)]
+    pub level: LogsLevel,
+
+    #[serde(default = "default_logs_directory")]
+    pub directory: String,
+
+    #[serde(default)]
+    pub format: LogsFormat,
+
+    #[serde(default)]
+    pub rotation: Rotation,
+
+    /// Level of a module and its children, e.g. `userman::dao: debug`.
+    #[serde(default)]
+    pub modules: BTreeMap<String, LogsLevel>,
+
+    #[serde(default)]
+    pub syslog: Syslog,
+}
+
+impl Default for Logs {
+    fn default() -> Self {
+        Self {
+            level: LogsLevel::default(),
+            directory: default_logs_directory(),
+            format: LogsFormat::default(),
+            rotation: Rotation::default(),
+            modules: BTreeMap::new(),
+            syslog: Syslog::default(),
+        }
+    }
+}
+
+fn default_tracing_endpoint() -> String {
+    String::from("http://localhost:4318/v1/traces")
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Tracing {
+    #[serde(default)]
+    pub exporter: Exporter,
+
+    /// OTLP/HTTP traces endpoint.
+    #[serde(default = "default_tracing_endpoint")]
+    pub endpoint: String,
+}
+
+impl Default for Tracing {
+    fn default() -> Self {
+        Self {
+            exporter: Exporter::default(),
+            endpoint: default_tracing_endpoint(),
+        }
+    }
+}
+
+fn default_audit_file_path() -> String {
+    String::from("logs/audit.jsonl")
+}
+
+/// Audit events appended as JSON lines.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AuditFile {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(default = "default_audit_file_path")]
+    pub path: String,
+
+    #[serde(default)]
+    pub rotation: Rotation,
+}
+
+impl Default for AuditFile {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: default_audit_file_path(),
+            rotation: Rotation::default(),
+        }
+    }
+}
+
+fn default_audit_http_batch_size() -> usize {
+    100
+}
+
+fn default_audit_http_flush_interval() -> u64 {
+    5
+}
+
+fn default_audit_http_retries() -> u32 {
+    3
+}
+
+fn default_audit_http_retry_delay_ms() -> u64 {
+    1000
+}
+
+fn default_audit_http_timeout() -> u64 {
+    10
+}
+
+/// Audit events posted as JSON arrays.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AuditHttp {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(default)]
+    pub url: String,
+
+    /// Headers added to the requests, e.g. `Authorization`.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    /// Events sent at most by request.
+    #[serde(default = "default_audit_http_batch_size")]
+    pub batch_size: usize,
+
+    /// Seconds between two sends of an incomplete batch.
+    #[serde(default = "default_audit_http_flush_interval")]
+    pub flush_interval: u64,
+
+    /// Attempts after the first failed one before dropping a batch.
+    #[serde(default = "default_audit_http_retries")]
+    pub retries: u32,
+
+    /// Delay before the first retry, doubled on each one.
+    #[serde(default = "default_audit_http_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+
+    /// Seconds to wait for a response.
+    #[serde(default = "default_audit_http_timeout")]
+    pub timeout: u64,
+}
+
+impl Default for AuditHttp {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::new(),
+            headers: BTreeMap::new(),
+            batch_size: default_audit_http_batch_size(),
+            flush_interval: default_audit_http_flush_interval(),
+            retries: default_audit_http_retries(),
+            retry_delay_ms: default_audit_http_retry_delay_ms(),
+            timeout: default_audit_http_timeout(),
+        }
+    }
+}
+
+/// Sinks the audit events are forwarded to, besides MongoDB.
+#[derive(Clone, Debug, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Audit {
+    #[serde(default)]
+    pub file: AuditFile,
+
+    /// RFC 5424 messages.
+    #[serde(default)]
+    pub syslog: Syslog,
+
+    #[serde(default)]
+    pub http: AuditHttp,
+}
+
+fn default_webhooks_max_attempts() -> u32 {
+    8
+}
+
+fn default_webhooks_retry_delay() -> u64 {
+    30
+}
+
+fn default_webhooks_timeout() -> u64 {
+    10
+}
+
+fn default_webhooks_concurrency() -> usize {
+    8
+}
+
+fn default_webhooks_per_webhook() -> usize {
+    2
+}
+
+/// Delivery of the webhook subscriptions stored in MongoDB.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Webhooks {
+    /// Attempts before a delivery is dead-lettered.
+    #[serde(default = "default_webhooks_max_attempts")]
+    pub max_attempts: u32,
+
+    /// Seconds before the first retry, doubled on each one.
+    #[serde(default = "default_webhooks_retry_delay")]
+    pub retry_delay: u64,
+
+    /// Seconds to wait for a response.
+    #[serde(default = "default_webhooks_timeout")]
+    pub timeout: u64,
+
+    /// Deliveries posted at the same time.
+    #[serde(default = "default_webhooks_concurrency")]
+    pub concurrency: usize,
+
+    /// Deliveries posted at the same time to a single webhook.
+    #[serde(default = "default_webhooks_per_webhook")]
+    pub per_webhook: usize,
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_webhooks_max_attempts(),
+            retry_delay: default_webhooks_retry_delay(),
+            timeout: default_webhooks_timeout(),
+            concurrency: default_webhooks_concurrency(),
+            per_webhook: default_webhooks_per_webhook(),
+        }
+    }
+}
+
 fn default_name() -> String {
     Haikunator::default().haikunate()
 }
@@ -95,8 +396,12 @@ fn default_port() -> u16 {
     8090
 }
 
+fn default_shutdown_timeout() -> u64 {
+    30
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct ConfigYAML {
     #[serde(default = "default_name")]
     pub name: String,
@@ -107,8 +412,17 @@ pub struct ConfigYAML {
     #[serde(default = "default_port")]
     pub port: u16,
 
+    /// Seconds to wait for the in-flight requests on shutdown.
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+
+    /// Proxies allowed to give the client IP through `X-Forwarded-For` or
+    /// `X-Real-IP`.
     #[serde(default)]
-    pub logs: LogsLevel,
+    pub trusted_proxies: Vec<IpAddr>,
+
+    #[serde(default)]
+    pub logs: Logs,
 
     #[serde(default)]
     pub mongo_db: MongoDB,
@@ -118,6 +432,15 @@ pub struct ConfigYAML {
 
     #[serde(default)]
     pub front: Front,
+
+    #[serde(default)]
+    pub tracing: Tracing,
+
+    #[serde(default)]
+    pub audit: Audit,
+
+    #[serde(default)]
+    pub webhooks: Webhooks,
 }
 
 impl Default for ConfigYAML {
@@ -126,40 +449,594 @@ impl Default for ConfigYAML {
             name: default_name(),
             ip: default_ip(),
             port: default_port(),
+            shutdown_timeout: default_shutdown_timeout(),
+            trusted_proxies: Vec::new(),
             mongo_db: MongoDB::default(),
             tls: Tls::default(),
-            logs: LogsLevel::default(),
+            logs: Logs::default(),
             front: Front::default(),
+            tracing: Tracing::default(),
+            audit: Audit::default(),
+            webhooks: Webhooks::default(),
+        }
+    }
+}
+
+/// `MONGO_DB__URI` to `["mongoDb", "uri"]`.
+fn env_path(key: &str) -> Vec<String> {
+    key.split("__")
+        .map(|part| {
+            part.to_lowercase()
+                .split('_')
+                .enumerate()
+                .map(|(i, word)| match i {
+                    0 => word.to_string(),
+                    _ => {
+                        let mut chars = word.chars();
+
+                        match chars.next() {
+                            Some(t) => t.to_uppercase().chain(chars).collect(),
+                            None => String::new(),
+                        }
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn lookup<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, key| value.get(key.as_str()))
+}
+
+fn lookup_mut<'a>(value: &'a mut Value, path: &[String]) -> Option<&'a mut Value> {
+    path.iter()
+        .try_fold(value, |value, key| value.get_mut(key.as_str()))
+}
+
+/// Set `path` in `value`, creating the missing mappings.
+fn insert(value: &mut Value, path: &[String], data: Value) {
+    match path.split_first() {
+        None => *value = data,
+        Some((key, rest)) => {
+            if !value.is_mapping() {
+                *value = Value::Mapping(Mapping::new());
+            }
+
+            if let Value::Mapping(t) = value {
+                insert(
+                    t.entry(Value::String(key.clone())).or_insert(Value::Null),
+                    rest,
+                    data,
+                );
+            }
+        }
+    }
+}
+
+/// Older files and variables only set the level, `logs: debug` or
+/// `USERMAN_LOGS=debug`.
+fn legacy_logs(value: &mut Value) {
+    if let Some(level) = value.get("logs").filter(|t| t.is_string()).cloned() {
+        insert(value, &[String::from("logs")], Value::Null);
+        insert(value, &[String::from("logs"), String::from("level")], level);
+    }
+}
+
+/// Text of a variable or a file as the type `schema` expects. Strings are kept
+/// as they are, so a numeric password is not turned into a number.
+fn typed(schema: Option<&Value>, raw: &str) -> Value {
+    match schema {
+        Some(Value::String(_)) | None => Value::String(raw.to_string()),
+        Some(_) => serde_yaml::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string())),
+    }
+}
+
+/// Whether `schema` has the field at `path`. `*File` variants are accepted
+/// for any field.
+fn is_field(schema: &Value, path: &[String]) -> bool {
+    let Some((last, parent)) = path.split_last() else {
+        return false;
+    };
+
+    match lookup(schema, parent).and_then(Value::as_mapping) {
+        Some(t) => t
+            .keys()
+            .filter_map(Value::as_str)
+            .any(|t| t == last || format!("{}{}", t, FILE_SUFFIX) == *last),
+        None => false,
+    }
+}
+
+/// Override `value` with every `USERMAN_*` variable that names a field.
+fn apply_env<I: Iterator<Item = (String, String)>>(value: &mut Value, schema: &Value, vars: I) {
+    for (key, raw) in vars {
+        if [CONFIG_ENV, ADMIN_PASSWORD_ENV, ADMIN_PASSWORD_FILE_ENV].contains(&key.as_str()) {
+            continue;
+        }
+
+        let Some(name) = key.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+
+        let path = env_path(name);
+
+        if !is_field(schema, &path) {
+            warn!("Ignoring {}, it does not match any config field.", key);
+            continue;
+        }
+
+        debug!("Config {} overridden by {}.", path.join("."), key);
+        insert(value, &path, typed(lookup(schema, &path), &raw));
+    }
+}
+
+/// Paths of the fields set through a `*File` field, with the file path.
+fn secret_files(
+    value: &Value,
+    schema: &Value,
+    prefix: &[String],
+    found: &mut Vec<(Vec<String>, String)>,
+) {
+    let Some(schema) = schema.as_mapping() else {
+        return;
+    };
+
+    for (key, child) in schema.iter().filter_map(|(k, v)| Some((k.as_str()?, v))) {
+        let mut path = prefix.to_vec();
+        path.push(key.to_string());
+
+        if let Some(Value::String(t)) = value.get(format!("{}{}", key, FILE_SUFFIX).as_str()) {
+            found.push((path.clone(), t.clone()));
+        }
+
+        if let Some(t) = value.get(key) {
+            secret_files(t, child, &path, found);
+        }
+    }
+}
+
+/// Replace every `*File` field with the content of the file it points to.
+async fn read_secret_files(value: &mut Value, schema: &Value) -> Result<()> {
+    let mut found = vec![];
+    secret_files(value, schema, &[], &mut found);
+
+    for (path, file) in found {
+        let content = tokio::fs::read_to_string(&file)
+            .await
+            .map_err(|err| UsermanError::StdIoError(format!("{}: {}", file, err)))?;
+
+        let (last, parent) = path.split_last().unwrap();
+
+        if let Some(Value::Mapping(t)) = lookup_mut(value, parent) {
+            t.remove(format!("{}{}", last, FILE_SUFFIX).as_str());
+        }
+
+        debug!("Config {} read from {}.", path.join("."), file);
+        insert(value, &path, typed(lookup(schema, &path), content.trim_end()));
+    }
+
+    Ok(())
+}
+
+/// One invalid config field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigProblem {
+    /// Dotted path of the field, e.g. `mongoDb.uri`.
+    pub path: String,
+    pub message: String,
+}
+
+impl ConfigProblem {
+    fn new<P: Into<String>, M: Into<String>>(path: P, message: M) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Every problem found in a config file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfigReport(pub Vec<ConfigProblem>);
+
+impl Display for ConfigReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, problem) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "  {}: {}", problem.path, problem.message)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    match prefix.is_empty() {
+        true => key.to_string(),
+        false => format!("{}.{}", prefix, key),
+    }
+}
+
+/// Report the fields of `value` missing from `schema`, and check every known
+/// leaf on its own so one wrong type does not hide the others.
+fn check_fields(
+    value: &Value,
+    schema: &Value,
+    root: &Value,
+    path: &mut Vec<String>,
+    problems: &mut Vec<ConfigProblem>,
+) {
+    let (Some(mapping), Some(schema_mapping)) = (value.as_mapping(), schema.as_mapping()) else {
+        let mut probe = root.clone();
+        insert(&mut probe, path, value.clone());
+
+        if let Err(err) = serde_yaml::from_value::<ConfigYAML>(probe) {
+            problems.push(ConfigProblem::new(path.join("."), err.to_string()));
+        }
+
+        return;
+    };
+
+    for (key, child) in mapping {
+        let Some(key) = key.as_str() else {
+            problems.push(ConfigProblem::new(
+                join(&path.join("."), &format!("{:?}", key)),
+                "keys must be strings",
+            ));
+            continue;
+        };
+
+        path.push(key.to_string());
+
+        // An empty mapping in the schema is a map of free keys.
+        match schema_mapping.get(key) {
+            Some(t) => check_fields(child, t, root, path, problems),
+            None if schema_mapping.is_empty() => {
+                check_fields(child, &Value::Null, root, path, problems)
+            }
+            None => problems.push(ConfigProblem::new(path.join("."), "unknown field")),
+        }
+
+        path.pop();
+    }
+}
+
+/// Fields changed between two configs, split by whether they apply without a
+/// restart.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfigChanges {
+    pub live: Vec<&'static str>,
+    pub restart: Vec<&'static str>,
+}
+
+impl ConfigChanges {
+    fn push(&mut self, changed: bool, field: &'static str, live: bool) {
+        match (changed, live) {
+            (true, true) => self.live.push(field),
+            (true, false) => self.restart.push(field),
+            (false, _) => {}
         }
     }
 }
 
 impl ConfigYAML {
+    /// Compare with the `new` config. The TLS files only reload live when TLS
+    /// stays enabled.
+    pub fn changes(&self, new: &Self) -> ConfigChanges {
+        let mut changes = ConfigChanges::default();
+        let tls = self.tls.enabled && new.tls.enabled;
+
+        changes.push(self.name != new.name, "name", false);
+        changes.push(self.ip != new.ip, "ip", false);
+        changes.push(self.port != new.port, "port", false);
+        changes.push(
+            self.shutdown_timeout != new.shutdown_timeout,
+            "shutdownTimeout",
+            false,
+        );
+        changes.push(
+            self.trusted_proxies != new.trusted_proxies,
+            "trustedProxies",
+            false,
+        );
+        changes.push(self.logs != new.logs, "logs", true);
+        changes.push(self.mongo_db.uri != new.mongo_db.uri, "mongoDb.uri", false);
+        changes.push(
+            self.mongo_db.db_name != new.mongo_db.db_name,
+            "mongoDb.dbName",
+            false,
+        );
+        changes.push(self.tls.enabled != new.tls.enabled, "tls.enabled", false);
+        changes.push(self.tls.certs != new.tls.certs, "tls.certs", tls);
+        changes.push(self.tls.key != new.tls.key, "tls.key", tls);
+        changes.push(self.tls.client_ca != new.tls.client_ca, "tls.clientCa", tls);
+        changes.push(
+            self.tls.client_auth_required != new.tls.client_auth_required,
+            "tls.clientAuthRequired",
+            tls,
+        );
+        changes.push(
+            self.tls.client_users != new.tls.client_users,
+            "tls.clientUsers",
+            false,
+        );
+        changes.push(
+            self.front.public_url != new.front.public_url,
+            "front.publicUrl",
+            false,
+        );
+        changes.push(
+            self.tracing.exporter != new.tracing.exporter,
+            "tracing.exporter",
+            false,
+        );
+        changes.push(
+            self.tracing.endpoint != new.tracing.endpoint,
+            "tracing.endpoint",
+            false,
+        );
+        changes.push(self.audit != new.audit, "audit", false);
+        changes.push(self.webhooks != new.webhooks, "webhooks", false);
+
+        changes
+    }
+
+    /// Problems of the values that parse but can not work.
+    async fn check(&self) -> Vec<ConfigProblem> {
+        let mut problems = vec![];
+
+        if self.name.trim().is_empty() {
+            problems.push(ConfigProblem::new("name", "must not be empty"));
+        }
+
+        if self.port == 0 {
+            problems.push(ConfigProblem::new("port", "must not be 0"));
+        }
+
+        if self.logs.directory.trim().is_empty() {
+            problems.push(ConfigProblem::new("logs.directory", "must not be empty"));
+        }
+
+        if self.logs.rotation.max_size_mb == 0 {
+            problems.push(ConfigProblem::new("logs.rotation.maxSizeMb", "must not be 0"));
+        }
+
+        if self.logs.rotation.archives == 0 {
+            problems.push(ConfigProblem::new("logs.rotation.archives", "must not be 0"));
+        }
+
+        let uri = &self.mongo_db.uri;
+
+        if uri.starts_with("mongodb://") {
+            if let Err(err) = ClientOptions::parse(uri).await {
+                problems.push(ConfigProblem::new("mongoDb.uri", err.to_string()));
+            }
+        } else if !uri.starts_with("mongodb+srv://") {
+            problems.push(ConfigProblem::new(
+                "mongoDb.uri",
+                "must start with mongodb:// or mongodb+srv://",
+            ));
+        }
+
+        let db_name = &self.mongo_db.db_name;
+
+        if db_name.is_empty() || db_name.contains(['/', '\\', '.', ' ', '"', '$']) {
+            problems.push(ConfigProblem::new(
+                "mongoDb.dbName",
+                "must be a valid MongoDB database name",
+            ));
+        }
+
+        if self.tls.enabled {
+            for (path, file) in [("tls.certs", &self.tls.certs), ("tls.key", &self.tls.key)] {
+                match file.is_empty() {
+                    true => problems.push(ConfigProblem::new(path, "required when TLS is enabled")),
+                    false if tokio::fs::metadata(file).await.is_err() => {
+                        problems.push(ConfigProblem::new(path, format!("{} does not exist", file)))
+                    }
+                    false => {}
+                }
+            }
+
+            let client_ca = &self.tls.client_ca;
+
+            if !client_ca.is_empty() && tokio::fs::metadata(client_ca).await.is_err() {
+                problems.push(ConfigProblem::new(
+                    "tls.clientCa",
+                    format!("{} does not exist", client_ca),
+                ));
+            }
+
+            if client_ca.is_empty() && self.tls.client_auth_required {
+                problems.push(ConfigProblem::new(
+                    "tls.clientAuthRequired",
+                    "requires tls.clientCa",
+                ));
+            }
+
+            for (common_name, username) in &self.tls.client_users {
+                if common_name.is_empty() || username.is_empty() {
+                    problems.push(ConfigProblem::new(
+                        "tls.clientUsers",
+                        "common names and usernames must not be empty",
+                    ));
+                }
+
+                if username == ADMIN_USERNAME {
+                    problems.push(ConfigProblem::new(
+                        "tls.clientUsers",
+                        format!(
+                            "{} must map to a service user, not {}",
+                            common_name, username
+                        ),
+                    ));
+                }
+            }
+        }
+
+        let public_url = &self.front.public_url;
+
+        if !public_url.is_empty() {
+            if !public_url.starts_with('/') {
+                problems.push(ConfigProblem::new("front.publicUrl", "must start with /"));
+            }
+
+            if public_url.ends_with('/') {
+                problems.push(ConfigProblem::new(
+                    "front.publicUrl",
+                    "must not end with /, leave it empty to serve from the root",
+                ));
+            }
+
+            if public_url.contains(|t: char| t.is_whitespace() || t == '?' || t == '#') {
+                problems.push(ConfigProblem::new(
+                    "front.publicUrl",
+                    "must be a plain path without spaces, query or fragment",
+                ));
+            }
+        }
+
+        let endpoint = &self.tracing.endpoint;
+
+        if self.tracing.exporter == Exporter::Otlp
+            && !endpoint.starts_with("http://")
+            && !endpoint.starts_with("https://")
+        {
+            problems.push(ConfigProblem::new(
+                "tracing.endpoint",
+                "must start with http:// or https://",
+            ));
+        }
+
+        if self.audit.file.enabled && self.audit.file.path.is_empty() {
+            problems.push(ConfigProblem::new("audit.file.path", "must not be empty"));
+        }
+
+        if self.audit.file.enabled && self.audit.file.rotation.max_size_mb == 0 {
+            problems.push(ConfigProblem::new(
+                "audit.file.rotation.maxSizeMb",
+                "must not be 0",
+            ));
+        }
+
+        let http = &self.audit.http;
+
+        if http.enabled && !http.url.starts_with("http://") && !http.url.starts_with("https://") {
+            problems.push(ConfigProblem::new(
+                "audit.http.url",
+                "must start with http:// or https://",
+            ));
+        }
+
+        if http.enabled && http.batch_size == 0 {
+            problems.push(ConfigProblem::new("audit.http.batchSize", "must not be 0"));
+        }
+
+        if http.enabled && http.flush_interval == 0 {
+            problems.push(ConfigProblem::new("audit.http.flushInterval", "must not be 0"));
+        }
+
+        if self.webhooks.max_attempts == 0 {
+            problems.push(ConfigProblem::new("webhooks.maxAttempts", "must not be 0"));
+        }
+
+        if self.webhooks.timeout == 0 {
+            problems.push(ConfigProblem::new("webhooks.timeout", "must not be 0"));
+        }
+
+        if self.webhooks.concurrency == 0 {
+            problems.push(ConfigProblem::new("webhooks.concurrency", "must not be 0"));
+        }
+
+        if self.webhooks.per_webhook == 0 {
+            problems.push(ConfigProblem::new("webhooks.perWebhook", "must not be 0"));
+        }
+
+        problems
+    }
+
+    /// Read and validate an existing config file.
+    pub async fn read_file<T: AsRef<Path>>(path: T) -> Result<Self> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|err| UsermanError::StdIoError(err.to_string()))?;
+        let value =
+            serde_yaml::from_str(&content).map_err(|err| UsermanError::YAMLFile(err.to_string()))?;
+
+        let vars: Vec<(String, String)> = std::env::vars().collect();
+
+        Self::from_value(value, vars.into_iter()).await
+    }
+
     pub async fn read_or_create_file<T: AsRef<Path> + Display>(path: T) -> Result<Self> {
-        match File::open(&path).await {
+        let value = match File::open(&path).await {
             Ok(mut reader) => {
                 let mut content = String::new();
                 reader
                     .read_to_string(&mut content)
                     .await
                     .map_err(|err| UsermanError::StdIoError(err.to_string()))?;
-                serde_yaml::from_str(&content).map_err(|err| UsermanError::YAMLFile(err.to_string()))
+                serde_yaml::from_str(&content).map_err(|err| UsermanError::YAMLFile(err.to_string()))?
             }
             Err(err) => {
                 debug!("{}", err);
                 info!("Missing {} file. Creating new..", path);
 
+                let default = Self::default();
+
                 let mut reader = File::create(path)
                     .await
                     .map_err(|err| UsermanError::StdIoError(err.to_string()))?;
-                let content: String = serde_yaml::to_string(&Self::default()).unwrap();
+                let content: String = serde_yaml::to_string(&default).unwrap();
                 reader
                     .write_all(content.as_bytes())
                     .await
                     .map_err(|err| UsermanError::StdIoError(err.to_string()))?;
 
-                Ok(Self::default())
+                serde_yaml::to_value(&default).unwrap()
             }
+        };
+
+        let vars: Vec<(String, String)> = std::env::vars().collect();
+
+        Self::from_value(value, vars.into_iter()).await
+    }
+
+    /// Build the config from the parsed file, overridden by `vars` and with
+    /// the `*File` fields read.
+    pub async fn from_value<I: Iterator<Item = (String, String)>>(
+        mut value: Value,
+        vars: I,
+    ) -> Result<Self> {
+        if value.is_null() {
+            value = Value::Mapping(Mapping::new());
+        }
+
+        let schema = serde_yaml::to_value(Self::default()).unwrap();
+
+        // Before the variables too, so `USERMAN_LOGS__FORMAT` keeps the level.
+        legacy_logs(&mut value);
+        apply_env(&mut value, &schema, vars);
+        legacy_logs(&mut value);
+        read_secret_files(&mut value, &schema).await?;
+
+        let mut problems = vec![];
+        check_fields(&value, &schema, &schema, &mut vec![], &mut problems);
+
+        if !problems.is_empty() {
+            return Err(UsermanError::InvalidConfig(ConfigReport(problems)));
+        }
+
+        let config: Self =
+            serde_yaml::from_value(value).map_err(|err| UsermanError::YAMLFile(err.to_string()))?;
+
+        match config.check().await {
+            t if t.is_empty() => Ok(config),
+            t => Err(UsermanError::InvalidConfig(ConfigReport(t))),
         }
     }
 
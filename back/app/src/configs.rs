@@ -42,7 +42,7 @@ pub enum ConfigData {
     Token(TokenConfig),
 }
 
-#[derive(Clone, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
     #[serde(rename = "_id")]
@@ -91,4 +91,8 @@ impl Memory<ConfigData, String> for Configs {
     async fn get(&self, id: &String) -> Option<ConfigData> {
         self.0.read().await.get(id).cloned()
     }
+
+    async fn get_all(&self) -> Vec<ConfigData> {
+        self.0.read().await.values().cloned().collect()
+    }
 }
@@ -2,31 +2,59 @@ use async_trait::async_trait;
 use bcrypt::{hash, DEFAULT_COST};
 use futures::stream::StreamExt;
 use futures::stream::TryStreamExt;
+use log::warn;
 use mongodb::bson::oid::ObjectId;
-use mongodb::bson::{self, doc};
+use mongodb::bson::{self, doc, DateTime, Document};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::FindOneAndUpdateOptions;
 use mongodb::options::FindOptions;
 use mongodb::options::IndexOptions;
-use mongodb::Database;
+use mongodb::options::ReplaceOptions;
+use mongodb::options::{GridFsBucketOptions, GridFsUploadOptions};
+use mongodb::{Database, GridFsBucket};
 use mongodb::IndexModel;
 use std::collections::HashMap;
+use tracing::instrument;
 use tokio::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
 
 use crate::apps::AppDB;
+use crate::audit::{AuditEvent, AuditEventDB};
+use crate::avatars::Avatar;
 use crate::configs::{Config, ConfigData, TOKEN_CONFIG};
+use crate::manifest::Managed;
 use crate::roles::RoleDB;
 use crate::tokens::{RefreshToken, SessionToken};
-use crate::users::{User, ADMIN_USERNAME};
-use crate::watchers::Event;
+use crate::users::{self, User, ADMIN_USERNAME};
+use crate::watchers::{Change, Event};
+use crate::webhooks::{Delivery, DeliveryDB, DeliveryStatus, Webhook, WebhookDB, WebhookEvent};
 use crate::{Result, UsermanError};
 
 use userman_auth::apps::{App, LOCAL_APP};
-use userman_auth::roles::{Role, LOCAL_ROLE};
+use userman_auth::roles::{
+    DataValue, Item, Role, RoleItems, RoleValues, Value, LOCAL_ROLE,
+};
 
 const ROLES: &str = "roles";
 const CONFIGS: &str = "configs";
 const TOKENS: &str = "tokens";
 const USERS: &str = "users";
 const APPS: &str = "apps";
+const AVATARS: &str = "avatars";
+const MANAGED: &str = "managed";
+const AUDIT: &str = "audit";
+const WEBHOOKS: &str = "webhooks";
+const DELIVERIES: &str = "deliveries";
+/// MongoDB error code of a unique index violation.
+const DUPLICATE_KEY: i32 = 11000;
+/// Permissions added after the local app, granted to its default role.
+const GRANTED: [(&str, &[&str]); 5] = [
+    ("logs", &["update"]),
+    ("audit", &["read"]),
+    ("webhooks", &["read", "update"]),
+    ("streams", &["read"]),
+    ("secrets", &["read", "update"]),
+];
 
 #[async_trait]
 pub trait Memory<T, I = String> {
@@ -58,8 +86,18 @@ impl Dao {
         Self { database }
     }
 
+    #[instrument(skip_all)]
+    pub async fn ping(&self) -> Result<()> {
+        self.database
+            .run_command(doc! { "ping": 1 }, None)
+            .await
+            .map(|_| ())
+            .map_err(UsermanError::MongoRunCommand)
+    }
+
     /* CONFIGS */
 
+    #[instrument(skip_all)]
     pub async fn create_config(&self, config: &Config) -> Result<Option<ObjectId>> {
         self.database
             .collection::<Config>(CONFIGS)
@@ -69,6 +107,7 @@ impl Dao {
             .map_err(UsermanError::MongoInsertOne)
     }
 
+    #[instrument(skip_all)]
     pub async fn read_config(&self, id: &str) -> Result<Option<Config>> {
         self.database
             .collection::<Config>(CONFIGS)
@@ -80,6 +119,27 @@ impl Dao {
             .map_err(UsermanError::MongoFindOne)
     }
 
+    #[instrument(skip_all)]
+    pub async fn update_config(&self, config: &Config) -> Result<()> {
+        self.database
+            .collection::<Config>(CONFIGS)
+            .replace_one(doc! { "_id": config.id() }, config, None)
+            .await
+            .map(|_| ())
+            .map_err(UsermanError::MongoUpdateOne)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn delete_config(&self, id: &str) -> Result<()> {
+        self.database
+            .collection::<Config>(CONFIGS)
+            .delete_one(doc! { "_id": id }, None)
+            .await
+            .map(|_| ())
+            .map_err(UsermanError::MongoDeleteOne)
+    }
+
+    #[instrument(skip_all)]
     pub async fn read_all_configs(&self) -> Result<HashMap<String, ConfigData>> {
         let mut cursor = self
             .database
@@ -97,7 +157,11 @@ impl Dao {
         Ok(configs)
     }
 
-    pub async fn watch_configs(&self, tx: &Sender<Event>) -> Result<()> {
+    pub async fn watch_configs(
+        &self,
+        tx: &Sender<Change>,
+        token: &CancellationToken,
+    ) -> Result<()> {
         let mut change_stream = self
             .database
             .collection::<Config>(CONFIGS)
@@ -105,8 +169,17 @@ impl Dao {
             .await
             .map_err(UsermanError::MongoWatchChangeStream)?;
 
-        while let Some(Ok(_)) = change_stream.next().await {
-            _ = tx.send(Event::Configs).await;
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                t = change_stream.next() => match t {
+                    Some(Ok(t)) => {
+                        _ = tx.send(Change::new(Event::Configs, &t)).await;
+                    }
+                    Some(Err(err)) => return Err(UsermanError::MongoWatchChangeStream(err)),
+                    None => break,
+                },
+            }
         }
 
         Ok(())
@@ -114,6 +187,7 @@ impl Dao {
 
     /* USERS */
 
+    #[instrument(skip_all)]
     pub async fn create_user(&self, user: &User) -> Result<Option<ObjectId>> {
         self.database
             .collection(USERS)
@@ -123,6 +197,7 @@ impl Dao {
             .map_err(UsermanError::MongoInsertOne)
     }
 
+    #[instrument(skip_all)]
     pub async fn read_user_by_username(&self, username: &str) -> Result<Option<User>> {
         self.database
             .collection::<User>(USERS)
@@ -131,6 +206,7 @@ impl Dao {
             .map_err(UsermanError::MongoFindOne)
     }
 
+    #[instrument(skip_all)]
     pub async fn update_user_by_id(&self, id: impl AsRef<str>, user: &User) -> Result<()> {
         let _id = ObjectId::parse_str(id).map_err(UsermanError::ParseObjectId)?;
 
@@ -146,11 +222,27 @@ impl Dao {
             .map_err(UsermanError::MongoUpdateOne)
     }
 
+    /// Set only `fields` on a user, the others are left as they are.
+    #[instrument(skip_all)]
+    pub async fn update_user_fields(&self, id: &ObjectId, mut fields: Document) -> Result<()> {
+        fields.insert("updatedAt", DateTime::now());
+
+        self.database
+            .collection::<User>(USERS)
+            .update_one(doc! { "_id": id }, doc! { "$set": fields }, None)
+            .await
+            .map(|_| ())
+            .map_err(UsermanError::MongoUpdateOne)
+    }
+
+    /// Set the first password of a user, returns the number of users modified,
+    /// 0 when it is missing or has one already.
+    #[instrument(skip_all)]
     pub async fn update_user_password_by_id(
         &self,
         id: impl AsRef<str>,
         password: &str,
-    ) -> Result<()> {
+    ) -> Result<u64> {
         let _id = ObjectId::parse_str(id).map_err(UsermanError::ParseObjectId)?;
 
         self.database
@@ -164,10 +256,36 @@ impl Dao {
                 None,
             )
             .await
+            .map(|t| t.modified_count)
+            .map_err(UsermanError::MongoUpdateOne)
+    }
+
+    /// Replace the password of a user, whether it has one or not. With
+    /// `change` the user has to choose a new one on next login.
+    #[instrument(skip_all)]
+    pub async fn set_user_password_by_id(
+        &self,
+        id: &ObjectId,
+        password: &str,
+        change: bool,
+    ) -> Result<()> {
+        self.database
+            .collection::<User>(USERS)
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set" : {
+                    "password": hash(password, DEFAULT_COST).unwrap(),
+                    "changePassword": change,
+                    "updatedAt": DateTime::now(),
+                } },
+                None,
+            )
+            .await
             .map(|_| ())
             .map_err(UsermanError::MongoUpdateOne)
     }
 
+    #[instrument(skip_all)]
     pub async fn reset_user_password_by_id(&self, id: impl AsRef<str>) -> Result<()> {
         let _id = ObjectId::parse_str(id).map_err(UsermanError::ParseObjectId)?;
 
@@ -183,6 +301,21 @@ impl Dao {
             .map_err(UsermanError::MongoUpdateOne)
     }
 
+    #[instrument(skip_all)]
+    pub async fn update_user_avatar_by_id(&self, id: &ObjectId, avatar: &str) -> Result<()> {
+        self.database
+            .collection::<User>(USERS)
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "avatar": avatar, "updatedAt": DateTime::now() } },
+                None,
+            )
+            .await
+            .map(|_| ())
+            .map_err(UsermanError::MongoUpdateOne)
+    }
+
+    #[instrument(skip_all)]
     pub async fn delete_user_by_id(&self, id: impl AsRef<str>) -> Result<()> {
         let _id = ObjectId::parse_str(id).map_err(UsermanError::ParseObjectId)?;
 
@@ -190,10 +323,12 @@ impl Dao {
             .collection::<User>(USERS)
             .delete_one(doc! { "_id": _id }, None)
             .await
-            .map(|_| ())
-            .map_err(UsermanError::MongoDeleteOne)
+            .map_err(UsermanError::MongoDeleteOne)?;
+
+        self.delete_avatars(&_id, None).await
     }
 
+    #[instrument(skip_all)]
     pub async fn read_all_users(&self) -> Result<(HashMap<ObjectId, User>, HashMap<String, User>)> {
         let mut cursor = self
             .database
@@ -216,7 +351,11 @@ impl Dao {
         Ok((users, users_by_username))
     }
 
-    pub async fn watch_users(&self, tx: &Sender<Event>) -> Result<()> {
+    pub async fn watch_users(
+        &self,
+        tx: &Sender<Change>,
+        token: &CancellationToken,
+    ) -> Result<()> {
         let mut change_stream = self
             .database
             .collection::<User>(USERS)
@@ -224,8 +363,119 @@ impl Dao {
             .await
             .map_err(UsermanError::MongoWatchChangeStream)?;
 
-        while let Some(Ok(_)) = change_stream.next().await {
-            _ = tx.send(Event::Users).await;
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                t = change_stream.next() => match t {
+                    Some(Ok(t)) => {
+                        _ = tx.send(Change::new(Event::Users, &t)).await;
+                    }
+                    Some(Err(err)) => return Err(UsermanError::MongoWatchChangeStream(err)),
+                    None => break,
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    /* AVATARS */
+
+    fn avatars(&self) -> GridFsBucket {
+        self.database.gridfs_bucket(
+            GridFsBucketOptions::builder()
+                .bucket_name(AVATARS.to_string())
+                .build(),
+        )
+    }
+
+    #[instrument(skip_all)]
+    pub async fn create_avatars(
+        &self,
+        user_id: &ObjectId,
+        version: &str,
+        avatars: &[Avatar],
+    ) -> Result<()> {
+        let bucket = self.avatars();
+
+        for avatar in avatars {
+            let options = GridFsUploadOptions::builder()
+                .metadata(doc! {
+                    "user": user_id,
+                    "version": version,
+                    "size": avatar.size,
+                })
+                .build();
+
+            bucket
+                .upload_from_futures_0_3_reader(
+                    format!("{}-{}.png", user_id, avatar.size),
+                    &avatar.data[..],
+                    options,
+                )
+                .await
+                .map_err(UsermanError::MongoGridFsUpload)?;
+        }
+
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    pub async fn read_avatar(
+        &self,
+        user_id: &ObjectId,
+        version: &str,
+        size: u32,
+    ) -> Result<Option<Vec<u8>>> {
+        let bucket = self.avatars();
+
+        let mut cursor = bucket
+            .find(
+                doc! {
+                    "metadata.user": user_id,
+                    "metadata.version": version,
+                    "metadata.size": size,
+                },
+                None,
+            )
+            .await
+            .map_err(UsermanError::MongoFind)?;
+
+        match cursor.try_next().await.map_err(UsermanError::MongoReadCursor)? {
+            Some(file) => {
+                let mut data = vec![];
+
+                bucket
+                    .download_to_futures_0_3_writer(file.id, &mut data)
+                    .await
+                    .map_err(UsermanError::MongoGridFsDownload)?;
+
+                Ok(Some(data))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Delete every stored avatar of a user except the ones of the `keep` version.
+    #[instrument(skip_all)]
+    pub async fn delete_avatars(&self, user_id: &ObjectId, keep: Option<&str>) -> Result<()> {
+        let bucket = self.avatars();
+
+        let filter = match keep {
+            Some(t) => doc! { "metadata.user": user_id, "metadata.version": { "$ne": t } },
+            None => doc! { "metadata.user": user_id },
+        };
+
+        let mut cursor = bucket
+            .find(filter, None)
+            .await
+            .map_err(UsermanError::MongoFind)?;
+
+        while let Some(file) = cursor.try_next().await.map_err(UsermanError::MongoReadCursor)? {
+            bucket
+                .delete(file.id)
+                .await
+                .map_err(UsermanError::MongoGridFsDelete)?;
         }
 
         Ok(())
@@ -233,6 +483,7 @@ impl Dao {
 
     /* ROLES */
 
+    #[instrument(skip_all)]
     pub async fn create_role(&self, role: &Role) -> Result<Option<ObjectId>> {
         self.database
             .collection(ROLES)
@@ -242,6 +493,7 @@ impl Dao {
             .map_err(UsermanError::MongoInsertOne)
     }
 
+    #[instrument(skip_all)]
     pub async fn read_role_by_name(&self, name: &str) -> Result<Option<Role>> {
         self.database
             .collection(ROLES)
@@ -250,6 +502,7 @@ impl Dao {
             .map_err(UsermanError::MongoFindOne)
     }
 
+    #[instrument(skip_all)]
     pub async fn update_role_by_id(&self, id: impl AsRef<str>, role: &Role) -> Result<()> {
         let _id = ObjectId::parse_str(id).map_err(UsermanError::ParseObjectId)?;
 
@@ -265,6 +518,7 @@ impl Dao {
             .map_err(UsermanError::MongoUpdateOne)
     }
 
+    #[instrument(skip_all)]
     pub async fn delete_role_by_id(&self, id: impl AsRef<str>) -> Result<()> {
         let _id = ObjectId::parse_str(id).map_err(UsermanError::ParseObjectId)?;
 
@@ -276,6 +530,7 @@ impl Dao {
             .map_err(UsermanError::MongoDeleteOne)
     }
 
+    #[instrument(skip_all)]
     pub async fn read_all_roles(&self) -> Result<(HashMap<ObjectId, Role>, HashMap<String, Role>)> {
         let mut cursor = self
             .database
@@ -295,7 +550,11 @@ impl Dao {
         Ok((roles, roles_by_name))
     }
 
-    pub async fn watch_roles(&self, tx: &Sender<Event>) -> Result<()> {
+    pub async fn watch_roles(
+        &self,
+        tx: &Sender<Change>,
+        token: &CancellationToken,
+    ) -> Result<()> {
         let mut change_stream = self
             .database
             .collection::<Role>(ROLES)
@@ -303,8 +562,17 @@ impl Dao {
             .await
             .map_err(UsermanError::MongoWatchChangeStream)?;
 
-        while let Some(Ok(_)) = change_stream.next().await {
-            _ = tx.send(Event::Roles).await;
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                t = change_stream.next() => match t {
+                    Some(Ok(t)) => {
+                        _ = tx.send(Change::new(Event::Roles, &t)).await;
+                    }
+                    Some(Err(err)) => return Err(UsermanError::MongoWatchChangeStream(err)),
+                    None => break,
+                },
+            }
         }
 
         Ok(())
@@ -312,6 +580,7 @@ impl Dao {
 
     /* APPS */
 
+    #[instrument(skip_all)]
     pub async fn create_app(&self, app: &App) -> Result<Option<ObjectId>> {
         self.database
             .collection(APPS)
@@ -321,6 +590,7 @@ impl Dao {
             .map_err(UsermanError::MongoInsertOne)
     }
 
+    #[instrument(skip_all)]
     pub async fn read_app_by_name(&self, name: &str) -> Result<Option<App>> {
         self.database
             .collection(APPS)
@@ -329,6 +599,7 @@ impl Dao {
             .map_err(UsermanError::MongoFindOne)
     }
 
+    #[instrument(skip_all)]
     pub async fn read_all_apps(&self) -> Result<HashMap<ObjectId, App>> {
         let mut cursor = self
             .database
@@ -346,6 +617,7 @@ impl Dao {
         Ok(apps)
     }
 
+    #[instrument(skip_all)]
     pub async fn update_app_by_id(&self, id: impl AsRef<str>, app: &App) -> Result<()> {
         let _id = ObjectId::parse_str(id).map_err(UsermanError::ParseObjectId)?;
 
@@ -361,6 +633,7 @@ impl Dao {
             .map_err(UsermanError::MongoUpdateOne)
     }
 
+    #[instrument(skip_all)]
     pub async fn delete_app_by_id(&self, id: impl AsRef<str>) -> Result<()> {
         let _id = ObjectId::parse_str(id).map_err(UsermanError::ParseObjectId)?;
 
@@ -372,7 +645,11 @@ impl Dao {
             .map_err(UsermanError::MongoDeleteOne)
     }
 
-    pub async fn watch_apps(&self, tx: &Sender<Event>) -> Result<()> {
+    pub async fn watch_apps(
+        &self,
+        tx: &Sender<Change>,
+        token: &CancellationToken,
+    ) -> Result<()> {
         let mut change_stream = self
             .database
             .collection::<App>(APPS)
@@ -380,8 +657,17 @@ impl Dao {
             .await
             .map_err(UsermanError::MongoWatchChangeStream)?;
 
-        while let Some(Ok(_)) = change_stream.next().await {
-            _ = tx.send(Event::Apps).await;
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                t = change_stream.next() => match t {
+                    Some(Ok(t)) => {
+                        _ = tx.send(Change::new(Event::Apps, &t)).await;
+                    }
+                    Some(Err(err)) => return Err(UsermanError::MongoWatchChangeStream(err)),
+                    None => break,
+                },
+            }
         }
 
         Ok(())
@@ -389,6 +675,7 @@ impl Dao {
 
     /* TOKENS */
 
+    #[instrument(skip_all)]
     pub async fn create_refresh_token(&self, refresh_token: &RefreshToken) -> Result<()> {
         self.database
             .collection::<RefreshToken>(TOKENS)
@@ -398,6 +685,7 @@ impl Dao {
             .map_err(UsermanError::MongoInsertOne)
     }
 
+    #[instrument(skip_all)]
     pub async fn read_refresh_token(
         &self,
         refresh_token: &str,
@@ -410,6 +698,7 @@ impl Dao {
             .map_err(UsermanError::MongoFindOne)
     }
 
+    #[instrument(skip_all)]
     pub async fn delete_refresh_token(
         &self,
         refresh_token: &str,
@@ -422,8 +711,335 @@ impl Dao {
             .map_err(UsermanError::MongoDeleteOne)
     }
 
+    #[instrument(skip_all)]
+    pub async fn count_refresh_tokens(&self) -> Result<u64> {
+        self.database
+            .collection::<RefreshToken>(TOKENS)
+            .count_documents(None, None)
+            .await
+            .map_err(UsermanError::MongoCountDocuments)
+    }
+
+    /* MANAGED */
+
+    #[instrument(skip_all)]
+    pub async fn set_managed(&self, managed: &Managed) -> Result<()> {
+        self.database
+            .collection::<Managed>(MANAGED)
+            .replace_one(
+                doc! { "_id": managed.id },
+                managed,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await
+            .map(|_| ())
+            .map_err(UsermanError::MongoUpdateOne)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn read_all_managed(&self) -> Result<Vec<Managed>> {
+        self.database
+            .collection::<Managed>(MANAGED)
+            .find(doc! {}, None)
+            .await
+            .map_err(UsermanError::MongoFind)?
+            .try_collect()
+            .await
+            .map_err(UsermanError::MongoReadCursor)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn delete_managed(&self, id: &ObjectId) -> Result<()> {
+        self.database
+            .collection::<Managed>(MANAGED)
+            .delete_one(doc! { "_id": id }, None)
+            .await
+            .map(|_| ())
+            .map_err(UsermanError::MongoDeleteOne)
+    }
+
+    /* AUDIT */
+
+    #[instrument(skip_all)]
+    pub async fn create_audit_event(&self, event: &AuditEvent) -> Result<Option<ObjectId>> {
+        self.database
+            .collection(AUDIT)
+            .insert_one(AuditEventDB::from(event), None)
+            .await
+            .map(|t| t.inserted_id.as_object_id())
+            .map_err(UsermanError::MongoInsertOne)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn count_audit_events(&self, filter: Document) -> Result<u64> {
+        self.database
+            .collection::<AuditEvent>(AUDIT)
+            .count_documents(filter, None)
+            .await
+            .map_err(UsermanError::MongoCountDocuments)
+    }
+
+    /// Newest events first.
+    #[instrument(skip_all)]
+    pub async fn read_audit_events(
+        &self,
+        filter: Document,
+        skip: u64,
+        limit: i64,
+    ) -> Result<Vec<AuditEvent>> {
+        self.database
+            .collection::<AuditEvent>(AUDIT)
+            .find(
+                filter,
+                Some(
+                    FindOptions::builder()
+                        .sort(doc! { "_id": -1 })
+                        .skip(skip)
+                        .limit(limit)
+                        .build(),
+                ),
+            )
+            .await
+            .map_err(UsermanError::MongoFind)?
+            .try_collect()
+            .await
+            .map_err(UsermanError::MongoReadCursor)
+    }
+
+    /* WEBHOOKS */
+
+    #[instrument(skip_all)]
+    pub async fn create_webhook(&self, webhook: &Webhook) -> Result<Option<ObjectId>> {
+        self.database
+            .collection(WEBHOOKS)
+            .insert_one(WebhookDB::from(webhook), None)
+            .await
+            .map(|t| t.inserted_id.as_object_id())
+            .map_err(UsermanError::MongoInsertOne)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn read_webhook_by_id(&self, id: &ObjectId) -> Result<Option<Webhook>> {
+        self.database
+            .collection::<Webhook>(WEBHOOKS)
+            .find_one(doc! { "_id": id }, None)
+            .await
+            .map_err(UsermanError::MongoFindOne)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn read_all_webhooks(&self) -> Result<Vec<Webhook>> {
+        self.database
+            .collection::<Webhook>(WEBHOOKS)
+            .find(doc! {}, None)
+            .await
+            .map_err(UsermanError::MongoFind)?
+            .try_collect()
+            .await
+            .map_err(UsermanError::MongoReadCursor)
+    }
+
+    /// Enabled webhooks subscribed to `event`.
+    #[instrument(skip_all)]
+    pub async fn read_webhooks_by_event(&self, event: WebhookEvent) -> Result<Vec<Webhook>> {
+        self.database
+            .collection::<Webhook>(WEBHOOKS)
+            .find(doc! { "enabled": true, "events": event.as_str() }, None)
+            .await
+            .map_err(UsermanError::MongoFind)?
+            .try_collect()
+            .await
+            .map_err(UsermanError::MongoReadCursor)
+    }
+
+    /// Update a webhook, its secret is kept when `webhook` has none.
+    #[instrument(skip_all)]
+    pub async fn update_webhook_by_id(&self, id: impl AsRef<str>, webhook: &Webhook) -> Result<()> {
+        let _id = ObjectId::parse_str(id).map_err(UsermanError::ParseObjectId)?;
+        let events: Vec<&str> = webhook.events.iter().map(|t| t.as_str()).collect();
+
+        let mut set = doc! {
+            "url": &webhook.url,
+            "events": events,
+            "enabled": webhook.enabled,
+            "description": &webhook.description,
+            "updatedAt": DateTime::now(),
+        };
+
+        if let Some(t) = &webhook.secret {
+            set.insert("secret", t);
+        }
+
+        let result = self
+            .database
+            .collection::<Webhook>(WEBHOOKS)
+            .update_one(doc! { "_id": _id }, doc! { "$set": set }, None)
+            .await
+            .map_err(UsermanError::MongoUpdateOne)?;
+
+        match result.matched_count {
+            0 => Err(UsermanError::WebhookNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn delete_webhook_by_id(&self, id: impl AsRef<str>) -> Result<()> {
+        let _id = ObjectId::parse_str(id).map_err(UsermanError::ParseObjectId)?;
+
+        self.database
+            .collection::<Webhook>(WEBHOOKS)
+            .delete_one(doc! { "_id": _id }, None)
+            .await
+            .map(|_| ())
+            .map_err(UsermanError::MongoDeleteOne)
+    }
+
+    /// Queue a delivery, returns `false` when its key was already queued.
+    #[instrument(skip_all)]
+    pub async fn create_delivery(&self, delivery: &DeliveryDB<'_>) -> Result<bool> {
+        let result = self
+            .database
+            .collection::<DeliveryDB>(DELIVERIES)
+            .insert_one(delivery, None)
+            .await;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(err) => match *err.kind {
+                ErrorKind::Write(WriteFailure::WriteError(ref t)) if t.code == DUPLICATE_KEY => {
+                    Ok(false)
+                }
+                _ => Err(UsermanError::MongoInsertOne(err)),
+            },
+        }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn count_deliveries(&self, filter: Document) -> Result<u64> {
+        self.database
+            .collection::<Delivery>(DELIVERIES)
+            .count_documents(filter, None)
+            .await
+            .map_err(UsermanError::MongoCountDocuments)
+    }
+
+    /// Newest deliveries first.
+    #[instrument(skip_all)]
+    pub async fn read_deliveries(
+        &self,
+        filter: Document,
+        skip: u64,
+        limit: i64,
+    ) -> Result<Vec<Delivery>> {
+        self.database
+            .collection::<Delivery>(DELIVERIES)
+            .find(
+                filter,
+                Some(
+                    FindOptions::builder()
+                        .sort(doc! { "_id": -1 })
+                        .skip(skip)
+                        .limit(limit)
+                        .build(),
+                ),
+            )
+            .await
+            .map_err(UsermanError::MongoFind)?
+            .try_collect()
+            .await
+            .map_err(UsermanError::MongoReadCursor)
+    }
+
+    /// Take the oldest due delivery, pushing its next attempt `lease` later so
+    /// no other instance takes it meanwhile. Deliveries of the `busy` webhooks
+    /// are left for later.
+    #[instrument(skip_all)]
+    pub async fn claim_delivery(
+        &self,
+        lease: std::time::Duration,
+        busy: &[ObjectId],
+    ) -> Result<Option<Delivery>> {
+        let now = DateTime::now();
+        let until = DateTime::from_millis(now.timestamp_millis() + lease.as_millis() as i64);
+
+        self.database
+            .collection::<Delivery>(DELIVERIES)
+            .find_one_and_update(
+                doc! {
+                    "status": DeliveryStatus::Pending.as_str(),
+                    "nextAttemptAt": { "$lte": now },
+                    "webhookId": { "$nin": busy },
+                },
+                doc! { "$set": { "nextAttemptAt": until } },
+                Some(
+                    FindOneAndUpdateOptions::builder()
+                        .sort(doc! { "nextAttemptAt": 1 })
+                        .build(),
+                ),
+            )
+            .await
+            .map_err(UsermanError::MongoUpdateOne)
+    }
+
+    /// Store the outcome of an attempt.
+    #[instrument(skip_all)]
+    pub async fn update_delivery(&self, delivery: &Delivery) -> Result<()> {
+        self.database
+            .collection::<Delivery>(DELIVERIES)
+            .update_one(
+                doc! { "_id": delivery.id },
+                doc! {
+                    "$set": {
+                        "status": delivery.status.as_str(),
+                        "attempts": delivery.attempts,
+                        "responseStatus": delivery.response_status.map(i32::from),
+                        "error": &delivery.error,
+                        "nextAttemptAt": delivery.next_attempt_at,
+                        "updatedAt": delivery.updated_at,
+                    }
+                },
+                None,
+            )
+            .await
+            .map(|_| ())
+            .map_err(UsermanError::MongoUpdateOne)
+    }
+
+    /// Queue a delivery of `webhook_id` again with every attempt left.
+    #[instrument(skip_all)]
+    pub async fn redeliver(&self, webhook_id: &ObjectId, id: &ObjectId) -> Result<()> {
+        let now = DateTime::now();
+
+        let result = self
+            .database
+            .collection::<Delivery>(DELIVERIES)
+            .update_one(
+                doc! { "_id": id, "webhookId": webhook_id },
+                doc! {
+                    "$set": {
+                        "status": DeliveryStatus::Pending.as_str(),
+                        "attempts": 0,
+                        "responseStatus": null,
+                        "error": null,
+                        "nextAttemptAt": now,
+                        "updatedAt": now,
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(UsermanError::MongoUpdateOne)?;
+
+        match result.matched_count {
+            0 => Err(UsermanError::DeliveryNotFound),
+            _ => Ok(()),
+        }
+    }
+
     /* INIT */
 
+    #[instrument(skip_all)]
     pub async fn init(&self) -> Result<()> {
         // Create web config.
         if self.read_config(TOKEN_CONFIG).await?.is_none() {
@@ -499,6 +1115,50 @@ impl Dao {
             .await
             .map_err(UsermanError::MongoCreateIndex)?;
 
+        // Create audit indexes.
+        for keys in [
+            doc! { "createdAt": -1 },
+            doc! { "actor": 1 },
+            doc! { "action": 1 },
+            doc! { "target.id": 1 },
+        ] {
+            self.database
+                .collection::<AuditEvent>(AUDIT)
+                .create_index(IndexModel::builder().keys(keys).build(), None)
+                .await
+                .map_err(UsermanError::MongoCreateIndex)?;
+        }
+
+        // Create webhooks indexes.
+        self.database
+            .collection::<Webhook>(WEBHOOKS)
+            .create_index(IndexModel::builder().keys(doc! { "events": 1 }).build(), None)
+            .await
+            .map_err(UsermanError::MongoCreateIndex)?;
+
+        for keys in [
+            doc! { "status": 1, "nextAttemptAt": 1 },
+            doc! { "webhookId": 1, "_id": -1 },
+        ] {
+            self.database
+                .collection::<Delivery>(DELIVERIES)
+                .create_index(IndexModel::builder().keys(keys).build(), None)
+                .await
+                .map_err(UsermanError::MongoCreateIndex)?;
+        }
+
+        self.database
+            .collection::<Delivery>(DELIVERIES)
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "webhookId": 1, "key": 1 })
+                    .options(Some(IndexOptions::builder().unique(true).build()))
+                    .build(),
+                None,
+            )
+            .await
+            .map_err(UsermanError::MongoCreateIndex)?;
+
         // Create local app.
         if self.read_app_by_name(LOCAL_APP).await?.is_none() {
             let app = App::default();
@@ -519,11 +1179,23 @@ impl Dao {
                 }
             }
 
-            // Create admin user.
+            // Create admin user. Its password has to be changed on first login.
             if let Some(t) = role_id {
                 if self.read_user_by_username(ADMIN_USERNAME).await?.is_none() {
+                    let password = match users::admin_password().await? {
+                        Some(t) => t,
+                        None => {
+                            let password = users::random_password();
+                            warn!("Generated {} password, it is only shown once.", ADMIN_USERNAME);
+                            println!("Password of {}: {}", ADMIN_USERNAME, password);
+                            password
+                        }
+                    };
+
                     let user = User {
+                        password: Some(password),
                         roles: vec![t],
+                        change_password: true,
                         ..Default::default()
                     };
 
@@ -532,6 +1204,66 @@ impl Dao {
             }
         }
 
+        for (item, values) in GRANTED {
+            self.grant(item, values).await?;
+        }
+
         Ok(())
     }
+
+    /// Add a permission to the local app and role created before it existed.
+    async fn grant(&self, item: &str, values: &[&str]) -> Result<()> {
+        let mut app = match self.read_app_by_name(LOCAL_APP).await? {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+
+        if app.default_role.find(item).is_some() {
+            return Ok(());
+        }
+
+        app.default_role = with_item(&app.default_role, item, values)?;
+
+        if let Some(t) = &app.id {
+            self.update_app_by_id(t.to_hex(), &app).await?;
+        }
+
+        if let Some(mut role) = self.read_role_by_name(LOCAL_ROLE).await? {
+            if role.items.find(item).is_none() {
+                role.items = with_item(&role.items, item, values)?;
+
+                if let Some(t) = &role.id {
+                    self.update_role_by_id(t.to_hex(), &role).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// `items` with an `item` granting the boolean `values`.
+pub(crate) fn with_item(items: &RoleItems, item: &str, values: &[&str]) -> Result<RoleItems> {
+    let item = Item {
+        name: item.to_string(),
+        values: RoleValues(
+            values
+                .iter()
+                .map(|t| Value {
+                    name: t.to_string(),
+                    data: DataValue::Boolean(true),
+                    options: None,
+                })
+                .collect(),
+        ),
+        items: RoleItems::new(vec![]),
+    };
+
+    let mut value = serde_json::to_value(items).map_err(UsermanError::CreateJSON)?;
+
+    if let Some(t) = value.as_array_mut() {
+        t.push(serde_json::to_value(item).map_err(UsermanError::CreateJSON)?);
+    }
+
+    serde_json::from_value(value).map_err(UsermanError::CreateJSON)
 }
@@ -8,6 +8,9 @@ use thiserror::Error;
 
 use userman_auth::AuthError;
 
+use crate::access;
+use crate::config_yaml::ConfigReport;
+
 #[derive(Debug, Error)]
 pub enum UsermanError {
     #[error("Can't create SIGINT stream. {0}")]
@@ -18,12 +21,32 @@ pub enum UsermanError {
     InterruptBlocking,
     #[error("Error blocking main thread with SIGTERM.")]
     TerminateBlocking,
+    #[error("Can't create SIGHUP stream. {0}")]
+    SignalHangup(std::io::Error),
+    #[error("Error waiting for SIGHUP.")]
+    HangupBlocking,
+    #[error("A task stopped before the shutdown signal.")]
+    TaskStopped,
     #[error("{0}")]
     StdIoError(String),
     #[error("Error parsing YAML file. {0}")]
     YAMLFile(String),
+    #[error("Invalid configuration.\n{0}")]
+    InvalidConfig(ConfigReport),
+    #[error("Error parsing import file. {0}")]
+    ImportFile(String),
+    #[error("Error creating export file. {0}")]
+    ExportFile(String),
+    #[error("Error restoring archive. {0}")]
+    RestoreArchive(String),
+    #[error("Invalid manifest. {0}")]
+    Manifest(String),
     #[error("Error reading PEM file. {0}")]
     PEMFile(String),
+    #[error("Error configuring the logger. {0}")]
+    Logger(String),
+    #[error("Error starting the tracing exporter. {0}")]
+    Tracing(String),
     #[error("Web server error. {0}")]
     WebServer(String),
     #[error("Could not parse MongoDB URI. {0}")]
@@ -46,8 +69,18 @@ pub enum UsermanError {
     MongoUpdateOne(mongodb::error::Error),
     #[error("MongoDB delete one API error. {0}")]
     MongoDeleteOne(mongodb::error::Error),
+    #[error("MongoDB run command API error. {0}")]
+    MongoRunCommand(mongodb::error::Error),
+    #[error("MongoDB count documents API error. {0}")]
+    MongoCountDocuments(mongodb::error::Error),
     #[error("MongoDB create index API error. {0}")]
     MongoCreateIndex(mongodb::error::Error),
+    #[error("MongoDB GridFS upload error. {0}")]
+    MongoGridFsUpload(mongodb::error::Error),
+    #[error("MongoDB GridFS download error. {0}")]
+    MongoGridFsDownload(mongodb::error::Error),
+    #[error("MongoDB GridFS delete error. {0}")]
+    MongoGridFsDelete(mongodb::error::Error),
     #[error("Could not get {0} configuration.")]
     GetConfig(&'static str),
     #[error("JWT encode: {0}")]
@@ -66,14 +99,44 @@ pub enum UsermanError {
     RoleNotFound,
     #[error("App not found.")]
     AppNotFound,
+    #[error("User not found.")]
+    UserNotFound,
+    #[error("Multipart error. {0}")]
+    Multipart(String),
+    #[error("Missing avatar image.")]
+    MissingAvatar,
+    #[error("Could not process image. {0}")]
+    Image(String),
     #[error("Unauthorized")]
     Unauthorized,
     #[error("Disabled user.")]
     DisabledUser,
     #[error("Invalid token.")]
     InvalidToken,
+    #[error("Invalid page cursor.")]
+    InvalidCursor,
+    #[error("Invalid sort field {0}.")]
+    InvalidSort(String),
+    #[error("Invalid audit query. {0}")]
+    InvalidAuditQuery(String),
+    #[error("Audit sink error. {0}")]
+    AuditSink(String),
+    #[error("Invalid webhook. {0}")]
+    InvalidWebhook(String),
+    #[error("Webhook not found.")]
+    WebhookNotFound,
+    #[error("Webhook delivery not found.")]
+    DeliveryNotFound,
+    #[error("Webhook delivery error. {0}")]
+    Webhook(String),
     #[error("Uninitialized password.")]
     UninitializedPassword,
+    #[error("Password change required.")]
+    PasswordChangeRequired,
+    #[error("Password not reset.")]
+    PasswordNotReset,
+    #[error("The new password must differ from the current one.")]
+    SamePassword,
 
     #[error("Auth error. {0}")]
     Auth(#[from] AuthError),
@@ -84,14 +147,107 @@ impl UsermanError {
         match self {
             Self::InvalidToken => Some(1),
             Self::UninitializedPassword => Some(2),
+            Self::PasswordChangeRequired => Some(3),
             _ => None,
         }
     }
+
+    /// Name of the variant, e.g. `MongoFindOne`, to label metrics.
+    pub fn variant(&self) -> &'static str {
+        match self {
+            Self::SignalInterrupt(..) => "SignalInterrupt",
+            Self::SignalTerminate(..) => "SignalTerminate",
+            Self::InterruptBlocking => "InterruptBlocking",
+            Self::TerminateBlocking => "TerminateBlocking",
+            Self::SignalHangup(..) => "SignalHangup",
+            Self::HangupBlocking => "HangupBlocking",
+            Self::TaskStopped => "TaskStopped",
+            Self::StdIoError(..) => "StdIoError",
+            Self::YAMLFile(..) => "YAMLFile",
+            Self::InvalidConfig(..) => "InvalidConfig",
+            Self::ImportFile(..) => "ImportFile",
+            Self::ExportFile(..) => "ExportFile",
+            Self::RestoreArchive(..) => "RestoreArchive",
+            Self::Manifest(..) => "Manifest",
+            Self::PEMFile(..) => "PEMFile",
+            Self::Logger(..) => "Logger",
+            Self::Tracing(..) => "Tracing",
+            Self::WebServer(..) => "WebServer",
+            Self::MongoParseUri(..) => "MongoParseUri",
+            Self::MongoCreateClient(..) => "MongoCreateClient",
+            Self::MongoCreateCollection(..) => "MongoCreateCollection",
+            Self::MongoReadCursor(..) => "MongoReadCursor",
+            Self::MongoWatchChangeStream(..) => "MongoWatchChangeStream",
+            Self::MongoFind(..) => "MongoFind",
+            Self::MongoFindOne(..) => "MongoFindOne",
+            Self::MongoInsertOne(..) => "MongoInsertOne",
+            Self::MongoUpdateOne(..) => "MongoUpdateOne",
+            Self::MongoDeleteOne(..) => "MongoDeleteOne",
+            Self::MongoRunCommand(..) => "MongoRunCommand",
+            Self::MongoCountDocuments(..) => "MongoCountDocuments",
+            Self::MongoCreateIndex(..) => "MongoCreateIndex",
+            Self::MongoGridFsUpload(..) => "MongoGridFsUpload",
+            Self::MongoGridFsDownload(..) => "MongoGridFsDownload",
+            Self::MongoGridFsDelete(..) => "MongoGridFsDelete",
+            Self::GetConfig(..) => "GetConfig",
+            Self::JWTEncode(..) => "JWTEncode",
+            Self::JWTDecode(..) => "JWTDecode",
+            Self::CreateJSON(..) => "CreateJSON",
+            Self::InvalidCredentials => "InvalidCredentials",
+            Self::InvalidUsername => "InvalidUsername",
+            Self::ParseObjectId(..) => "ParseObjectId",
+            Self::RoleNotFound => "RoleNotFound",
+            Self::AppNotFound => "AppNotFound",
+            Self::UserNotFound => "UserNotFound",
+            Self::Multipart(..) => "Multipart",
+            Self::MissingAvatar => "MissingAvatar",
+            Self::Image(..) => "Image",
+            Self::Unauthorized => "Unauthorized",
+            Self::DisabledUser => "DisabledUser",
+            Self::InvalidToken => "InvalidToken",
+            Self::InvalidCursor => "InvalidCursor",
+            Self::InvalidSort(..) => "InvalidSort",
+            Self::InvalidAuditQuery(..) => "InvalidAuditQuery",
+            Self::AuditSink(..) => "AuditSink",
+            Self::InvalidWebhook(..) => "InvalidWebhook",
+            Self::WebhookNotFound => "WebhookNotFound",
+            Self::DeliveryNotFound => "DeliveryNotFound",
+            Self::Webhook(..) => "Webhook",
+            Self::UninitializedPassword => "UninitializedPassword",
+            Self::PasswordChangeRequired => "PasswordChangeRequired",
+            Self::PasswordNotReset => "PasswordNotReset",
+            Self::SamePassword => "SamePassword",
+            Self::Auth(..) => "Auth",
+        }
+    }
+
+    /// Whether the error comes from the MongoDB driver.
+    pub fn is_mongo(&self) -> bool {
+        matches!(
+            self,
+            Self::MongoParseUri(..)
+                | Self::MongoCreateClient(..)
+                | Self::MongoCreateCollection(..)
+                | Self::MongoReadCursor(..)
+                | Self::MongoWatchChangeStream(..)
+                | Self::MongoFind(..)
+                | Self::MongoFindOne(..)
+                | Self::MongoInsertOne(..)
+                | Self::MongoUpdateOne(..)
+                | Self::MongoDeleteOne(..)
+                | Self::MongoRunCommand(..)
+                | Self::MongoCountDocuments(..)
+                | Self::MongoCreateIndex(..)
+                | Self::MongoGridFsUpload(..)
+                | Self::MongoGridFsDownload(..)
+                | Self::MongoGridFsDelete(..)
+        )
+    }
 }
 
 impl IntoResponse for UsermanError {
     fn into_response(self) -> Response {
-        let body = match self.code_number() {
+        let mut body = match self.code_number() {
             Some(t) => {
                 json!({
                     "status": "error",
@@ -107,6 +263,10 @@ impl IntoResponse for UsermanError {
             }
         };
 
+        if let Some(t) = access::request_id() {
+            body["requestId"] = json!(t);
+        }
+
         (StatusCode::BAD_REQUEST, Json(body)).into_response()
     }
 }
@@ -0,0 +1,405 @@
+//! Full export of apps, roles, users and configs as a versioned archive.
+use chrono::{DateTime, Utc};
+use mongodb::bson;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+use userman_auth::apps::App;
+use userman_auth::roles::{Role, RoleItems};
+
+use crate::configs::{Config, ConfigData, TokenConfig};
+use crate::dao::Dao;
+use crate::users::User;
+use crate::{Result, UsermanError, VERSION};
+
+/// Archive layout version. Bump it on any breaking change of the records.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+const HIDDEN: &str = "hidden";
+
+#[derive(
+    Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Default, ToSchema, clap::ValueEnum,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    /// One JSON document.
+    #[default]
+    Json,
+    /// One JSON record per line, starting with a header.
+    Ndjson,
+    /// Users only, with the same columns as the users import.
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Ndjson => "application/x-ndjson",
+            Self::Csv => "text/csv",
+        }
+    }
+
+    pub fn from_path(path: &str) -> Self {
+        match path.to_lowercase() {
+            t if t.ends_with(".ndjson") => Self::Ndjson,
+            t if t.ends_with(".csv") => Self::Csv,
+            _ => Self::Json,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Ndjson => "ndjson",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+fn to_chrono(src: Option<bson::DateTime>) -> Option<DateTime<Utc>> {
+    src.map(|t| t.to_chrono())
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppRecord {
+    pub id: String,
+    pub name: String,
+    pub version: u64,
+    pub default_role: RoleItems,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl From<App> for AppRecord {
+    fn from(src: App) -> Self {
+        Self {
+            id: src.id().to_hex(),
+            name: src.name,
+            version: src.version,
+            default_role: src.default_role,
+            created_at: to_chrono(src.created_at),
+            updated_at: to_chrono(src.updated_at),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleRecord {
+    pub id: String,
+    pub app: String,
+    pub name: String,
+    pub items: RoleItems,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl From<Role> for RoleRecord {
+    fn from(src: Role) -> Self {
+        Self {
+            id: src.id().to_hex(),
+            app: src.app.to_hex(),
+            name: src.name,
+            items: src.items,
+            created_at: to_chrono(src.created_at),
+            updated_at: to_chrono(src.updated_at),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserRecord {
+    pub id: String,
+    pub username: String,
+    /// Password hash, only exported with secrets.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    pub email: String,
+    pub name: String,
+    pub surname: String,
+    pub description: String,
+    pub department: String,
+    pub roles: Vec<String>,
+    pub enabled: bool,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl From<User> for UserRecord {
+    fn from(src: User) -> Self {
+        Self {
+            id: src.id().to_hex(),
+            username: src.username,
+            password: src.password,
+            email: src.email,
+            name: src.name,
+            surname: src.surname,
+            description: src.description,
+            department: src.department,
+            roles: src.roles.iter().map(|t| t.to_hex()).collect(),
+            enabled: src.enabled,
+            created_at: to_chrono(src.created_at),
+            updated_at: to_chrono(src.updated_at),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveHeader {
+    pub version: u32,
+    /// Userman version that created the archive.
+    pub userman: String,
+    pub created_at: DateTime<Utc>,
+    /// Whether password hashes and token secrets are included.
+    pub secrets: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Archive {
+    #[serde(flatten)]
+    pub header: ArchiveHeader,
+    pub apps: Vec<AppRecord>,
+    pub roles: Vec<RoleRecord>,
+    pub users: Vec<UserRecord>,
+    pub configs: Vec<Config>,
+}
+
+/// One NDJSON line of an archive.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ArchiveEntry {
+    Header(ArchiveHeader),
+    App(AppRecord),
+    Role(RoleRecord),
+    User(UserRecord),
+    Config(Config),
+}
+
+/// CSV row of a user, same columns accepted by the users import.
+#[derive(Serialize)]
+struct CsvUserRecord<'a> {
+    username: &'a str,
+    email: &'a str,
+    name: &'a str,
+    surname: &'a str,
+    department: &'a str,
+    description: &'a str,
+    roles: String,
+    enabled: bool,
+}
+
+fn hide_secrets(data: ConfigData) -> ConfigData {
+    match data {
+        ConfigData::Token(t) => ConfigData::Token(TokenConfig {
+            secret: HIDDEN.to_string(),
+            ..t
+        }),
+    }
+}
+
+impl Archive {
+    /// Read the whole database. Password hashes and token secrets are only
+    /// kept with `secrets`.
+    pub async fn read(dao: &Dao, secrets: bool) -> Result<Self> {
+        let mut apps: Vec<AppRecord> = dao
+            .read_all_apps()
+            .await?
+            .into_values()
+            .map(AppRecord::from)
+            .collect();
+
+        let mut roles: Vec<RoleRecord> = dao
+            .read_all_roles()
+            .await?
+            .0
+            .into_values()
+            .map(RoleRecord::from)
+            .collect();
+
+        let mut users: Vec<UserRecord> = dao
+            .read_all_users()
+            .await?
+            .0
+            .into_values()
+            .map(|t| match secrets {
+                true => UserRecord::from(t),
+                false => UserRecord::from(t.none_password()),
+            })
+            .collect();
+
+        let mut configs: Vec<Config> = dao
+            .read_all_configs()
+            .await?
+            .into_iter()
+            .map(|(id, data)| Config {
+                id,
+                data: match secrets {
+                    true => data,
+                    false => hide_secrets(data),
+                },
+            })
+            .collect();
+
+        // Keep archives of the same data identical.
+        apps.sort_by(|a, b| (&a.name, a.version).cmp(&(&b.name, b.version)));
+        roles.sort_by(|a, b| a.name.cmp(&b.name));
+        users.sort_by(|a, b| a.username.cmp(&b.username));
+        configs.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(Self {
+            header: ArchiveHeader {
+                version: ARCHIVE_VERSION,
+                userman: VERSION.to_string(),
+                created_at: Utc::now(),
+                secrets,
+            },
+            apps,
+            roles,
+            users,
+            configs,
+        })
+    }
+
+    pub fn entries(self) -> Vec<ArchiveEntry> {
+        let mut entries = vec![ArchiveEntry::Header(self.header)];
+
+        entries.extend(self.apps.into_iter().map(ArchiveEntry::App));
+        entries.extend(self.roles.into_iter().map(ArchiveEntry::Role));
+        entries.extend(self.users.into_iter().map(ArchiveEntry::User));
+        entries.extend(self.configs.into_iter().map(ArchiveEntry::Config));
+
+        entries
+    }
+
+    /// Parse an archive written by `into_string`. CSV exports only hold users
+    /// and can not be restored.
+    pub fn parse(src: &str, format: ExportFormat) -> Result<Self> {
+        let archive: Self = match format {
+            ExportFormat::Json => serde_json::from_str(src)
+                .map_err(|err| UsermanError::RestoreArchive(err.to_string()))?,
+            ExportFormat::Ndjson => {
+                let mut entries = src.lines().filter(|t| !t.trim().is_empty()).map(|t| {
+                    serde_json::from_str::<ArchiveEntry>(t)
+                        .map_err(|err| UsermanError::RestoreArchive(err.to_string()))
+                });
+
+                let header = match entries.next().transpose()? {
+                    Some(ArchiveEntry::Header(t)) => t,
+                    _ => {
+                        return Err(UsermanError::RestoreArchive(
+                            "Missing archive header.".to_string(),
+                        ))
+                    }
+                };
+
+                let mut archive = Self {
+                    header,
+                    apps: vec![],
+                    roles: vec![],
+                    users: vec![],
+                    configs: vec![],
+                };
+
+                for entry in entries {
+                    match entry? {
+                        ArchiveEntry::Header(_) => {
+                            return Err(UsermanError::RestoreArchive(
+                                "Duplicated archive header.".to_string(),
+                            ))
+                        }
+                        ArchiveEntry::App(t) => archive.apps.push(t),
+                        ArchiveEntry::Role(t) => archive.roles.push(t),
+                        ArchiveEntry::User(t) => archive.users.push(t),
+                        ArchiveEntry::Config(t) => archive.configs.push(t),
+                    }
+                }
+
+                archive
+            }
+            ExportFormat::Csv => {
+                return Err(UsermanError::RestoreArchive(
+                    "CSV exports only hold users, use the users import.".to_string(),
+                ))
+            }
+        };
+
+        if archive.header.version > ARCHIVE_VERSION {
+            return Err(UsermanError::RestoreArchive(format!(
+                "Unsupported archive version {}.",
+                archive.header.version
+            )));
+        }
+
+        Ok(archive)
+    }
+
+    fn to_csv(&self) -> Result<String> {
+        let role_names: HashMap<&str, &str> = self
+            .roles
+            .iter()
+            .map(|t| (t.id.as_str(), t.name.as_str()))
+            .collect();
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+
+        for user in &self.users {
+            let roles: Vec<&str> = user
+                .roles
+                .iter()
+                .filter_map(|t| role_names.get(t.as_str()).copied())
+                .collect();
+
+            writer
+                .serialize(CsvUserRecord {
+                    username: &user.username,
+                    email: &user.email,
+                    name: &user.name,
+                    surname: &user.surname,
+                    department: &user.department,
+                    description: &user.description,
+                    roles: roles.join(";"),
+                    enabled: user.enabled,
+                })
+                .map_err(|err| UsermanError::ExportFile(err.to_string()))?;
+        }
+
+        let data = writer
+            .into_inner()
+            .map_err(|err| UsermanError::ExportFile(err.to_string()))?;
+
+        String::from_utf8(data).map_err(|err| UsermanError::ExportFile(err.to_string()))
+    }
+
+    pub fn into_string(self, format: ExportFormat) -> Result<String> {
+        match format {
+            ExportFormat::Json => {
+                serde_json::to_string_pretty(&self).map_err(UsermanError::CreateJSON)
+            }
+            ExportFormat::Ndjson => {
+                let mut lines = vec![];
+
+                for entry in self.entries() {
+                    lines.push(serde_json::to_string(&entry).map_err(UsermanError::CreateJSON)?);
+                }
+
+                lines.push(String::new());
+                Ok(lines.join("\n"))
+            }
+            ExportFormat::Csv => self.to_csv(),
+        }
+    }
+
+    pub fn file_name(&self, format: ExportFormat) -> String {
+        format!(
+            "userman-export-{}.{}",
+            self.header.created_at.format("%Y%m%d%H%M%S"),
+            format.extension()
+        )
+    }
+}
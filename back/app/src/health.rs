@@ -0,0 +1,109 @@
+//! Liveness, readiness and version endpoints for the orchestrator. They are
+//! served outside `front.publicUrl` and need no token.
+
+use axum::extract::Extension;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::timeout;
+
+use userman_auth::apps::LOCAL_APP;
+use userman_auth::roles::LOCAL_ROLE;
+
+use crate::configs::TOKEN_CONFIG;
+use crate::dao::Memory;
+use crate::{Shared, COMMIT, VERSION};
+
+/// Change stream watchers started by `watchers::run`.
+pub const WATCHERS: usize = 4;
+/// Deadline of the MongoDB ping, probes usually time out after a second.
+const PING_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Running state of the background tasks.
+#[derive(Clone, Default)]
+pub struct Health {
+    watchers: Arc<AtomicUsize>,
+}
+
+impl Health {
+    /// Count a running watcher until the guard drops.
+    pub fn watcher(&self) -> WatcherGuard {
+        self.watchers.fetch_add(1, Ordering::SeqCst);
+        WatcherGuard(self.watchers.clone())
+    }
+
+    pub fn watchers(&self) -> usize {
+        self.watchers.load(Ordering::SeqCst)
+    }
+}
+
+pub struct WatcherGuard(Arc<AtomicUsize>);
+
+impl Drop for WatcherGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Readiness {
+    status: &'static str,
+    mongo_db: bool,
+    watchers: usize,
+    caches: bool,
+}
+
+#[derive(Serialize)]
+pub struct Version {
+    version: &'static str,
+    name: String,
+    commit: &'static str,
+}
+
+/// The process is up.
+pub async fn healthz() -> impl IntoResponse {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// MongoDB answers, every watcher runs and the caches hold the built in app,
+/// role and token config.
+pub async fn readyz(Extension(shared): Extension<Shared>) -> impl IntoResponse {
+    let mongo_db = matches!(timeout(PING_TIMEOUT, shared.dao.ping()).await, Ok(Ok(_)));
+    let watchers = shared.health.watchers();
+    let caches = shared.apps.get(&LOCAL_APP.to_string()).await.is_some()
+        && shared.roles.get(&LOCAL_ROLE.to_string()).await.is_some()
+        && shared
+            .configs
+            .get(&TOKEN_CONFIG.to_string())
+            .await
+            .is_some();
+
+    let ready = mongo_db && watchers == WATCHERS && caches;
+
+    let status = match ready {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    let readiness = Readiness {
+        status: if ready { "ok" } else { "error" },
+        mongo_db,
+        watchers,
+        caches,
+    };
+
+    (status, Json(readiness))
+}
+
+pub async fn version(Extension(shared): Extension<Shared>) -> impl IntoResponse {
+    Json(Version {
+        version: VERSION,
+        name: shared.config_yaml.name,
+        commit: COMMIT,
+    })
+}
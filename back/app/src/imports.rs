@@ -0,0 +1,396 @@
+//! Bulk users import from CSV or JSON rows.
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Document};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use utoipa::ToSchema;
+
+use crate::dao::Dao;
+use crate::users::User;
+use crate::{Result, UsermanError};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl ImportFormat {
+    pub fn from_path(path: &str) -> Self {
+        match path.to_lowercase().ends_with(".csv") {
+            true => Self::Csv,
+            false => Self::Json,
+        }
+    }
+}
+
+/// Missing optional fields are left as they are on updates.
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserRow {
+    pub username: String,
+    pub email: String,
+    pub name: String,
+    pub surname: String,
+    #[serde(default)]
+    pub department: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Role names.
+    #[serde(default)]
+    pub roles: Option<Vec<String>>,
+    #[serde(default)]
+    pub enabled: Option<bool>,
+}
+
+/// CSV flavour of `UserRow`, roles are separated with `;`. Missing columns and
+/// blank cells are missing fields.
+#[derive(Deserialize)]
+struct CsvUserRow {
+    username: String,
+    email: String,
+    name: String,
+    surname: String,
+    #[serde(default)]
+    department: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    roles: Option<String>,
+    #[serde(default)]
+    enabled: Option<bool>,
+}
+
+impl From<CsvUserRow> for UserRow {
+    fn from(src: CsvUserRow) -> Self {
+        Self {
+            username: src.username,
+            email: src.email,
+            name: src.name,
+            surname: src.surname,
+            department: src.department,
+            description: src.description,
+            roles: src.roles.map(|roles| {
+                roles
+                    .split(';')
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty())
+                    .collect()
+            }),
+            enabled: src.enabled,
+        }
+    }
+}
+
+/// Columns every CSV file needs, the others have a default.
+const CSV_COLUMNS: [&str; 4] = ["username", "email", "name", "surname"];
+
+/// Row of the file, or the error reported for it.
+pub type ParsedRow = std::result::Result<UserRow, RowError>;
+
+fn row_error(index: usize, username: Option<&str>, error: impl ToString) -> RowError {
+    RowError {
+        row: index + 1,
+        username: username.unwrap_or_default().to_string(),
+        error: error.to_string(),
+    }
+}
+
+/// Parse the rows of the file one by one so an invalid row is reported alone.
+/// The whole file fails only when it is not a JSON array or its CSV header
+/// misses a column.
+pub fn parse_rows(src: &str, format: ImportFormat) -> Result<Vec<ParsedRow>> {
+    match format {
+        ImportFormat::Json => {
+            let values: Vec<serde_json::Value> = serde_json::from_str(src)
+                .map_err(|err| UsermanError::ImportFile(err.to_string()))?;
+
+            Ok(values
+                .into_iter()
+                .enumerate()
+                .map(|(index, value)| {
+                    let username = value
+                        .get("username")
+                        .and_then(|t| t.as_str())
+                        .map(str::to_string);
+
+                    serde_json::from_value(value)
+                        .map_err(|err| row_error(index, username.as_deref(), err))
+                })
+                .collect())
+        }
+        ImportFormat::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(src.as_bytes());
+
+            let headers = reader
+                .headers()
+                .map_err(|err| UsermanError::ImportFile(err.to_string()))?
+                .clone();
+
+            if let Some(t) = CSV_COLUMNS
+                .iter()
+                .find(|t| !headers.iter().any(|h| h == **t))
+            {
+                return Err(UsermanError::ImportFile(format!("Missing column {}.", t)));
+            }
+
+            let username = headers.iter().position(|t| t == "username");
+
+            Ok(reader
+                .records()
+                .enumerate()
+                .map(|(index, record)| {
+                    let record = record.map_err(|err| row_error(index, None, err))?;
+                    let name = username.and_then(|t| record.get(t));
+
+                    record
+                        .deserialize::<CsvUserRow>(Some(&headers))
+                        .map(UserRow::from)
+                        .map_err(|err| row_error(index, name, err))
+                })
+                .collect())
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ImportOptions {
+    /// Validate every row without writing anything.
+    pub dry_run: bool,
+    /// Update users whose username already exists instead of reporting a conflict.
+    pub upsert: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RowError {
+    /// Row number starting at 1.
+    pub row: usize,
+    pub username: String,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub created: usize,
+    pub updated: usize,
+    pub errors: Vec<RowError>,
+}
+
+impl UserRow {
+    fn validate(
+        &self,
+        roles: &HashMap<String, ObjectId>,
+    ) -> std::result::Result<Option<Vec<ObjectId>>, String> {
+        if self.username.trim().is_empty() {
+            return Err("Empty username.".to_string());
+        }
+
+        if !self.email.contains('@') {
+            return Err(format!("Invalid email {}.", self.email));
+        }
+
+        self.roles
+            .as_ref()
+            .map(|names| {
+                names
+                    .iter()
+                    .map(|t| {
+                        roles
+                            .get(t)
+                            .cloned()
+                            .ok_or_else(|| format!("Unknown role {}.", t))
+                    })
+                    .collect()
+            })
+            .transpose()
+    }
+
+    fn into_user(self, roles: Option<Vec<ObjectId>>) -> User {
+        User {
+            id: None,
+            username: self.username,
+            password: None,
+            email: self.email,
+            name: self.name,
+            surname: self.surname,
+            description: self.description.unwrap_or_default(),
+            department: self.department.unwrap_or_default(),
+            roles: roles.unwrap_or_default(),
+            avatar: None,
+            enabled: self.enabled.unwrap_or(true),
+            change_password: false,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    /// Fields set on the existing user, only the ones given by the row.
+    fn into_fields(self, roles: Option<Vec<ObjectId>>) -> Document {
+        let mut fields = doc! {
+            "email": self.email,
+            "name": self.name,
+            "surname": self.surname,
+        };
+
+        if let Some(t) = self.department {
+            fields.insert("department", t);
+        }
+
+        if let Some(t) = self.description {
+            fields.insert("description", t);
+        }
+
+        if let Some(t) = roles {
+            fields.insert("roles", t);
+        }
+
+        if let Some(t) = self.enabled {
+            fields.insert("enabled", t);
+        }
+
+        fields
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportWrite {
+    Create(User),
+    /// Id of the existing user and the fields to set.
+    Update(ObjectId, Document),
+}
+
+/// Write of a valid row.
+#[derive(Debug)]
+pub struct ResolvedRow {
+    /// Row number starting at 1.
+    pub row: usize,
+    pub username: String,
+    pub write: ImportWrite,
+}
+
+/// Validate the rows against the existing `users` and `roles`, by name. The
+/// report counts the writes, as returned by a dry run.
+pub fn resolve(
+    rows: Vec<ParsedRow>,
+    users: &HashMap<String, User>,
+    roles: &HashMap<String, ObjectId>,
+    options: ImportOptions,
+) -> (ImportReport, Vec<ResolvedRow>) {
+    let mut report = ImportReport {
+        dry_run: options.dry_run,
+        ..Default::default()
+    };
+
+    let mut resolved = vec![];
+    let mut seen = HashSet::new();
+
+    for (index, row) in rows.into_iter().enumerate() {
+        let row = match row {
+            Ok(t) => t,
+            Err(err) => {
+                report.errors.push(err);
+                continue;
+            }
+        };
+
+        let row_number = index + 1;
+        let username = row.username.clone();
+
+        let error = |error: String| RowError {
+            row: row_number,
+            username: username.clone(),
+            error,
+        };
+
+        let role_ids = match row.validate(roles) {
+            Ok(t) => t,
+            Err(err) => {
+                report.errors.push(error(err));
+                continue;
+            }
+        };
+
+        if !seen.insert(row.username.clone()) {
+            report
+                .errors
+                .push(error("Duplicated username in file.".to_string()));
+            continue;
+        }
+
+        let write = match users.get(&row.username) {
+            Some(_) if !options.upsert => {
+                report
+                    .errors
+                    .push(error("Username already exists.".to_string()));
+                continue;
+            }
+            Some(t) => {
+                report.updated += 1;
+                ImportWrite::Update(t.id(), row.into_fields(role_ids))
+            }
+            None => {
+                report.created += 1;
+                ImportWrite::Create(row.into_user(role_ids))
+            }
+        };
+
+        resolved.push(ResolvedRow {
+            row: row_number,
+            username,
+            write,
+        });
+    }
+
+    (report, resolved)
+}
+
+/// Create, or update with `upsert`, one user per row. Users are created
+/// without password so they have to set it through the reset flow.
+pub async fn import_users(
+    dao: &Dao,
+    rows: Vec<ParsedRow>,
+    options: ImportOptions,
+) -> Result<ImportReport> {
+    let (_, users) = dao.read_all_users().await?;
+    let (_, roles) = dao.read_all_roles().await?;
+
+    let roles: HashMap<String, ObjectId> = roles
+        .into_iter()
+        .map(|(name, role)| (name, role.id()))
+        .collect();
+
+    let (mut report, resolved) = resolve(rows, &users, &roles, options);
+
+    if options.dry_run {
+        return Ok(report);
+    }
+
+    for t in resolved {
+        let result = match &t.write {
+            ImportWrite::Create(user) => dao.create_user(user).await.map(|_| ()),
+            ImportWrite::Update(id, fields) => dao.update_user_fields(id, fields.clone()).await,
+        };
+
+        if let Err(err) = result {
+            match t.write {
+                ImportWrite::Create(_) => report.created -= 1,
+                ImportWrite::Update(..) => report.updated -= 1,
+            }
+
+            report.errors.push(RowError {
+                row: t.row,
+                username: t.username,
+                error: err.to_string(),
+            });
+        }
+    }
+
+    Ok(report)
+}
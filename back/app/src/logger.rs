@@ -1,25 +1,42 @@
 //! Main log4rs configuration functions.
 
-use log::LevelFilter;
+use chrono::{DateTime, Utc};
+use log::kv::{self, Key, Source, VisitSource};
+use log::{error, info, Level, LevelFilter, Record};
 use log4rs::append::console::{ConsoleAppender, Target};
 use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
 use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
 use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
 use log4rs::append::rolling_file::RollingFileAppender;
-use log4rs::config::{Appender, Config, Root};
+use log4rs::append::Append;
+use log4rs::config::{Appender, Config, Logger, Root};
+use log4rs::encode::json::JsonEncoder;
 use log4rs::encode::pattern::PatternEncoder;
-use log4rs::filter::threshold::ThresholdFilter;
+use log4rs::encode::{Encode, Write};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use syslog::{Facility, Formatter3164, LoggerBackend};
+use utoipa::ToSchema;
 
-const PATTERN_ENCODER: &str = "{d(%Y-%m-%d %H:%M:%S)} {l} [{M}:{L}] {m}{n}";
+use crate::access;
+use crate::config_yaml::{Logs, Syslog};
+use crate::{Result, UsermanError};
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+const PATTERN_ENCODER: &str =
+    "{d(%Y-%m-%d %H:%M:%S)} {l} [{M}:{L}] [{K(request_id)(-)}] {m}{n}";
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Default, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum LogsLevel {
     #[default]
     Info,
     Debug,
     Error,
+    Warn,
+    Trace,
 }
 
 impl std::fmt::Display for LogsLevel {
@@ -28,67 +45,326 @@ impl std::fmt::Display for LogsLevel {
             Self::Info => write!(f, "info"),
             Self::Debug => write!(f, "debug"),
             Self::Error => write!(f, "error"),
+            Self::Warn => write!(f, "warn"),
+            Self::Trace => write!(f, "trace"),
+        }
+    }
+}
+
+impl From<&LogsLevel> for LevelFilter {
+    fn from(src: &LogsLevel) -> Self {
+        match src {
+            LogsLevel::Error => LevelFilter::Error,
+            LogsLevel::Warn => LevelFilter::Warn,
+            LogsLevel::Info => LevelFilter::Info,
+            LogsLevel::Debug => LevelFilter::Debug,
+            LogsLevel::Trace => LevelFilter::Trace,
         }
     }
 }
 
-fn create_log(name: &str) -> RollingFileAppender {
-    let mut zipped_path = String::from("logs/");
-    zipped_path.push_str(name);
-    zipped_path.push_str(".{}.log.gz");
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum LogsFormat {
+    #[default]
+    Text,
+    /// One JSON object per line, with the `key = value` pairs of the record
+    /// under `attributes`.
+    Json,
+}
+
+fn encoder(format: &LogsFormat) -> Box<dyn Encode> {
+    let encoder: Box<dyn Encode> = match format {
+        LogsFormat::Text => Box::new(PatternEncoder::new(PATTERN_ENCODER)),
+        LogsFormat::Json => Box::new(JsonEncoder::new()),
+    };
+
+    Box::new(RequestIdEncoder(encoder))
+}
+
+/// Key-values of a record with the id of the request being served.
+struct WithRequestId<'a> {
+    source: &'a dyn Source,
+    request_id: (&'static str, &'a str),
+}
+
+impl Source for WithRequestId<'_> {
+    fn visit<'kvs>(
+        &'kvs self,
+        visitor: &mut dyn VisitSource<'kvs>,
+    ) -> std::result::Result<(), kv::Error> {
+        self.request_id.visit(visitor)?;
+        self.source.visit(visitor)
+    }
+}
+
+/// Adds `request_id` to the records written while serving a request.
+#[derive(Debug)]
+struct RequestIdEncoder(Box<dyn Encode>);
+
+impl Encode for RequestIdEncoder {
+    fn encode(&self, w: &mut dyn Write, record: &Record) -> anyhow::Result<()> {
+        let request_id = match access::request_id() {
+            Some(t) if record.key_values().get(Key::from("request_id")).is_none() => t,
+            _ => return self.0.encode(w, record),
+        };
+
+        let source = WithRequestId {
+            source: record.key_values(),
+            request_id: ("request_id", request_id.as_str()),
+        };
+
+        self.0.encode(w, &record.to_builder().key_values(&source).build())
+    }
+}
+
+fn create_log(logs: &Logs, name: &str) -> Result<RollingFileAppender> {
+    let directory = Path::new(&logs.directory);
+    let zipped_path = directory.join(format!("{}.{{}}.log.gz", name));
 
-    let size_trigger = SizeTrigger::new(250 * 1024 * 1024); // 250M file
+    let size_trigger = SizeTrigger::new(logs.rotation.max_size_mb * 1024 * 1024);
     let window_roller = FixedWindowRoller::builder()
-        .build(&zipped_path, 50)
-        .unwrap();
+        .build(&zipped_path.to_string_lossy(), logs.rotation.archives)
+        .map_err(|err| UsermanError::Logger(err.to_string()))?;
 
     let policy = CompoundPolicy::new(Box::new(size_trigger), Box::new(window_roller));
 
     RollingFileAppender::builder()
-        .encoder(Box::new(PatternEncoder::new(PATTERN_ENCODER)))
-        .build(format!("logs/{}.log", name), Box::new(policy))
-        .unwrap()
+        .encoder(encoder(&logs.format))
+        .build(directory.join(format!("{}.log", name)), Box::new(policy))
+        .map_err(|err| UsermanError::Logger(err.to_string()))
 }
 
-fn create_config(logs_level: &LogsLevel) -> Config {
-    let log4rs_level = match logs_level {
-        LogsLevel::Error => LevelFilter::Error,
-        LogsLevel::Info => LevelFilter::Info,
-        LogsLevel::Debug => LevelFilter::Debug,
-    };
+/// Appender sending the records to the local syslog daemon, or to a remote
+/// one over UDP.
+struct SyslogAppender(Mutex<syslog::Logger<LoggerBackend, Formatter3164>>);
+
+impl std::fmt::Debug for SyslogAppender {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SyslogAppender").finish()
+    }
+}
+
+impl SyslogAppender {
+    fn new(syslog: &Syslog) -> Result<Self> {
+        let formatter = Formatter3164 {
+            facility: Facility::LOG_DAEMON,
+            hostname: None,
+            process: String::from("userman"),
+            pid: std::process::id(),
+        };
 
+        let logger = match syslog.address.is_empty() {
+            true => syslog::unix(formatter),
+            false => syslog::udp(formatter, "0.0.0.0:0", &syslog.address),
+        }
+        .map_err(|err| UsermanError::Logger(err.to_string()))?;
+
+        Ok(Self(Mutex::new(logger)))
+    }
+}
+
+impl Append for SyslogAppender {
+    fn append(&self, record: &Record) -> anyhow::Result<()> {
+        let mut logger = self
+            .0
+            .lock()
+            .map_err(|err| anyhow::anyhow!(err.to_string()))?;
+        let message = match access::request_id() {
+            Some(t) => format!("[{}] [{}] {}", record.target(), t, record.args()),
+            None => format!("[{}] {}", record.target(), record.args()),
+        };
+
+        match record.level() {
+            Level::Error => logger.err(message),
+            Level::Warn => logger.warning(message),
+            Level::Info => logger.info(message),
+            Level::Debug | Level::Trace => logger.debug(message),
+        }
+        .map_err(|err| anyhow::anyhow!(err.to_string()))
+    }
+
+    fn flush(&self) {}
+}
+
+fn create_config(logs: &Logs) -> Result<Config> {
     let stdout = ConsoleAppender::builder()
-        .encoder(Box::new(PatternEncoder::new(PATTERN_ENCODER)))
+        .encoder(encoder(&logs.format))
         .target(Target::Stdout)
         .build();
 
-    Config::builder()
-        .appender(Appender::builder().build("mainlog", Box::new(create_log("main"))))
-        .appender(
-            Appender::builder()
-                .filter(Box::new(ThresholdFilter::new(log4rs_level)))
-                .build("stdout", Box::new(stdout)),
-        )
+    let mut appenders = vec!["mainlog", "stdout"];
+
+    let mut builder = Config::builder()
+        .appender(Appender::builder().build("mainlog", Box::new(create_log(logs, "main")?)))
+        .appender(Appender::builder().build("stdout", Box::new(stdout)));
+
+    if logs.syslog.enabled {
+        let syslog = SyslogAppender::new(&logs.syslog)?;
+        builder = builder.appender(Appender::builder().build("syslog", Box::new(syslog)));
+        appenders.push("syslog");
+    }
+
+    for (module, level) in &logs.modules {
+        builder = builder.logger(Logger::builder().build(module, level.into()));
+    }
+
+    builder
         .build(
             Root::builder()
-                .appender("mainlog")
-                .appender("stdout")
-                .build(log4rs_level),
+                .appenders(appenders)
+                .build((&logs.level).into()),
         )
-        .unwrap()
+        .map_err(|err| UsermanError::Logger(err.to_string()))
+}
+
+/// Levels set at runtime over the ones of `config.yaml`.
+#[derive(Clone, Debug, Default)]
+struct Overrides {
+    level: Option<LogsLevel>,
+    modules: BTreeMap<String, LogsLevel>,
+    revert_at: Option<DateTime<Utc>>,
+    /// Bumped on every change, so an old revert timer does not undo a newer
+    /// change.
+    generation: u64,
+}
+
+/// Levels in effect.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLevels {
+    pub level: LogsLevel,
+    pub modules: BTreeMap<String, LogsLevel>,
+    /// When the runtime levels go back to the ones of `config.yaml`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = String)]
+    pub revert_at: Option<DateTime<Utc>>,
+}
+
+struct State {
+    handle: log4rs::Handle,
+    logs: Logs,
+    overrides: Overrides,
+}
+
+impl State {
+    fn effective(&self) -> Logs {
+        let mut logs = self.logs.clone();
+
+        if let Some(t) = &self.overrides.level {
+            logs.level = t.clone();
+        }
+
+        logs.modules.extend(self.overrides.modules.clone());
+
+        logs
+    }
+
+    fn apply(&self) -> Result<()> {
+        let config = create_config(&self.effective())?;
+        self.handle.set_config(config);
+        Ok(())
+    }
+
+    fn levels(&self) -> LogLevels {
+        let logs = self.effective();
+
+        LogLevels {
+            level: logs.level,
+            modules: logs.modules,
+            revert_at: self.overrides.revert_at,
+        }
+    }
 }
 
-pub struct Handle(log4rs::Handle);
+#[derive(Clone)]
+pub struct Handle(Arc<Mutex<State>>);
 
 pub fn build() -> Handle {
-    let config = create_config(&LogsLevel::Info);
+    build_with(Logs::default())
+}
+
+/// Install the global logger with `logs`, only once per process.
+pub fn build_with(logs: Logs) -> Handle {
+    let config = create_config(&logs).unwrap();
     let handle = log4rs::init_config(config).unwrap();
-    Handle(handle)
+
+    Handle(Arc::new(Mutex::new(State {
+        handle,
+        logs,
+        overrides: Overrides::default(),
+    })))
 }
 
 impl Handle {
-    pub fn set_logger(&mut self, src: &LogsLevel) {
-        let config = create_config(src);
-        self.0.set_config(config);
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Apply the `logs` of `config.yaml`, the runtime levels stay on top.
+    pub fn set_logger(&self, src: &Logs) -> Result<()> {
+        let mut state = self.state();
+        state.logs = src.clone();
+        state.apply()
+    }
+
+    pub fn levels(&self) -> LogLevels {
+        self.state().levels()
+    }
+
+    /// Set the global level, or the one of `module`. With `revert_after` the
+    /// runtime levels are dropped once it elapses, unless changed again.
+    pub fn set_level(
+        &self,
+        level: LogsLevel,
+        module: Option<String>,
+        revert_after: Option<Duration>,
+    ) -> Result<LogLevels> {
+        let mut state = self.state();
+
+        match module {
+            Some(t) => _ = state.overrides.modules.insert(t, level),
+            None => state.overrides.level = Some(level),
+        }
+
+        state.overrides.generation += 1;
+        state.overrides.revert_at = revert_after
+            .and_then(|t| chrono::Duration::from_std(t).ok())
+            .map(|t| Utc::now() + t);
+        state.apply()?;
+
+        if let Some(duration) = revert_after {
+            let generation = state.overrides.generation;
+            let handle = self.clone();
+
+            tokio::spawn(async move {
+                tokio::time::sleep(duration).await;
+
+                if handle.state().overrides.generation != generation {
+                    return;
+                }
+
+                match handle.reset() {
+                    Ok(_) => info!("Log levels reverted to config.yaml."),
+                    Err(err) => error!("{}", err),
+                }
+            });
+        }
+
+        Ok(state.levels())
+    }
+
+    /// Drop the runtime levels.
+    pub fn reset(&self) -> Result<LogLevels> {
+        let mut state = self.state();
+        let generation = state.overrides.generation + 1;
+
+        state.overrides = Overrides {
+            generation,
+            ..Default::default()
+        };
+        state.apply()?;
+
+        Ok(state.levels())
     }
 }
@@ -1,40 +1,71 @@
+mod access;
 mod api;
 mod apps;
+mod audit;
+mod avatars;
+mod cli;
 mod config_yaml;
 mod configs;
 mod dao;
 mod error;
+mod exports;
 mod files;
+mod health;
+mod imports;
 mod logger;
+mod manifest;
+mod metrics;
+mod pages;
+mod reload;
+mod restore;
 mod roles;
+mod sinks;
+mod streams;
+mod telemetry;
+mod tls;
 mod tokens;
 mod users;
 mod watchers;
 mod web;
+mod webhooks;
 
 #[cfg(test)]
 mod tests;
 
-use config_yaml::ConfigYAML;
+use clap::Parser;
+use cli::{Cli, Command};
+use config_yaml::{ConfigYAML, Logs};
 use configs::Configs;
 use dao::{Dao, Memory};
 use error::UsermanError;
-use logger::LogsLevel;
+use health::Health;
+use imports::ImportOptions;
 use mongodb::bson::oid::ObjectId;
+use reload::Reloader;
+use restore::RestoreOptions;
 use serde::ser::SerializeSeq;
+use sinks::Sinks;
+use std::process::ExitCode;
+use std::time::Duration;
+use streams::Streams;
 use tokens::{Keys, SessionToken};
+use tokio::task::{JoinError, JoinSet};
+use tokio_util::sync::CancellationToken;
 use userman_auth::apps::LOCAL_APP;
 use userman_auth::roles::RoleItems;
 use userman_auth::Auth;
+use webhooks::Notifier;
 
 pub type Result<T> = std::result::Result<T, UsermanError>;
 
 const YAML_FILE: &str = "config.yaml";
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+const COMMIT: &str = env!("USERMAN_COMMIT");
 
 use apps::Apps;
 use log::{error, info};
 use roles::Roles;
+use tracing::{info_span, instrument, Instrument};
 use users::Users;
 
 pub fn serialize_option_oid_as_string<S>(
@@ -128,28 +159,115 @@ pub struct Shared {
     keys: Keys,
     users: Users,
     roles: Roles,
+    health: Health,
+    logger: logger::Handle,
+    sinks: Sinks,
+    streams: Streams,
+    webhooks: Notifier,
 }
 
 impl Shared {
+    #[instrument(skip_all)]
     async fn permissions(&self, token: SessionToken) -> Result<RoleItems> {
-        let role_names = token.role_names(&self.keys).await?;
-        Ok(self.auth.permissions(role_names).await)
+        let role_names = token
+            .role_names(
+                &self.keys,
+                &self.users,
+                &self.roles,
+                &self.config_yaml.tls.client_users,
+            )
+            .await?;
+
+        Ok(self
+            .auth
+            .permissions(role_names)
+            .instrument(info_span!("merge_permissions"))
+            .await)
     }
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let mut logger = logger::build();
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let logger = logger::build();
 
     info!("Proteus Userman v{}", VERSION);
 
-    let config_yaml = ConfigYAML::read_or_create_file(YAML_FILE).await?;
+    let result = run(cli, logger).await;
+
+    telemetry::shutdown();
 
-    if config_yaml.logs != LogsLevel::Info {
-        logger.set_logger(&config_yaml.logs);
-        info!("Logger in {} mode.", &config_yaml.logs);
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            error!("{}", err);
+            ExitCode::FAILURE
+        }
     }
+}
 
+async fn run(cli: Cli, logger: logger::Handle) -> Result<()> {
+    let command = cli.command.unwrap_or(Command::Serve);
+
+    // Checking the file must not create it.
+    let config_yaml = match command {
+        Command::CheckConfig => ConfigYAML::read_file(&cli.config).await?,
+        _ => ConfigYAML::read_or_create_file(&cli.config).await?,
+    };
+
+    if config_yaml.logs != Logs::default() {
+        logger.set_logger(&config_yaml.logs)?;
+        info!("Logger in {} mode.", &config_yaml.logs.level);
+    }
+
+    telemetry::init(&config_yaml)?;
+
+    match command {
+        Command::Serve => serve(&cli.config, config_yaml, logger).await,
+        Command::CreateAdmin {
+            username,
+            email,
+            password,
+        } => cli::create_admin(&config_yaml, &username, email, password).await,
+        Command::ResetPassword { username, password } => {
+            cli::reset_password(&config_yaml, &username, password).await
+        }
+        Command::Unlock { username } => cli::unlock(&config_yaml, &username).await,
+        Command::RotateKeys => cli::rotate_keys(&config_yaml).await,
+        Command::Migrate => cli::migrate(&config_yaml).await,
+        Command::CheckConfig => cli::check_config(&config_yaml).await,
+        Command::ImportUsers {
+            file,
+            dry_run,
+            upsert,
+        } => cli::import_users(&config_yaml, &file, ImportOptions { dry_run, upsert }).await,
+        Command::Export {
+            format,
+            output,
+            include_secrets,
+        } => cli::export(&config_yaml, format, output.as_deref(), include_secrets).await,
+        Command::Restore {
+            file,
+            strategy,
+            dry_run,
+        } => {
+            // The command line has the database credentials anyway.
+            let options = RestoreOptions {
+                strategy,
+                dry_run,
+                secrets: true,
+            };
+
+            cli::restore(&config_yaml, &file, options).await
+        }
+        Command::Apply { file, dry_run, yes } => {
+            cli::apply(&config_yaml, &file, dry_run, yes).await
+        }
+    }
+}
+
+async fn serve(path: &str, config_yaml: ConfigYAML, logger: logger::Handle) -> Result<()> {
     let dao = config_yaml.dao().await?;
     dao.init().await?;
 
@@ -159,6 +277,11 @@ async fn main() -> Result<()> {
         .await?;
     auth.init().await?;
 
+    let rustls = web::rustls(&config_yaml).await?;
+    let drain = Duration::from_secs(config_yaml.shutdown_timeout);
+    let (sinks, forwarder) = sinks::channel(&config_yaml.audit, drain)?;
+    let reloader = Reloader::new(path, config_yaml.clone(), logger.clone(), rustls.clone());
+
     let shared = Shared {
         configs: Configs::load(&dao).await?,
         apps: Apps::load(&dao).await?,
@@ -168,21 +291,63 @@ async fn main() -> Result<()> {
         config_yaml,
         auth,
         dao,
+        health: Health::default(),
+        logger,
+        sinks,
+        streams: Streams::default(),
+        webhooks: Notifier::default(),
     };
 
-    let shared_ref = shared.clone();
+    let token = CancellationToken::new();
+    let mut tasks = JoinSet::new();
 
-    tokio::spawn(async move {
-        if let Err(err) = web::run(shared_ref).await {
-            error!("{}", err);
+    tasks.spawn(web::run(shared.clone(), rustls, token.clone()));
+    tasks.spawn(reloader.run(token.clone()));
+    tasks.spawn(webhooks::run(shared.clone(), token.clone()));
+    tasks.spawn(watchers::run(shared, token.clone()));
+
+    if let Some(t) = forwarder {
+        tasks.spawn(t.run(token.clone()));
+    }
+
+    // Any task ending before the signal is a failure.
+    let mut failed = tokio::select! {
+        t = wait_for_shutdown() => {
+            t?;
+            false
+        }
+        Some(t) = tasks.join_next() => {
+            log_task(t);
+            error!("{}", UsermanError::TaskStopped);
+            true
         }
-    });
+    };
+
+    token.cancel();
+
+    while let Some(t) = tasks.join_next().await {
+        failed |= !log_task(t);
+    }
 
-    tokio::spawn(async move {
-        if let Err(err) = watchers::run(shared).await {
+    info!("Shutdown completed.");
+
+    match failed {
+        true => Err(UsermanError::TaskStopped),
+        false => Ok(()),
+    }
+}
+
+/// Log the error of a finished task, returns whether it succeeded.
+fn log_task(result: std::result::Result<Result<()>, JoinError>) -> bool {
+    match result {
+        Ok(Ok(())) => true,
+        Ok(Err(err)) => {
             error!("{}", err);
+            false
         }
-    });
-
-    wait_for_shutdown().await
+        Err(err) => {
+            error!("{}", err);
+            false
+        }
+    }
 }
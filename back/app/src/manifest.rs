@@ -0,0 +1,710 @@
+//! Declarative desired state of apps, roles and users. A manifest is compared
+//! with the database contents to build a plan, which is applied on demand.
+//! Entities missing from the manifest are only deleted when they were marked
+//! as managed by a previous apply.
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+use userman_auth::apps::{App, LOCAL_APP};
+use userman_auth::roles::{Role, RoleItems, LOCAL_ROLE};
+
+use crate::dao::Dao;
+use crate::users::{User, ADMIN_USERNAME};
+use crate::{Result, UsermanError};
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AppEntry {
+    pub name: String,
+    pub version: u64,
+    #[serde(default)]
+    pub default_role: RoleItems,
+    /// Delete the app once it is removed from the manifest.
+    #[serde(default)]
+    pub managed: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct RoleEntry {
+    pub name: String,
+    /// App name.
+    pub app: String,
+    /// Latest version of the app when missing.
+    #[serde(default)]
+    pub app_version: Option<u64>,
+    #[serde(default)]
+    pub items: RoleItems,
+    /// Delete the role once it is removed from the manifest.
+    #[serde(default)]
+    pub managed: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct UserEntry {
+    pub username: String,
+    pub email: String,
+    pub name: String,
+    pub surname: String,
+    #[serde(default)]
+    pub department: String,
+    #[serde(default)]
+    pub description: String,
+    /// Role names.
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Delete the user once it is removed from the manifest.
+    #[serde(default)]
+    pub managed: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default)]
+    pub apps: Vec<AppEntry>,
+    #[serde(default)]
+    pub roles: Vec<RoleEntry>,
+    #[serde(default)]
+    pub users: Vec<UserEntry>,
+}
+
+impl Manifest {
+    /// Parse a YAML manifest. JSON manifests are valid YAML.
+    pub fn parse(src: &str) -> Result<Self> {
+        serde_yaml::from_str(src).map_err(|err| UsermanError::Manifest(err.to_string()))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum Kind {
+    App,
+    Role,
+    User,
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::App => write!(f, "app"),
+            Self::Role => write!(f, "role"),
+            Self::User => write!(f, "user"),
+        }
+    }
+}
+
+/// Entity owned by the manifest.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Managed {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub kind: Kind,
+    pub name: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    Create,
+    Update,
+    Delete,
+    Manage,
+    Unmanage,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Create => write!(f, "+ create"),
+            Self::Update => write!(f, "~ update"),
+            Self::Delete => write!(f, "- delete"),
+            Self::Manage => write!(f, "* manage"),
+            Self::Unmanage => write!(f, "* unmanage"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub change: Change,
+    pub kind: Kind,
+    pub name: String,
+    /// App version, only set for apps.
+    pub version: Option<u64>,
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.change, self.kind, self.name)?;
+
+        match self.version {
+            Some(t) => write!(f, " v{}", t),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Database contents a plan is computed against.
+#[derive(Clone, Debug, Default)]
+pub struct State {
+    pub apps: Vec<App>,
+    pub roles: Vec<Role>,
+    pub users: Vec<User>,
+    pub managed: Vec<Managed>,
+}
+
+impl State {
+    pub async fn read(dao: &Dao) -> Result<Self> {
+        Ok(Self {
+            apps: dao.read_all_apps().await?.into_values().collect(),
+            roles: dao.read_all_roles().await?.0.into_values().collect(),
+            users: dao.read_all_users().await?.0.into_values().collect(),
+            managed: dao.read_all_managed().await?,
+        })
+    }
+
+    fn app(&self, name: &str, version: u64) -> Option<&App> {
+        self.apps
+            .iter()
+            .find(|t| t.name == name && t.version == version)
+    }
+
+    fn role(&self, name: &str) -> Option<&Role> {
+        self.roles.iter().find(|t| t.name == name)
+    }
+
+    fn user(&self, username: &str) -> Option<&User> {
+        self.users.iter().find(|t| t.username == username)
+    }
+
+    fn is_managed(&self, id: ObjectId) -> bool {
+        self.managed.iter().any(|t| t.id == id)
+    }
+
+    fn id(&self, step: &Step) -> Option<ObjectId> {
+        match step.kind {
+            Kind::App => self
+                .app(&step.name, step.version.unwrap_or_default())
+                .map(|t| t.id()),
+            Kind::Role => self.role(&step.name).map(|t| t.id()),
+            Kind::User => self.user(&step.name).map(|t| t.id()),
+        }
+    }
+}
+
+fn check_unique<I: Iterator<Item = String>>(kind: Kind, names: I) -> Result<()> {
+    let mut seen = HashSet::new();
+
+    for name in names {
+        if !seen.insert(name.clone()) {
+            return Err(UsermanError::Manifest(format!(
+                "Duplicated {} {}.",
+                kind, name
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Mark or unmark an entry of the manifest when its flag changed.
+fn manage_step(
+    current: bool,
+    managed: bool,
+    kind: Kind,
+    name: &str,
+    version: Option<u64>,
+) -> Option<Step> {
+    let change = match (current, managed) {
+        (false, true) => Change::Manage,
+        (true, false) => Change::Unmanage,
+        _ => return None,
+    };
+
+    Some(Step {
+        change,
+        kind,
+        name: name.to_string(),
+        version,
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct Plan {
+    pub steps: Vec<Step>,
+    manifest: Manifest,
+    /// Resolved app version of every role.
+    role_apps: HashMap<String, u64>,
+}
+
+impl Plan {
+    /// Compare `manifest` with `state`. Creates and updates come first, then
+    /// deletes in reverse dependency order and finally the managed marks.
+    pub fn new(manifest: Manifest, state: &State) -> Result<Self> {
+        check_unique(
+            Kind::App,
+            manifest
+                .apps
+                .iter()
+                .map(|t| format!("{} v{}", t.name, t.version)),
+        )?;
+        check_unique(Kind::Role, manifest.roles.iter().map(|t| t.name.clone()))?;
+        check_unique(
+            Kind::User,
+            manifest.users.iter().map(|t| t.username.clone()),
+        )?;
+
+        let app_keys: HashSet<(&str, u64)> = manifest
+            .apps
+            .iter()
+            .map(|t| (t.name.as_str(), t.version))
+            .collect();
+        let role_names: HashSet<&str> = manifest.roles.iter().map(|t| t.name.as_str()).collect();
+        let usernames: HashSet<&str> = manifest.users.iter().map(|t| t.username.as_str()).collect();
+
+        // Managed entities missing from the manifest. Built in ones are kept.
+        let pruned_apps: Vec<&App> = state
+            .apps
+            .iter()
+            .filter(|t| {
+                state.is_managed(t.id())
+                    && t.name != LOCAL_APP
+                    && !app_keys.contains(&(t.name.as_str(), t.version))
+            })
+            .collect();
+        let pruned_roles: Vec<&Role> = state
+            .roles
+            .iter()
+            .filter(|t| {
+                state.is_managed(t.id())
+                    && t.name != LOCAL_ROLE
+                    && !role_names.contains(t.name.as_str())
+            })
+            .collect();
+        let pruned_users: Vec<&User> = state
+            .users
+            .iter()
+            .filter(|t| {
+                state.is_managed(t.id())
+                    && t.username != ADMIN_USERNAME
+                    && !usernames.contains(t.username.as_str())
+            })
+            .collect();
+
+        let pruned_app_ids: HashSet<ObjectId> = pruned_apps.iter().map(|t| t.id()).collect();
+        let pruned_role_ids: HashSet<ObjectId> = pruned_roles.iter().map(|t| t.id()).collect();
+
+        // Apps and roles left once the plan is applied.
+        let mut final_apps: HashMap<&str, Vec<u64>> = HashMap::new();
+
+        for (name, version) in app_keys.iter().copied().chain(
+            state
+                .apps
+                .iter()
+                .filter(|t| !pruned_app_ids.contains(&t.id()))
+                .map(|t| (t.name.as_str(), t.version)),
+        ) {
+            final_apps.entry(name).or_default().push(version);
+        }
+
+        let final_roles: HashSet<&str> = role_names
+            .iter()
+            .copied()
+            .chain(
+                state
+                    .roles
+                    .iter()
+                    .filter(|t| !pruned_role_ids.contains(&t.id()))
+                    .map(|t| t.name.as_str()),
+            )
+            .collect();
+
+        let mut upserts = vec![];
+        let mut marks = vec![];
+
+        for entry in &manifest.apps {
+            let current = state.app(&entry.name, entry.version);
+
+            match current {
+                None => upserts.push(Step {
+                    change: Change::Create,
+                    kind: Kind::App,
+                    name: entry.name.clone(),
+                    version: Some(entry.version),
+                }),
+                Some(t) if t.default_role != entry.default_role => upserts.push(Step {
+                    change: Change::Update,
+                    kind: Kind::App,
+                    name: entry.name.clone(),
+                    version: Some(entry.version),
+                }),
+                Some(_) => {}
+            }
+
+            marks.extend(manage_step(
+                matches!(current, Some(t) if state.is_managed(t.id())),
+                entry.managed,
+                Kind::App,
+                &entry.name,
+                Some(entry.version),
+            ));
+        }
+
+        let mut role_apps = HashMap::new();
+
+        for entry in &manifest.roles {
+            let versions = final_apps.get(entry.app.as_str());
+
+            let version = match (entry.app_version, versions) {
+                (Some(t), Some(v)) if v.contains(&t) => t,
+                (None, Some(v)) => v.iter().copied().max().unwrap_or_default(),
+                _ => {
+                    return Err(UsermanError::Manifest(format!(
+                        "Role {} references unknown app {}.",
+                        entry.name, entry.app
+                    )))
+                }
+            };
+
+            role_apps.insert(entry.name.clone(), version);
+
+            let current = state.role(&entry.name);
+
+            let changed = match current {
+                Some(t) => {
+                    let app = state
+                        .apps
+                        .iter()
+                        .find(|a| a.id() == t.app)
+                        .map(|a| (a.name.as_str(), a.version));
+
+                    app != Some((entry.app.as_str(), version)) || t.items != entry.items
+                }
+                None => true,
+            };
+
+            if changed {
+                upserts.push(Step {
+                    change: match current {
+                        Some(_) => Change::Update,
+                        None => Change::Create,
+                    },
+                    kind: Kind::Role,
+                    name: entry.name.clone(),
+                    version: None,
+                });
+            }
+
+            marks.extend(manage_step(
+                matches!(current, Some(t) if state.is_managed(t.id())),
+                entry.managed,
+                Kind::Role,
+                &entry.name,
+                None,
+            ));
+        }
+
+        for entry in &manifest.users {
+            if let Some(t) = entry
+                .roles
+                .iter()
+                .find(|t| !final_roles.contains(t.as_str()))
+            {
+                return Err(UsermanError::Manifest(format!(
+                    "User {} references unknown role {}.",
+                    entry.username, t
+                )));
+            }
+
+            let current = state.user(&entry.username);
+
+            let changed = match current {
+                Some(t) => {
+                    let mut roles: Vec<&str> = t
+                        .roles
+                        .iter()
+                        .filter_map(|r| state.roles.iter().find(|s| s.id() == *r))
+                        .map(|r| r.name.as_str())
+                        .collect();
+                    let mut wanted: Vec<&str> = entry.roles.iter().map(String::as_str).collect();
+
+                    roles.sort_unstable();
+                    wanted.sort_unstable();
+
+                    t.email != entry.email
+                        || t.name != entry.name
+                        || t.surname != entry.surname
+                        || t.department != entry.department
+                        || t.description != entry.description
+                        || t.enabled != entry.enabled
+                        || roles != wanted
+                }
+                None => true,
+            };
+
+            if changed {
+                upserts.push(Step {
+                    change: match current {
+                        Some(_) => Change::Update,
+                        None => Change::Create,
+                    },
+                    kind: Kind::User,
+                    name: entry.username.clone(),
+                    version: None,
+                });
+            }
+
+            marks.extend(manage_step(
+                matches!(current, Some(t) if state.is_managed(t.id())),
+                entry.managed,
+                Kind::User,
+                &entry.username,
+                None,
+            ));
+        }
+
+        // Entities kept outside the manifest can not depend on pruned ones.
+        for role in state
+            .roles
+            .iter()
+            .filter(|t| !pruned_role_ids.contains(&t.id()) && !role_names.contains(t.name.as_str()))
+        {
+            if pruned_app_ids.contains(&role.app) {
+                return Err(UsermanError::Manifest(format!(
+                    "Role {} uses an app that would be deleted.",
+                    role.name
+                )));
+            }
+        }
+
+        for user in state.users.iter().filter(|t| {
+            !pruned_users.iter().any(|p| p.id() == t.id())
+                && !usernames.contains(t.username.as_str())
+        }) {
+            if user.roles.iter().any(|t| pruned_role_ids.contains(t)) {
+                return Err(UsermanError::Manifest(format!(
+                    "User {} uses a role that would be deleted.",
+                    user.username
+                )));
+            }
+        }
+
+        let deletes = pruned_users
+            .iter()
+            .map(|t| (Kind::User, t.username.clone(), None))
+            .chain(
+                pruned_roles
+                    .iter()
+                    .map(|t| (Kind::Role, t.name.clone(), None)),
+            )
+            .chain(
+                pruned_apps
+                    .iter()
+                    .map(|t| (Kind::App, t.name.clone(), Some(t.version))),
+            )
+            .map(|(kind, name, version)| Step {
+                change: Change::Delete,
+                kind,
+                name,
+                version,
+            });
+
+        let mut steps = upserts;
+        steps.extend(deletes);
+        steps.extend(marks);
+
+        Ok(Self {
+            steps,
+            manifest,
+            role_apps,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    fn count(&self, change: Change) -> usize {
+        self.steps.iter().filter(|t| t.change == change).count()
+    }
+
+    /// Run every step in order. Each step reads the ids it needs from a
+    /// fresh state when a previous step changed them.
+    pub async fn apply(&self, dao: &Dao) -> Result<()> {
+        let mut state = State::read(dao).await?;
+        let mut stale = HashSet::new();
+
+        for step in &self.steps {
+            let needs = match (step.kind, step.change) {
+                (Kind::App, Change::Create) => vec![],
+                (Kind::Role, Change::Create) => vec![Kind::App],
+                (Kind::Role, Change::Update) => vec![Kind::App, Kind::Role],
+                (Kind::User, Change::Create) => vec![Kind::Role],
+                (Kind::User, Change::Update) => vec![Kind::Role, Kind::User],
+                (kind, _) => vec![kind],
+            };
+
+            if needs.iter().any(|t| stale.contains(t)) {
+                state = State::read(dao).await?;
+                stale.clear();
+            }
+
+            self.apply_step(dao, &state, step).await?;
+            stale.insert(step.kind);
+        }
+
+        Ok(())
+    }
+
+    async fn apply_step(&self, dao: &Dao, state: &State, step: &Step) -> Result<()> {
+        let missing = || UsermanError::Manifest(format!("Missing {} {}.", step.kind, step.name));
+
+        let id = match step.change {
+            Change::Create => None,
+            _ => Some(state.id(step).ok_or_else(missing)?),
+        };
+
+        match (step.change, id) {
+            (Change::Create | Change::Update, id) => match step.kind {
+                Kind::App => {
+                    let entry = self
+                        .manifest
+                        .apps
+                        .iter()
+                        .find(|t| t.name == step.name && Some(t.version) == step.version)
+                        .ok_or_else(missing)?;
+
+                    let app = App {
+                        id,
+                        name: entry.name.clone(),
+                        version: entry.version,
+                        default_role: entry.default_role.clone(),
+                        ..Default::default()
+                    };
+
+                    match id {
+                        Some(t) => dao.update_app_by_id(t.to_hex(), &app).await,
+                        None => dao.create_app(&app).await.map(|_| ()),
+                    }
+                }
+                Kind::Role => {
+                    let entry = self
+                        .manifest
+                        .roles
+                        .iter()
+                        .find(|t| t.name == step.name)
+                        .ok_or_else(missing)?;
+
+                    let version = self.role_apps.get(&entry.name).copied().unwrap_or_default();
+
+                    let app = state.app(&entry.app, version).ok_or_else(|| {
+                        UsermanError::Manifest(format!("Missing app {} v{}.", entry.app, version))
+                    })?;
+
+                    let role = Role {
+                        id,
+                        app: app.id(),
+                        name: entry.name.clone(),
+                        items: entry.items.clone(),
+                        ..Default::default()
+                    };
+
+                    match id {
+                        Some(t) => dao.update_role_by_id(t.to_hex(), &role).await,
+                        None => dao.create_role(&role).await.map(|_| ()),
+                    }
+                }
+                Kind::User => {
+                    let entry = self
+                        .manifest
+                        .users
+                        .iter()
+                        .find(|t| t.username == step.name)
+                        .ok_or_else(missing)?;
+
+                    let roles = entry
+                        .roles
+                        .iter()
+                        .map(|t| {
+                            state.role(t).map(|r| r.id()).ok_or_else(|| {
+                                UsermanError::Manifest(format!("Missing role {}.", t))
+                            })
+                        })
+                        .collect::<Result<Vec<ObjectId>>>()?;
+
+                    // Users are created without password, they have to set it
+                    // through the reset flow. Updates keep the current one.
+                    let user = User {
+                        id,
+                        username: entry.username.clone(),
+                        password: None,
+                        email: entry.email.clone(),
+                        name: entry.name.clone(),
+                        surname: entry.surname.clone(),
+                        description: entry.description.clone(),
+                        department: entry.department.clone(),
+                        roles,
+                        avatar: None,
+                        enabled: entry.enabled,
+                        change_password: false,
+                        created_at: None,
+                        updated_at: None,
+                    };
+
+                    match id {
+                        Some(t) => dao.update_user_by_id(t.to_hex(), &user).await,
+                        None => dao.create_user(&user).await.map(|_| ()),
+                    }
+                }
+            },
+            (Change::Delete, Some(id)) => {
+                match step.kind {
+                    Kind::App => dao.delete_app_by_id(id.to_hex()).await?,
+                    Kind::Role => dao.delete_role_by_id(id.to_hex()).await?,
+                    Kind::User => dao.delete_user_by_id(id.to_hex()).await?,
+                }
+
+                dao.delete_managed(&id).await
+            }
+            (Change::Manage, Some(id)) => {
+                dao.set_managed(&Managed {
+                    id,
+                    kind: step.kind,
+                    name: step.name.clone(),
+                })
+                .await
+            }
+            (Change::Unmanage, Some(id)) => dao.delete_managed(&id).await,
+            (_, None) => Err(missing()),
+        }
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "No changes.");
+        }
+
+        for step in &self.steps {
+            writeln!(f, "  {}", step)?;
+        }
+
+        write!(
+            f,
+            "{} to create, {} to update, {} to delete.",
+            self.count(Change::Create),
+            self.count(Change::Update),
+            self.count(Change::Delete)
+        )
+    }
+}
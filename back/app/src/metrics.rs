@@ -0,0 +1,190 @@
+//! Prometheus metrics served on `/metrics`, outside `front.publicUrl` like the
+//! probes.
+
+use axum::extract::{Extension, MatchedPath};
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use log::error;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge, register_int_gauge_vec,
+    Encoder, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, TextEncoder,
+};
+use serde::Serialize;
+use std::sync::LazyLock;
+use std::time::Instant;
+
+use crate::api::v1::Output;
+use crate::dao::Memory;
+use crate::watchers::Event;
+use crate::{Shared, UsermanError};
+
+static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "userman_http_requests_total",
+        "HTTP requests by route and status.",
+        &["method", "route", "status"]
+    )
+    .unwrap()
+});
+
+static HTTP_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "userman_http_request_duration_seconds",
+        "HTTP request latency by route and status.",
+        &["method", "route", "status"]
+    )
+    .unwrap()
+});
+
+static LOGINS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "userman_logins_total",
+        "Logins by result, success or the error.",
+        &["result"]
+    )
+    .unwrap()
+});
+
+static SESSIONS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "userman_sessions_total",
+        "Refreshes and logouts by result, success or the error.",
+        &["action", "result"]
+    )
+    .unwrap()
+});
+
+static WATCHER_EVENTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "userman_watcher_events_total",
+        "Change stream events by watcher.",
+        &["event"]
+    )
+    .unwrap()
+});
+
+static CACHE_RELOADS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "userman_cache_reloads_total",
+        "Cache reloads by watcher and result.",
+        &["event", "result"]
+    )
+    .unwrap()
+});
+
+static CACHE_ITEMS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!("userman_cache_items", "Items in each cache.", &["cache"]).unwrap()
+});
+
+static MONGO_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "userman_mongo_errors_total",
+        "MongoDB operation errors by error variant.",
+        &["error"]
+    )
+    .unwrap()
+});
+
+static REFRESH_TOKENS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!("userman_refresh_tokens", "Active refresh tokens.").unwrap()
+});
+
+/// `success`, or the variant of the error.
+fn result<T: Serialize>(output: &Output<T>) -> &'static str {
+    match output {
+        Output::Done | Output::Success(_) => "success",
+        Output::Failure(err) | Output::Unauthorized(err) => err.variant(),
+    }
+}
+
+/// Count the requests and their latency by matched route, the unmatched
+/// ones share a label to bound the series.
+pub async fn track<B>(request: Request<B>, next: Next<B>) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(t) => t.as_str().to_string(),
+        None => String::from("unmatched"),
+    };
+
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16().to_string();
+    let labels = [method.as_str(), route.as_str(), status.as_str()];
+
+    HTTP_REQUESTS.with_label_values(&labels).inc();
+    HTTP_DURATION
+        .with_label_values(&labels)
+        .observe(start.elapsed().as_secs_f64());
+
+    response
+}
+
+pub fn login<T: Serialize>(output: &Output<T>) {
+    LOGINS.with_label_values(&[result(output)]).inc();
+}
+
+/// Count a `refresh` or `logout`.
+pub fn session<T: Serialize>(action: &str, output: &Output<T>) {
+    SESSIONS.with_label_values(&[action, result(output)]).inc();
+}
+
+pub fn watcher_event(event: &Event) {
+    WATCHER_EVENTS
+        .with_label_values(&[&format!("{:?}", event)])
+        .inc();
+}
+
+pub fn cache_reload(event: &Event, result: &crate::Result<()>) {
+    let label = match result {
+        Ok(()) => "success",
+        Err(err) => err.variant(),
+    };
+
+    CACHE_RELOADS
+        .with_label_values(&[&format!("{:?}", event), label])
+        .inc();
+}
+
+/// Count the MongoDB errors, the others are ignored.
+pub fn error(err: &UsermanError) {
+    if err.is_mongo() {
+        MONGO_ERRORS.with_label_values(&[err.variant()]).inc();
+    }
+}
+
+pub async fn handler(Extension(shared): Extension<Shared>) -> impl IntoResponse {
+    CACHE_ITEMS
+        .with_label_values(&["configs"])
+        .set(shared.configs.get_all().await.len() as i64);
+    CACHE_ITEMS
+        .with_label_values(&["apps"])
+        .set(shared.apps.get_all().await.len() as i64);
+    CACHE_ITEMS
+        .with_label_values(&["roles"])
+        .set(shared.roles.get_all().await.len() as i64);
+    CACHE_ITEMS
+        .with_label_values(&["users"])
+        .set(shared.users.get_all().await.len() as i64);
+
+    match shared.dao.count_refresh_tokens().await {
+        Ok(t) => REFRESH_TOKENS.set(t as i64),
+        Err(err) => {
+            self::error(&err);
+            error!("{}", err);
+        }
+    }
+
+    let mut buffer = vec![];
+
+    match TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        Ok(()) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+            buffer,
+        )
+            .into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
@@ -0,0 +1,169 @@
+//! Paging and sorting of the in memory listings.
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use utoipa::{IntoParams, ToSchema};
+
+use userman_auth::apps::App;
+use userman_auth::roles::Role;
+
+use crate::audit::AuditEvent;
+use crate::roles::RoleName;
+use crate::users::User;
+use crate::webhooks::{Delivery, Webhook};
+use crate::{Result, UsermanError};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
+pub struct PageQuery {
+    /// Page number starting at 1. Ignored when `cursor` is set.
+    pub page: Option<usize>,
+    /// Items per page. Every item is returned when missing.
+    pub limit: Option<usize>,
+    /// Id of the last item of the previous page.
+    pub cursor: Option<String>,
+    /// Name of the field to sort by, each listing accepts its own.
+    pub sort: Option<String>,
+    pub order: Option<SortOrder>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[aliases(
+    UsersPage = Page<User>,
+    RolesPage = Page<Role>,
+    RoleNamesPage = Page<RoleName>,
+    AppsPage = Page<App>,
+    AuditPage = Page<AuditEvent>,
+    WebhooksPage = Page<Webhook>,
+    DeliveriesPage = Page<Delivery>,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct Page<T: Serialize> {
+    pub items: Vec<T>,
+    pub total: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// Order JSON values as null < bool < number < string. Strings are compared
+/// without case, arrays and objects only by kind.
+fn compare(a: &Value, b: &Value) -> Ordering {
+    fn rank(value: &Value) -> u8 {
+        match value {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::String(_) => 3,
+            Value::Array(_) => 4,
+            Value::Object(_) => 5,
+        }
+    }
+
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => a
+            .as_f64()
+            .unwrap_or_default()
+            .total_cmp(&b.as_f64().unwrap_or_default()),
+        (Value::String(a), Value::String(b)) => a.to_lowercase().cmp(&b.to_lowercase()),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+/// Sort key of a field, bson dates are compared by their milliseconds.
+fn key(value: Option<&Value>) -> Value {
+    let Some(value) = value else {
+        return Value::Null;
+    };
+
+    match value.get("$date") {
+        Some(Value::Object(t)) => t
+            .get("$numberLong")
+            .and_then(|t| t.as_str())
+            .and_then(|t| t.parse::<i64>().ok())
+            .map(Value::from)
+            .unwrap_or(Value::Null),
+        Some(t) => t.clone(),
+        None => value.clone(),
+    }
+}
+
+impl PageQuery {
+    /// Sort `items` by the requested field, one of `sorts` whose first one is
+    /// the default, and slice the requested page. `id` gives the value used as
+    /// cursor, and breaks the ties so the order is the same on every request.
+    pub fn paginate<T, F>(&self, items: Vec<T>, sorts: &[&str], id: F) -> Result<Page<T>>
+    where
+        T: Serialize,
+        F: Fn(&T) -> String,
+    {
+        let sort = match self.sort.as_deref() {
+            Some(t) if sorts.contains(&t) => t,
+            Some(t) => return Err(UsermanError::InvalidSort(t.to_string())),
+            None => sorts.first().copied().unwrap_or_default(),
+        };
+
+        let mut values: Vec<(Value, String, T)> = items
+            .into_iter()
+            .map(|t| {
+                let value = serde_json::to_value(&t).ok();
+                (key(value.as_ref().and_then(|v| v.get(sort))), id(&t), t)
+            })
+            .collect();
+
+        values.sort_by(|a, b| compare(&a.0, &b.0).then_with(|| a.1.cmp(&b.1)));
+
+        if self.order == Some(SortOrder::Desc) {
+            values.reverse();
+        }
+
+        let mut items: Vec<T> = values.into_iter().map(|(_, _, t)| t).collect();
+        let total = items.len();
+
+        let (page, start) = match (&self.cursor, self.limit) {
+            (Some(cursor), _) => match items.iter().position(|t| &id(t) == cursor) {
+                Some(t) => (None, t + 1),
+                None => return Err(UsermanError::InvalidCursor),
+            },
+            (None, Some(limit)) => {
+                let page = self.page.unwrap_or(1).max(1);
+                (Some(page), (page - 1).saturating_mul(limit).min(total))
+            }
+            (None, None) => (None, 0),
+        };
+
+        let end = match self.limit {
+            Some(t) => start.saturating_add(t).min(total),
+            None => total,
+        };
+
+        let next_cursor = match end < total && end > start {
+            true => Some(id(&items[end - 1])),
+            false => None,
+        };
+
+        items.truncate(end);
+        let items = items.split_off(start);
+
+        Ok(Page {
+            items,
+            total,
+            page,
+            limit: self.limit,
+            next_cursor,
+        })
+    }
+}
@@ -0,0 +1,176 @@
+//! Reload of config.yaml on SIGHUP or when the file, or the TLS files, change
+//! on disk. Only the logs level and the TLS material apply live, any other
+//! changed field is reported until the process restarts.
+
+use axum_server::tls_rustls::RustlsConfig;
+use log::{error, info, warn};
+use std::time::{Duration, SystemTime};
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio_util::sync::CancellationToken;
+
+use crate::config_yaml::ConfigYAML;
+use crate::logger::Handle;
+use crate::tls;
+use crate::{Result, UsermanError};
+
+/// How often the files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// SIGHUP listener.
+#[cfg(unix)]
+struct Hangup(Signal);
+
+#[cfg(unix)]
+impl Hangup {
+    fn new() -> Result<Self> {
+        signal(SignalKind::hangup())
+            .map(Self)
+            .map_err(UsermanError::SignalHangup)
+    }
+
+    async fn recv(&mut self) -> Result<()> {
+        self.0.recv().await.ok_or(UsermanError::HangupBlocking)
+    }
+}
+
+/// No SIGHUP outside unix, only the file changes reload.
+#[cfg(not(unix))]
+struct Hangup;
+
+#[cfg(not(unix))]
+impl Hangup {
+    fn new() -> Result<Self> {
+        Ok(Self)
+    }
+
+    async fn recv(&mut self) -> Result<()> {
+        std::future::pending().await
+    }
+}
+
+pub struct Reloader {
+    path: String,
+    config_yaml: ConfigYAML,
+    logger: Handle,
+    rustls: Option<RustlsConfig>,
+}
+
+impl Reloader {
+    /// `config_yaml` is the config the process started with, `rustls` the
+    /// config served when TLS is enabled.
+    pub fn new(
+        path: &str,
+        config_yaml: ConfigYAML,
+        logger: Handle,
+        rustls: Option<RustlsConfig>,
+    ) -> Self {
+        Self {
+            path: path.to_string(),
+            config_yaml,
+            logger,
+            rustls,
+        }
+    }
+
+    /// Latest modification time of the config and TLS files.
+    async fn modified(&self) -> Option<SystemTime> {
+        let mut paths = vec![self.path.as_str()];
+
+        if self.rustls.is_some() {
+            paths.push(&self.config_yaml.tls.certs);
+            paths.push(&self.config_yaml.tls.key);
+
+            if !self.config_yaml.tls.client_ca.is_empty() {
+                paths.push(&self.config_yaml.tls.client_ca);
+            }
+        }
+
+        let mut latest = None;
+
+        for path in paths {
+            let modified = tokio::fs::metadata(path)
+                .await
+                .and_then(|t| t.modified())
+                .ok();
+            latest = latest.max(modified);
+        }
+
+        latest
+    }
+
+    /// Re-read the config file and apply the fields that can change live.
+    /// The applied fields are kept, so the pending ones are reported again on
+    /// the next reload. A field failing to apply is logged without stopping
+    /// the others.
+    pub async fn reload(&mut self) -> Result<()> {
+        let new = ConfigYAML::read_file(&self.path).await?;
+        let changes = self.config_yaml.changes(&new);
+
+        if let Some(rustls) = &self.rustls {
+            if new.tls.enabled {
+                match tls::reload(rustls, &new.tls).await {
+                    Ok(()) => {
+                        info!("TLS certificates reloaded.");
+
+                        self.config_yaml.tls = new.tls;
+                    }
+                    Err(err) => error!("TLS certificates not reloaded. {}", err),
+                }
+            }
+        }
+
+        if self.config_yaml.logs != new.logs {
+            match self.logger.set_logger(&new.logs) {
+                Ok(()) => {
+                    info!("Logger in {} mode.", &new.logs.level);
+
+                    self.config_yaml.logs = new.logs;
+                }
+                Err(err) => error!("Logger not changed. {}", err),
+            }
+        }
+
+        if !changes.live.is_empty() {
+            info!("Applied {}.", changes.live.join(", "));
+        }
+
+        if !changes.restart.is_empty() {
+            warn!("Changed {}, restart to apply.", changes.restart.join(", "));
+        }
+
+        Ok(())
+    }
+
+    /// Reload until `token` is cancelled.
+    pub async fn run(mut self, token: CancellationToken) -> Result<()> {
+        let mut hangup = Hangup::new()?;
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        let mut modified = self.modified().await;
+
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => return Ok(()),
+                t = hangup.recv() => {
+                    t?;
+                    info!("Received SIGHUP, reloading {}.", self.path);
+                }
+                _ = interval.tick() => {
+                    let t = self.modified().await;
+
+                    if t == modified {
+                        continue;
+                    }
+
+                    info!("{} changed, reloading.", self.path);
+                }
+            }
+
+            if let Err(err) = self.reload().await {
+                error!("{}", err);
+            }
+
+            modified = self.modified().await;
+        }
+    }
+}
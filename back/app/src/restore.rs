@@ -0,0 +1,514 @@
+//! Restore of a full export archive into an empty or existing database. The
+//! archive ids are remapped to the ids of the restored documents and every
+//! write is reverted when one of them fails.
+use log::error;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use utoipa::ToSchema;
+
+use userman_auth::apps::App;
+use userman_auth::roles::{DataValue, Role, RoleItems};
+
+use crate::configs::Config;
+use crate::dao::Dao;
+use crate::exports::{AppRecord, Archive, RoleRecord, UserRecord};
+use crate::users::User;
+use crate::{Result, UsermanError};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Default, ToSchema, clap::ValueEnum)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictStrategy {
+    /// Keep the existing document.
+    #[default]
+    Skip,
+    /// Replace the existing document with the archived one.
+    Overwrite,
+    /// Restore the archived document under a free name.
+    Rename,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RestoreOptions {
+    pub strategy: ConflictStrategy,
+    /// Resolve every record without writing anything.
+    pub dry_run: bool,
+    /// Restore the configs and the password hashes, which hold the token
+    /// secrets and the credentials of every user.
+    pub secrets: bool,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreCount {
+    pub created: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    pub renamed: usize,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreReport {
+    pub dry_run: bool,
+    pub apps: RestoreCount,
+    pub roles: RestoreCount,
+    pub users: RestoreCount,
+    pub configs: RestoreCount,
+    /// Password hashes of the restored users.
+    pub passwords: RestoreCount,
+}
+
+/// How an archived record is written.
+enum Resolution<T> {
+    Create,
+    Rename(String),
+    Skip(T),
+    Overwrite(T),
+}
+
+/// A write of the restore and the previous document, if any, to revert it.
+enum Undo {
+    App(ObjectId, Option<App>),
+    Role(ObjectId, Option<Role>),
+    User(ObjectId, Option<User>),
+    Config(String, Option<Config>),
+}
+
+/// First of `name-2`, `name-3`... that is not taken.
+pub fn unique_name<F: Fn(&str) -> bool>(name: &str, taken: F) -> String {
+    (2..)
+        .map(|n| format!("{}-{}", name, n))
+        .find(|t| !taken(t))
+        .unwrap_or_default()
+}
+
+fn created(id: Option<ObjectId>, kind: &str, name: &str) -> Result<ObjectId> {
+    id.ok_or_else(|| UsermanError::RestoreArchive(format!("{} {} was not created.", kind, name)))
+}
+
+/// Whether `items` allow restoring the configs and the password hashes. Anyone
+/// restoring them could forge tokens or log in as any user.
+pub fn secrets_granted(items: &RoleItems) -> bool {
+    matches!(
+        items.find_value("/secrets/update.boolean"),
+        Ok(DataValue::Boolean(true))
+    )
+}
+
+/// Drop the configs and the password hashes of `archive`, restored only with
+/// `RestoreOptions::secrets`, and count them as skipped. Users overwritten
+/// without their hash keep the current one.
+pub fn withhold_secrets(archive: &mut Archive, report: &mut RestoreReport) {
+    report.configs.skipped += archive.configs.len();
+    archive.configs.clear();
+
+    for user in &mut archive.users {
+        if user.password.take().is_some() {
+            report.passwords.skipped += 1;
+        }
+    }
+}
+
+/// Check that every reference points to a record of the archive or to a
+/// document of the database.
+fn validate(
+    archive: &Archive,
+    app_ids: &HashMap<String, ObjectId>,
+    role_ids: &HashMap<String, ObjectId>,
+) -> Result<()> {
+    let apps: HashSet<&str> = archive
+        .apps
+        .iter()
+        .map(|t| t.id.as_str())
+        .chain(app_ids.keys().map(String::as_str))
+        .collect();
+
+    let roles: HashSet<&str> = archive
+        .roles
+        .iter()
+        .map(|t| t.id.as_str())
+        .chain(role_ids.keys().map(String::as_str))
+        .collect();
+
+    if let Some(t) = archive
+        .roles
+        .iter()
+        .find(|t| !apps.contains(t.app.as_str()))
+    {
+        return Err(UsermanError::RestoreArchive(format!(
+            "Role {} references unknown app {}.",
+            t.name, t.app
+        )));
+    }
+
+    for user in &archive.users {
+        if let Some(t) = user.roles.iter().find(|t| !roles.contains(t.as_str())) {
+            return Err(UsermanError::RestoreArchive(format!(
+                "User {} references unknown role {}.",
+                user.username, t
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+struct Restore<'a> {
+    dao: &'a Dao,
+    options: RestoreOptions,
+    report: RestoreReport,
+    undo: Vec<Undo>,
+    /// Archive ids, and ids already in the database, to restored ids.
+    app_ids: HashMap<String, ObjectId>,
+    role_ids: HashMap<String, ObjectId>,
+}
+
+impl Restore<'_> {
+    fn resolve<T: Clone, F: Fn(&str) -> bool>(
+        &self,
+        current: Option<&T>,
+        name: &str,
+        taken: F,
+    ) -> Resolution<T> {
+        match (current, self.options.strategy) {
+            (None, _) => Resolution::Create,
+            (Some(t), ConflictStrategy::Skip) => Resolution::Skip(t.clone()),
+            (Some(t), ConflictStrategy::Overwrite) => Resolution::Overwrite(t.clone()),
+            (Some(_), ConflictStrategy::Rename) => Resolution::Rename(unique_name(name, taken)),
+        }
+    }
+
+    async fn apps(
+        &mut self,
+        records: Vec<AppRecord>,
+        existing: HashMap<ObjectId, App>,
+    ) -> Result<()> {
+        let mut existing: HashMap<(String, u64), App> = existing
+            .into_values()
+            .map(|t| ((t.name.clone(), t.version), t))
+            .collect();
+
+        for record in records {
+            let mut app = App {
+                id: None,
+                name: record.name,
+                version: record.version,
+                default_role: record.default_role,
+                ..Default::default()
+            };
+
+            let resolution = self.resolve(
+                existing.get(&(app.name.clone(), app.version)),
+                &app.name,
+                |t| existing.contains_key(&(t.to_string(), app.version)),
+            );
+
+            let id = match resolution {
+                Resolution::Skip(t) => {
+                    self.report.apps.skipped += 1;
+                    t.id()
+                }
+                Resolution::Overwrite(t) => {
+                    app.id = t.id;
+
+                    if !self.options.dry_run {
+                        self.dao.update_app_by_id(t.id().to_hex(), &app).await?;
+                        self.undo.push(Undo::App(t.id(), Some(t.clone())));
+                    }
+
+                    self.report.apps.updated += 1;
+                    t.id()
+                }
+                Resolution::Create | Resolution::Rename(_) => {
+                    match resolution {
+                        Resolution::Rename(t) => {
+                            app.name = t;
+                            self.report.apps.renamed += 1;
+                        }
+                        _ => self.report.apps.created += 1,
+                    }
+
+                    let id = match self.options.dry_run {
+                        true => ObjectId::new(),
+                        false => {
+                            let id = created(self.dao.create_app(&app).await?, "App", &app.name)?;
+                            self.undo.push(Undo::App(id, None));
+                            id
+                        }
+                    };
+
+                    app.id = Some(id);
+                    existing.insert((app.name.clone(), app.version), app);
+                    id
+                }
+            };
+
+            self.app_ids.insert(record.id, id);
+        }
+
+        Ok(())
+    }
+
+    async fn roles(
+        &mut self,
+        records: Vec<RoleRecord>,
+        existing: HashMap<String, Role>,
+    ) -> Result<()> {
+        let mut existing = existing;
+
+        for record in records {
+            let mut role = Role {
+                id: None,
+                app: self.app_ids[&record.app],
+                name: record.name,
+                items: record.items,
+                ..Default::default()
+            };
+
+            let resolution = self.resolve(existing.get(&role.name), &role.name, |t| {
+                existing.contains_key(t)
+            });
+
+            let id = match resolution {
+                Resolution::Skip(t) => {
+                    self.report.roles.skipped += 1;
+                    t.id()
+                }
+                Resolution::Overwrite(t) => {
+                    role.id = t.id;
+
+                    if !self.options.dry_run {
+                        self.dao.update_role_by_id(t.id().to_hex(), &role).await?;
+                        self.undo.push(Undo::Role(t.id(), Some(t.clone())));
+                    }
+
+                    self.report.roles.updated += 1;
+                    t.id()
+                }
+                Resolution::Create | Resolution::Rename(_) => {
+                    match resolution {
+                        Resolution::Rename(t) => {
+                            role.name = t;
+                            self.report.roles.renamed += 1;
+                        }
+                        _ => self.report.roles.created += 1,
+                    }
+
+                    let id = match self.options.dry_run {
+                        true => ObjectId::new(),
+                        false => {
+                            let id =
+                                created(self.dao.create_role(&role).await?, "Role", &role.name)?;
+                            self.undo.push(Undo::Role(id, None));
+                            id
+                        }
+                    };
+
+                    role.id = Some(id);
+                    existing.insert(role.name.clone(), role);
+                    id
+                }
+            };
+
+            self.role_ids.insert(record.id, id);
+        }
+
+        Ok(())
+    }
+
+    async fn users(
+        &mut self,
+        records: Vec<UserRecord>,
+        existing: HashMap<String, User>,
+    ) -> Result<()> {
+        let mut existing = existing;
+
+        for record in records {
+            let mut user = User {
+                id: None,
+                username: record.username,
+                password: record.password,
+                email: record.email,
+                name: record.name,
+                surname: record.surname,
+                description: record.description,
+                department: record.department,
+                roles: record.roles.iter().map(|t| self.role_ids[t]).collect(),
+                avatar: None,
+                enabled: record.enabled,
+                change_password: false,
+                created_at: None,
+                updated_at: None,
+            };
+
+            let resolution = self.resolve(existing.get(&user.username), &user.username, |t| {
+                existing.contains_key(t)
+            });
+
+            match resolution {
+                Resolution::Skip(_) => self.report.users.skipped += 1,
+                Resolution::Overwrite(t) => {
+                    user.id = t.id;
+
+                    if !self.options.dry_run {
+                        self.dao.update_user_by_id(t.id().to_hex(), &user).await?;
+                        self.undo.push(Undo::User(t.id(), Some(t.clone())));
+                    }
+
+                    if user.password.is_some() {
+                        self.report.passwords.updated += 1;
+                    }
+
+                    self.report.users.updated += 1;
+                }
+                Resolution::Create | Resolution::Rename(_) => {
+                    match resolution {
+                        Resolution::Rename(t) => {
+                            user.username = t;
+                            self.report.users.renamed += 1;
+                        }
+                        _ => self.report.users.created += 1,
+                    }
+
+                    if user.password.is_some() {
+                        self.report.passwords.created += 1;
+                    }
+
+                    if !self.options.dry_run {
+                        let id =
+                            created(self.dao.create_user(&user).await?, "User", &user.username)?;
+                        self.undo.push(Undo::User(id, None));
+                        user.id = Some(id);
+                    }
+
+                    existing.insert(user.username.clone(), user);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn configs(&mut self, configs: Vec<Config>, secrets: bool) -> Result<()> {
+        // Archives without secrets only hold masked token secrets.
+        if !secrets {
+            self.report.configs.skipped += configs.len();
+            return Ok(());
+        }
+
+        let existing = self.dao.read_all_configs().await?;
+
+        for config in configs {
+            match existing.get(&config.id) {
+                Some(t) if self.options.strategy == ConflictStrategy::Overwrite => {
+                    if !self.options.dry_run {
+                        self.dao.update_config(&config).await?;
+                        self.undo.push(Undo::Config(
+                            config.id(),
+                            Some(Config {
+                                id: config.id(),
+                                data: t.clone(),
+                            }),
+                        ));
+                    }
+
+                    self.report.configs.updated += 1;
+                }
+                Some(_) => self.report.configs.skipped += 1,
+                None => {
+                    if !self.options.dry_run {
+                        self.dao.create_config(&config).await?;
+                        self.undo.push(Undo::Config(config.id(), None));
+                    }
+
+                    self.report.configs.created += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Revert every write in reverse order. Failures are only logged so the
+    /// remaining writes are still reverted.
+    async fn rollback(&mut self) {
+        while let Some(undo) = self.undo.pop() {
+            let result = match undo {
+                Undo::App(id, Some(t)) => self.dao.update_app_by_id(id.to_hex(), &t).await,
+                Undo::App(id, None) => self.dao.delete_app_by_id(id.to_hex()).await,
+                Undo::Role(id, Some(t)) => self.dao.update_role_by_id(id.to_hex(), &t).await,
+                Undo::Role(id, None) => self.dao.delete_role_by_id(id.to_hex()).await,
+                Undo::User(id, Some(t)) => {
+                    let password = t.password.is_some();
+
+                    match self.dao.update_user_by_id(id.to_hex(), &t).await {
+                        Ok(_) if !password => self.dao.reset_user_password_by_id(id.to_hex()).await,
+                        result => result,
+                    }
+                }
+                Undo::User(id, None) => self.dao.delete_user_by_id(id.to_hex()).await,
+                Undo::Config(_, Some(t)) => self.dao.update_config(&t).await,
+                Undo::Config(id, None) => self.dao.delete_config(&id).await,
+            };
+
+            if let Err(err) = result {
+                error!("Could not roll back restore. {}", err);
+            }
+        }
+    }
+}
+
+/// Restore apps, roles, users and, with secrets, configs of `archive`.
+/// Conflicts on app name and version, role name and username are resolved
+/// with `options.strategy`.
+pub async fn restore(
+    dao: &Dao,
+    mut archive: Archive,
+    options: RestoreOptions,
+) -> Result<RestoreReport> {
+    let apps = dao.read_all_apps().await?;
+    let (roles, roles_by_name) = dao.read_all_roles().await?;
+    let (_, users_by_username) = dao.read_all_users().await?;
+
+    let app_ids: HashMap<String, ObjectId> = apps.keys().map(|t| (t.to_hex(), *t)).collect();
+    let role_ids: HashMap<String, ObjectId> = roles.keys().map(|t| (t.to_hex(), *t)).collect();
+
+    validate(&archive, &app_ids, &role_ids)?;
+
+    let mut report = RestoreReport {
+        dry_run: options.dry_run,
+        ..Default::default()
+    };
+
+    if !options.secrets {
+        withhold_secrets(&mut archive, &mut report);
+    }
+
+    let mut restore = Restore {
+        dao,
+        options,
+        report,
+        undo: vec![],
+        app_ids,
+        role_ids,
+    };
+
+    let result = async {
+        restore.apps(archive.apps, apps).await?;
+        restore.roles(archive.roles, roles_by_name).await?;
+        restore.users(archive.users, users_by_username).await?;
+        restore
+            .configs(archive.configs, archive.header.secrets)
+            .await
+    }
+    .await;
+
+    match result {
+        Ok(_) => Ok(restore.report),
+        Err(err) => {
+            restore.rollback().await;
+            Err(err)
+        }
+    }
+}
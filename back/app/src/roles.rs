@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use utoipa::ToSchema;
 
 use userman_auth::roles::{Role, RoleItems};
 
@@ -55,7 +56,7 @@ impl From<&Role> for RoleDB {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct RoleName {
     #[serde(
@@ -63,6 +64,7 @@ pub struct RoleName {
         skip_serializing_if = "Option::is_none",
         serialize_with = "serialize_option_oid_as_string"
     )]
+    #[schema(value_type = String)]
     pub id: Option<ObjectId>,
     pub name: String,
 }
@@ -82,6 +84,18 @@ pub struct Roles {
     roles_by_name: Arc<RwLock<HashMap<String, Role>>>,
 }
 
+impl From<Vec<Role>> for Roles {
+    fn from(values: Vec<Role>) -> Self {
+        let roles = values.iter().map(|t| (t.id(), t.clone())).collect();
+        let roles_by_name = values.into_iter().map(|t| (t.name.clone(), t)).collect();
+
+        Self {
+            roles: Arc::new(RwLock::new(roles)),
+            roles_by_name: Arc::new(RwLock::new(roles_by_name)),
+        }
+    }
+}
+
 #[async_trait]
 impl Memory<Role> for Roles {
     async fn load(dao: &Dao) -> Result<Self> {
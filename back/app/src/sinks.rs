@@ -0,0 +1,398 @@
+//! Forwarding of the audit events to the sinks selected in `config.yaml`: a
+//! rotating JSON-lines file, syslog (RFC 5424) and an HTTP endpoint. Events go
+//! through a bounded queue per sink so a slow one never delays a request nor the
+//! other sinks.
+
+use async_trait::async_trait;
+use log::{error, warn, Record};
+use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
+use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
+use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
+use log4rs::append::rolling_file::RollingFileAppender;
+use log4rs::append::Append;
+use log4rs::encode::pattern::PatternEncoder;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+use syslog::{Facility, Formatter5424, LoggerBackend};
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+use crate::audit::{AuditEvent, AuditResult};
+use crate::config_yaml::{Audit, AuditFile, AuditHttp, Syslog};
+use crate::{Result, UsermanError};
+
+const QUEUE_SIZE: usize = 10_000;
+const FLUSH_PERIOD: Duration = Duration::from_secs(1);
+
+/// Enterprise number reserved for documentation by RFC 5612, used in the id of
+/// the structured data.
+const SD_ID: &str = "audit@32473";
+
+/// Sending side of the queues, one per enabled sink. A no-op when none is.
+#[derive(Clone, Default)]
+pub struct Sinks(Vec<(&'static str, Sender<AuditEvent>)>);
+
+impl Sinks {
+    /// Queue `event` for every sink, dropping it on those that can't keep up.
+    pub fn send(&self, event: AuditEvent) {
+        for (name, sender) in &self.0 {
+            match sender.try_send(event.clone()) {
+                Ok(_) => {}
+                Err(TrySendError::Full(t)) => {
+                    warn!("Audit {} queue full, {} not forwarded.", name, t.action)
+                }
+                Err(TrySendError::Closed(t)) => {
+                    warn!("Audit {} sink stopped, {} not forwarded.", name, t.action)
+                }
+            }
+        }
+    }
+}
+
+/// Build the enabled sinks, the [`Forwarder`] is `None` when there is none.
+/// Queued events are dropped once `drain` has elapsed on shutdown.
+pub fn channel(audit: &Audit, drain: Duration) -> Result<(Sinks, Option<Forwarder>)> {
+    let mut sinks: Vec<(&'static str, Box<dyn Sink>, Duration)> = vec![];
+
+    if audit.file.enabled {
+        sinks.push(("file", Box::new(FileSink::new(&audit.file)?), FLUSH_PERIOD));
+    }
+
+    if audit.syslog.enabled {
+        let sink = SyslogSink::new(&audit.syslog)?;
+        sinks.push(("syslog", Box::new(sink), FLUSH_PERIOD));
+    }
+
+    if audit.http.enabled {
+        let period = Duration::from_secs(audit.http.flush_interval);
+        sinks.push(("http", Box::new(HttpSink::new(&audit.http)?), period));
+    }
+
+    if sinks.is_empty() {
+        return Ok((Sinks::default(), None));
+    }
+
+    let mut senders = vec![];
+    let mut queues = vec![];
+
+    for (name, sink, period) in sinks {
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+
+        senders.push((name, sender));
+        queues.push(Queue {
+            name,
+            receiver,
+            sink,
+            period,
+        });
+    }
+
+    Ok((Sinks(senders), Some(Forwarder { queues, drain })))
+}
+
+/// Destination of the audit events.
+#[async_trait]
+trait Sink: Send {
+    async fn forward(&mut self, event: AuditEvent) -> Result<()>;
+
+    /// Called periodically and once the queue is drained.
+    async fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct FileSink(RollingFileAppender);
+
+impl FileSink {
+    pub fn new(config: &AuditFile) -> Result<Self> {
+        let size_trigger = SizeTrigger::new(config.rotation.max_size_mb * 1024 * 1024);
+        let window_roller = FixedWindowRoller::builder()
+            .build(
+                &format!("{}.{{}}.gz", config.path),
+                config.rotation.archives,
+            )
+            .map_err(|err| UsermanError::AuditSink(err.to_string()))?;
+
+        let policy = CompoundPolicy::new(Box::new(size_trigger), Box::new(window_roller));
+
+        RollingFileAppender::builder()
+            .encoder(Box::new(PatternEncoder::new("{m}{n}")))
+            .build(&config.path, Box::new(policy))
+            .map(Self)
+            .map_err(|err| UsermanError::AuditSink(err.to_string()))
+    }
+
+    pub fn send(&self, event: &AuditEvent) -> Result<()> {
+        let line = serde_json::to_string(event).map_err(UsermanError::CreateJSON)?;
+
+        self.0
+            .append(&Record::builder().args(format_args!("{}", line)).build())
+            .map_err(|err| UsermanError::AuditSink(err.to_string()))
+    }
+
+    pub fn flush(&self) {
+        self.0.flush();
+    }
+}
+
+#[async_trait]
+impl Sink for FileSink {
+    async fn forward(&mut self, event: AuditEvent) -> Result<()> {
+        self.send(&event)
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        FileSink::flush(self);
+        Ok(())
+    }
+}
+
+pub struct SyslogSink(syslog::Logger<LoggerBackend, Formatter5424>);
+
+/// Escape a structured data parameter value, RFC 5424 section 6.3.3.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace(']', "\\]")
+}
+
+impl SyslogSink {
+    pub fn new(syslog: &Syslog) -> Result<Self> {
+        let formatter = Formatter5424 {
+            facility: Facility::LOG_AUTH,
+            hostname: None,
+            process: String::from("userman"),
+            pid: std::process::id(),
+        };
+
+        let logger = match syslog.address.is_empty() {
+            true => syslog::unix(formatter),
+            false => syslog::udp(formatter, "0.0.0.0:0", &syslog.address),
+        }
+        .map_err(|err| UsermanError::AuditSink(err.to_string()))?;
+
+        Ok(Self(logger))
+    }
+
+    pub fn send(&mut self, event: &AuditEvent) -> Result<()> {
+        let line = serde_json::to_string(event).map_err(UsermanError::CreateJSON)?;
+
+        let mut params = HashMap::from([
+            (String::from("action"), escape(&event.action)),
+            (String::from("result"), escape(&event.result.to_string())),
+        ]);
+
+        if let Some(t) = &event.actor {
+            params.insert(String::from("actor"), escape(t));
+        }
+
+        if let Some(t) = &event.request_id {
+            params.insert(String::from("requestId"), escape(t));
+        }
+
+        let data = HashMap::from([(SD_ID.to_string(), params)]);
+        let message = (1, data, line);
+
+        match event.result {
+            AuditResult::Success => self.0.notice(message),
+            AuditResult::Failure | AuditResult::Unauthorized => self.0.warning(message),
+        }
+        .map_err(|err| UsermanError::AuditSink(err.to_string()))
+    }
+}
+
+#[async_trait]
+impl Sink for SyslogSink {
+    async fn forward(&mut self, event: AuditEvent) -> Result<()> {
+        self.send(&event)
+    }
+}
+
+pub struct HttpSink {
+    client: reqwest::Client,
+    config: AuditHttp,
+    batch: Vec<AuditEvent>,
+}
+
+impl HttpSink {
+    pub fn new(config: &AuditHttp) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+
+        for (name, value) in &config.headers {
+            let name = HeaderName::from_str(name)
+                .map_err(|err| UsermanError::AuditSink(format!("{}: {}", name, err)))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|err| UsermanError::AuditSink(format!("{}: {}", name, err)))?;
+
+            headers.insert(name, value);
+        }
+
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .timeout(Duration::from_secs(config.timeout))
+            .build()
+            .map_err(|err| UsermanError::AuditSink(err.to_string()))?;
+
+        Ok(Self {
+            client,
+            config: config.clone(),
+            batch: vec![],
+        })
+    }
+
+    /// Add `event` to the batch, sent once full.
+    pub async fn send(&mut self, event: AuditEvent) -> Result<()> {
+        self.batch.push(event);
+
+        match self.batch.len() >= self.config.batch_size {
+            true => self.flush().await,
+            false => Ok(()),
+        }
+    }
+
+    async fn post(&self) -> Result<()> {
+        self.client
+            .post(&self.config.url)
+            .json(&self.batch)
+            .send()
+            .await
+            .and_then(|t| t.error_for_status())
+            .map(|_| ())
+            .map_err(|err| UsermanError::AuditSink(err.to_string()))
+    }
+
+    /// Send the pending events, retrying with an exponential backoff. The
+    /// batch is dropped once the retries are exhausted.
+    pub async fn flush(&mut self) -> Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let mut delay = Duration::from_millis(self.config.retry_delay_ms);
+        let mut result = self.post().await;
+
+        for _ in 0..self.config.retries {
+            if result.is_ok() {
+                break;
+            }
+
+            tokio::time::sleep(delay).await;
+            delay = delay.saturating_mul(2);
+            result = self.post().await;
+        }
+
+        if result.is_err() {
+            error!("{} audit event(s) not forwarded.", self.batch.len());
+        }
+
+        self.batch.clear();
+        result
+    }
+}
+
+#[async_trait]
+impl Sink for HttpSink {
+    async fn forward(&mut self, event: AuditEvent) -> Result<()> {
+        self.send(event).await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        HttpSink::flush(self).await
+    }
+}
+
+/// Receiving side of the queue of one sink.
+struct Queue {
+    name: &'static str,
+    receiver: Receiver<AuditEvent>,
+    sink: Box<dyn Sink>,
+    period: Duration,
+}
+
+impl Queue {
+    async fn forward(&mut self, event: AuditEvent) {
+        if let Err(err) = self.sink.forward(event).await {
+            error!("{}", err);
+        }
+    }
+
+    async fn flush(&mut self) {
+        if let Err(err) = self.sink.flush().await {
+            error!("{}", err);
+        }
+    }
+
+    /// Forward the events until `token` is cancelled, then the queued ones.
+    async fn run(mut self, token: CancellationToken) -> &'static str {
+        let mut interval = tokio::time::interval(self.period);
+
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                event = self.receiver.recv() => match event {
+                    Some(t) => self.forward(t).await,
+                    None => break,
+                },
+                _ = interval.tick() => self.flush().await,
+            }
+        }
+
+        self.receiver.close();
+
+        while let Ok(t) = self.receiver.try_recv() {
+            self.forward(t).await;
+        }
+
+        self.flush().await;
+
+        self.name
+    }
+}
+
+/// Feeds every sink from its own task, so a slow one never holds the others.
+pub struct Forwarder {
+    queues: Vec<Queue>,
+    drain: Duration,
+}
+
+impl Forwarder {
+    /// Forward the events until `token` is cancelled, then the queued ones for
+    /// at most the drain duration.
+    pub async fn run(self, token: CancellationToken) -> Result<()> {
+        let mut tasks = JoinSet::new();
+
+        for t in self.queues {
+            tasks.spawn(t.run(token.clone()));
+        }
+
+        tokio::select! {
+            biased;
+            _ = token.cancelled() => {}
+            Some(t) = tasks.join_next() => {
+                if let Ok(name) = t {
+                    error!("Audit {} sink stopped.", name);
+                }
+
+                return Err(UsermanError::TaskStopped);
+            }
+        }
+
+        let drained = tokio::time::timeout(self.drain, async {
+            while tasks.join_next().await.is_some() {}
+        })
+        .await;
+
+        if drained.is_err() {
+            warn!(
+                "Audit sinks not drained in {}s, queued events dropped.",
+                self.drain.as_secs()
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+//! Server-sent events of the permission changes. The watchers publish every
+//! user, role and app change once the caches are reloaded, and each stream
+//! forwards the ones in the scope of its client so it can drop the `RoleItems`
+//! it cached.
+
+use axum::response::sse::Event as SseEvent;
+use chrono::Utc;
+use futures::future;
+use futures::stream::{self, Stream};
+use mongodb::bson::oid::ObjectId;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::dao::Memory;
+use crate::roles::Roles;
+use crate::users::{User, Users};
+use crate::watchers::{Change, Event, Operation};
+use crate::{serialize_option_oid_as_string, Shared};
+
+/// Events kept for the slow streams before they lag.
+const CAPACITY: usize = 256;
+/// Name of the SSE events carrying a [`PermissionEvent`].
+pub const PERMISSIONS: &str = "permissions";
+/// Name of the SSE event sent when some events were missed, every cached
+/// permission should be dropped.
+pub const LAGGED: &str = "lagged";
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum EntityKind {
+    User,
+    Role,
+    App,
+}
+
+/// Roles and apps whose permissions depend on a changed entity.
+#[derive(Clone, Debug, Default)]
+pub struct Related {
+    pub roles: HashSet<ObjectId>,
+    pub apps: HashSet<ObjectId>,
+}
+
+impl Related {
+    pub fn extend(&mut self, other: Related) {
+        self.roles.extend(other.roles);
+        self.apps.extend(other.apps);
+    }
+}
+
+/// Roles and apps of the cached entity of `change`: the role and its app for a
+/// role, the roles and their apps for a user.
+pub async fn related(shared: &Shared, change: &Change) -> Related {
+    let mut related = Related::default();
+
+    let Some(id) = change.id else {
+        return related;
+    };
+
+    let roles = match change.event {
+        Event::Apps => {
+            related.apps.insert(id);
+            vec![]
+        }
+        Event::Roles => vec![id],
+        Event::Users => match shared.users.get_by_id(&id).await {
+            Some(t) => t.roles,
+            None => vec![],
+        },
+        Event::Configs => vec![],
+    };
+
+    for role_id in roles {
+        related.roles.insert(role_id);
+
+        if let Some(t) = shared.roles.get_by_id(&role_id).await {
+            related.apps.insert(t.app);
+        }
+    }
+
+    related
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PermissionEvent {
+    pub kind: EntityKind,
+    /// Missing when the whole collection changed.
+    #[schema(value_type = String)]
+    pub operation: Option<Operation>,
+    /// Missing when the whole collection changed.
+    #[serde(serialize_with = "serialize_option_oid_as_string")]
+    #[schema(value_type = String)]
+    pub id: Option<ObjectId>,
+    #[serde(skip)]
+    related: Related,
+}
+
+impl PermissionEvent {
+    pub fn new(change: &Change, related: Related) -> Option<Self> {
+        let kind = match change.event {
+            Event::Users => EntityKind::User,
+            Event::Roles => EntityKind::Role,
+            Event::Apps => EntityKind::App,
+            Event::Configs => return None,
+        };
+
+        Some(Self {
+            kind,
+            operation: change.operation,
+            id: change.id,
+            related,
+        })
+    }
+}
+
+/// Entities a stream is notified about. The users are followed with their
+/// roles and apps, the authenticated one by default.
+#[derive(Debug, Default)]
+pub struct Subscription {
+    pub username: Option<String>,
+    pub users: HashSet<ObjectId>,
+    pub roles: HashSet<ObjectId>,
+    pub apps: HashSet<ObjectId>,
+    /// Unix time the session token expires at, the stream ends then.
+    pub expires_at: Option<i64>,
+}
+
+/// Whether `event` changes the permissions of `user`, read again on each event
+/// as its roles can change.
+async fn follows(roles: &Roles, user: &User, event: &PermissionEvent) -> bool {
+    if event.kind == EntityKind::User && event.id == user.id {
+        return true;
+    }
+
+    let mut apps = HashSet::new();
+
+    for role_id in &user.roles {
+        if event.related.roles.contains(role_id) {
+            return true;
+        }
+
+        if let Some(t) = roles.get_by_id(role_id).await {
+            apps.insert(t.app);
+        }
+    }
+
+    !apps.is_disjoint(&event.related.apps)
+}
+
+impl Subscription {
+    /// Whether `event` concerns the requested roles or apps. Every client is
+    /// concerned when the whole collection changed.
+    pub fn requested(&self, event: &PermissionEvent) -> bool {
+        event.id.is_none()
+            || !self.roles.is_disjoint(&event.related.roles)
+            || !self.apps.is_disjoint(&event.related.apps)
+    }
+
+    pub async fn matches(&self, users: &Users, roles: &Roles, event: &PermissionEvent) -> bool {
+        if self.requested(event) {
+            return true;
+        }
+
+        let mut followed = vec![];
+
+        if let Some(t) = &self.username {
+            followed.extend(users.get(t).await);
+        }
+
+        for id in &self.users {
+            followed.extend(users.get_by_id(id).await);
+        }
+
+        for user in followed {
+            if follows(roles, &user, event).await {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Whether the authenticated user still exists and is enabled.
+    pub async fn active(&self, users: &Users) -> bool {
+        match &self.username {
+            Some(t) => matches!(users.get(t).await, Some(t) if t.enabled),
+            None => true,
+        }
+    }
+
+    /// When the stream has to end as the session token expires.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.expires_at.map(|t| {
+            let left = t.saturating_sub(Utc::now().timestamp()).max(0);
+            Instant::now() + Duration::from_secs(left as u64)
+        })
+    }
+}
+
+/// Publishing side of the streams, closed on shutdown so the open streams end.
+#[derive(Clone)]
+pub struct Streams {
+    sender: broadcast::Sender<PermissionEvent>,
+    token: CancellationToken,
+}
+
+impl Default for Streams {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+            token: CancellationToken::new(),
+        }
+    }
+}
+
+impl Streams {
+    /// Send `change` to the open streams, if it changes any permission.
+    pub fn publish(&self, change: &Change, related: Related) {
+        if let Some(t) = PermissionEvent::new(change, related) {
+            // No stream is open when it fails.
+            let _ = self.sender.send(t);
+        }
+    }
+
+    pub fn close(&self) {
+        self.token.cancel();
+    }
+
+    /// SSE events of `subscription` until the streams are closed, its token
+    /// expires or its user is no longer enabled.
+    pub fn subscribe(
+        &self,
+        shared: Shared,
+        subscription: Subscription,
+    ) -> impl Stream<Item = Result<SseEvent, serde_json::Error>> {
+        let state = (
+            self.sender.subscribe(),
+            self.token.clone(),
+            shared,
+            subscription,
+        );
+
+        stream::unfold(
+            state,
+            |(mut receiver, token, shared, subscription)| async move {
+                let deadline = subscription.deadline();
+                let expired = async move {
+                    match deadline {
+                        Some(t) => tokio::time::sleep_until(t).await,
+                        None => future::pending().await,
+                    }
+                };
+                tokio::pin!(expired);
+
+                loop {
+                    let received = tokio::select! {
+                        _ = token.cancelled() => return None,
+                        _ = &mut expired => return None,
+                        t = receiver.recv() => t,
+                    };
+
+                    // Disabling or deleting the user is itself a change.
+                    if !subscription.active(&shared.users).await {
+                        return None;
+                    }
+
+                    let event = match received {
+                        Ok(t) if subscription.matches(&shared.users, &shared.roles, &t).await => {
+                            SseEvent::default().event(PERMISSIONS).json_data(&t)
+                        }
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(_)) => Ok(SseEvent::default().event(LAGGED).data("")),
+                        Err(RecvError::Closed) => return None,
+                    };
+
+                    return Some((event, (receiver, token, shared, subscription)));
+                }
+            },
+        )
+    }
+}
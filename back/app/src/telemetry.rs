@@ -0,0 +1,135 @@
+//! OpenTelemetry tracing of the requests, handlers, permission checks and DAO
+//! calls. Spans are exported with OTLP over HTTP, or printed to stdout for
+//! local debugging.
+
+use axum::extract::MatchedPath;
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, KeyValue};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{self, Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use serde::{Deserialize, Serialize};
+use tracing::{info_span, Instrument, Level};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::config_yaml::ConfigYAML;
+use crate::{Result, UsermanError, VERSION};
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum Exporter {
+    #[default]
+    None,
+    Otlp,
+    Stdout,
+}
+
+impl std::fmt::Display for Exporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Otlp => write!(f, "otlp"),
+            Self::Stdout => write!(f, "stdout"),
+        }
+    }
+}
+
+fn trace_config(config_yaml: &ConfigYAML) -> trace::Config {
+    trace::config().with_resource(Resource::new(vec![
+        KeyValue::new("service.name", "userman"),
+        KeyValue::new("service.version", VERSION),
+        KeyValue::new("service.instance.id", config_yaml.name.clone()),
+    ]))
+}
+
+fn tracer(config_yaml: &ConfigYAML) -> Result<Option<Tracer>> {
+    let tracing = &config_yaml.tracing;
+
+    let tracer = match tracing.exporter {
+        Exporter::None => return Ok(None),
+        Exporter::Otlp => opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .http()
+                    .with_endpoint(&tracing.endpoint),
+            )
+            .with_trace_config(trace_config(config_yaml))
+            .install_batch(runtime::Tokio)
+            .map_err(|err| UsermanError::Tracing(err.to_string()))?,
+        Exporter::Stdout => {
+            let provider = TracerProvider::builder()
+                .with_simple_exporter(opentelemetry_stdout::SpanExporter::default())
+                .with_config(trace_config(config_yaml))
+                .build();
+            let tracer = provider.tracer("userman");
+            global::set_tracer_provider(provider);
+            tracer
+        }
+    };
+
+    Ok(Some(tracer))
+}
+
+/// Install the exporter of `tracing.exporter`, nothing is traced with `none`.
+/// Only the spans of this crate are exported.
+pub fn init(config_yaml: &ConfigYAML) -> Result<()> {
+    let Some(tracer) = tracer(config_yaml)? else {
+        return Ok(());
+    };
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .with(Targets::new().with_target(env!("CARGO_CRATE_NAME"), Level::INFO));
+
+    // Not `try_init`, it would also bridge the `log` records while log4rs is
+    // already the global logger.
+    tracing::subscriber::set_global_default(subscriber)
+        .map_err(|err| UsermanError::Tracing(err.to_string()))?;
+
+    log::info!("Exporting traces to {}.", config_yaml.tracing.exporter);
+
+    Ok(())
+}
+
+/// Flush the pending spans.
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+/// Open the span of a request, child of the W3C `traceparent` header when
+/// present.
+pub async fn trace<B>(request: Request<B>, next: Next<B>) -> Response {
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(t) => t.as_str().to_string(),
+        None => String::from("unmatched"),
+    };
+
+    let span = info_span!(
+        "request",
+        otel.name = format!("{} {}", request.method(), route),
+        http.method = %request.method(),
+        http.route = route,
+        http.status_code = tracing::field::Empty,
+    );
+    span.set_parent(parent);
+
+    let response = next.run(request).instrument(span.clone()).await;
+
+    span.record("http.status_code", response.status().as_u16());
+
+    response
+}
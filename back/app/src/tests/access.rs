@@ -0,0 +1,48 @@
+use axum::http::{HeaderMap, HeaderValue};
+use std::net::IpAddr;
+
+use crate::access::client_ip;
+
+fn ip(raw: &str) -> IpAddr {
+    raw.parse().unwrap()
+}
+
+#[test]
+fn untrusted_peer() {
+    let mut headers = HeaderMap::new();
+    headers.insert("x-forwarded-for", HeaderValue::from_static("1.1.1.1"));
+
+    assert_eq!(
+        client_ip(Some(ip("10.0.0.1")), &headers, &[]),
+        Some(ip("10.0.0.1"))
+    );
+}
+
+#[test]
+fn trusted_proxies() {
+    let trusted = [ip("10.0.0.1"), ip("10.0.0.2")];
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "x-forwarded-for",
+        HeaderValue::from_static("6.6.6.6, 1.1.1.1, 10.0.0.2"),
+    );
+
+    assert_eq!(
+        client_ip(Some(ip("10.0.0.1")), &headers, &trusted),
+        Some(ip("1.1.1.1"))
+    );
+
+    let mut headers = HeaderMap::new();
+    headers.insert("x-real-ip", HeaderValue::from_static("2.2.2.2"));
+
+    assert_eq!(
+        client_ip(Some(ip("10.0.0.1")), &headers, &trusted),
+        Some(ip("2.2.2.2"))
+    );
+
+    assert_eq!(
+        client_ip(Some(ip("10.0.0.1")), &HeaderMap::new(), &trusted),
+        Some(ip("10.0.0.1"))
+    );
+}
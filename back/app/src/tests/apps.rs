@@ -0,0 +1,112 @@
+use mongodb::bson::oid::ObjectId;
+use serde_json::json;
+
+use userman_auth::apps::App;
+
+use crate::api::v1::apps::{AppsFilter, SORTS};
+use crate::pages::PageQuery;
+
+fn app(name: &str, version: u64) -> App {
+    App {
+        id: Some(ObjectId::new()),
+        name: name.to_string(),
+        version,
+        ..Default::default()
+    }
+}
+
+fn apps() -> Vec<App> {
+    vec![
+        app("billing", 1),
+        app("billing", 3),
+        app("Billing-Reports", 1),
+        app("billing", 2),
+        app("crm", 1),
+    ]
+}
+
+fn filter(value: serde_json::Value) -> AppsFilter {
+    serde_json::from_value(value).unwrap()
+}
+
+fn versions(apps: &[App]) -> Vec<(&str, u64)> {
+    apps.iter().map(|t| (t.name.as_str(), t.version)).collect()
+}
+
+#[test]
+fn filter_none() {
+    assert_eq!(filter(json!({})).apply(apps()).len(), 5);
+}
+
+#[test]
+fn filter_name() {
+    let mut apps = filter(json!({ "name": "BILL" })).apply(apps());
+    apps.sort_by_key(|t| (t.name.clone(), t.version));
+
+    assert_eq!(
+        versions(&apps),
+        vec![
+            ("Billing-Reports", 1),
+            ("billing", 1),
+            ("billing", 2),
+            ("billing", 3)
+        ]
+    );
+}
+
+#[test]
+fn filter_latest() {
+    let mut apps = filter(json!({ "latest": true })).apply(apps());
+    apps.sort_by_key(|t| t.name.clone());
+
+    assert_eq!(
+        versions(&apps),
+        vec![("Billing-Reports", 1), ("billing", 3), ("crm", 1)]
+    );
+
+    let apps = filter(json!({ "name": "billing", "latest": false })).apply(self::apps());
+
+    assert_eq!(apps.len(), 4);
+}
+
+#[test]
+fn filter_pages() {
+    let apps = filter(json!({ "latest": true })).apply(apps());
+
+    let mut cursor = None;
+    let mut seen = Vec::new();
+
+    loop {
+        let query = PageQuery {
+            limit: Some(2),
+            cursor: cursor.clone(),
+            ..Default::default()
+        };
+
+        let page = query
+            .paginate(apps.clone(), SORTS, |t| t.id().to_hex())
+            .unwrap();
+
+        assert_eq!(page.total, 3);
+
+        seen.extend(
+            versions(&page.items)
+                .into_iter()
+                .map(|(n, v)| (n.to_string(), v)),
+        );
+
+        match page.next_cursor {
+            Some(t) => cursor = Some(t),
+            None => break,
+        }
+    }
+
+    assert_eq!(
+        seen,
+        vec![
+            ("billing".to_string(), 3),
+            ("Billing-Reports".to_string(), 1),
+            ("crm".to_string(), 1)
+        ]
+    );
+}
@@ -0,0 +1,78 @@
+use mongodb::bson::doc;
+use serde_json::json;
+
+use crate::audit::{diff, AuditQuery, AuditResult};
+
+#[test]
+fn diff_fields() {
+    let before = json!({
+        "id": "1",
+        "username": "user",
+        "password": "hash",
+        "enabled": true,
+        "profile": { "name": "Name", "surname": "Surname" },
+        "roles": ["a"],
+    });
+    let after = json!({
+        "id": "1",
+        "username": "user",
+        "enabled": false,
+        "profile": { "name": "Name", "department": "IT" },
+        "roles": ["a", "b"],
+    });
+
+    let changes = diff(Some(&before), Some(&after));
+    let paths: Vec<&str> = changes.iter().map(|t| t.path.as_str()).collect();
+
+    assert_eq!(
+        paths,
+        vec!["enabled", "profile.department", "profile.surname", "roles"]
+    );
+
+    assert_eq!(changes[0].before, Some(json!(true)));
+    assert_eq!(changes[0].after, Some(json!(false)));
+    assert_eq!(changes[1].before, None);
+    assert_eq!(changes[2].after, None);
+    assert_eq!(changes[3].after, Some(json!(["a", "b"])));
+}
+
+#[test]
+fn diff_created() {
+    let after = json!({ "name": "app", "password": "secret" });
+
+    let changes = diff(None, Some(&after));
+
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].path, "name");
+    assert_eq!(changes[0].before, None);
+}
+
+#[test]
+fn query_filter() {
+    let query = AuditQuery {
+        actor: Some("admin".to_string()),
+        target_kind: Some("user".to_string()),
+        result: Some(AuditResult::Failure),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        query.filter().unwrap(),
+        doc! { "actor": "admin", "target.kind": "user", "result": "failure" }
+    );
+
+    let query = AuditQuery {
+        from: Some("2024-01-01T00:00:00Z".to_string()),
+        ..Default::default()
+    };
+
+    assert!(query.filter().unwrap().get_document("createdAt").is_ok());
+
+    let query = AuditQuery {
+        to: Some("yesterday".to_string()),
+        ..Default::default()
+    };
+
+    assert!(query.filter().is_err());
+    assert_eq!(AuditQuery::default().limit(), 50);
+}
@@ -0,0 +1,67 @@
+use image::{DynamicImage, ImageOutputFormat};
+use std::io::Cursor;
+
+use crate::avatars::{
+    identicon, identicon_seed, nearest_size, resize, AVATAR_MAX_SIDE, AVATAR_SIZES,
+    DEFAULT_AVATAR_SIZE,
+};
+use crate::UsermanError;
+
+#[test]
+fn resize_avatars() {
+    let mut src = Cursor::new(vec![]);
+
+    DynamicImage::new_rgb8(300, 200)
+        .write_to(&mut src, ImageOutputFormat::Png)
+        .unwrap();
+
+    let avatars = resize(src.get_ref()).unwrap();
+
+    assert_eq!(avatars.len(), AVATAR_SIZES.len());
+
+    for avatar in avatars {
+        let image = image::load_from_memory(&avatar.data).unwrap();
+
+        assert_eq!(image.width(), avatar.size);
+        assert_eq!(image.height(), avatar.size);
+    }
+}
+
+#[test]
+fn resize_oversized() {
+    let mut src = Cursor::new(vec![]);
+
+    DynamicImage::new_luma8(AVATAR_MAX_SIDE + 1, 1)
+        .write_to(&mut src, ImageOutputFormat::Png)
+        .unwrap();
+
+    assert!(matches!(resize(src.get_ref()), Err(UsermanError::Image(_))));
+    assert!(matches!(
+        resize(b"not an image"),
+        Err(UsermanError::Image(_))
+    ));
+}
+
+#[test]
+fn nearest_avatar_size() {
+    assert_eq!(nearest_size(None), DEFAULT_AVATAR_SIZE);
+    assert_eq!(nearest_size(Some(1)), 32);
+    assert_eq!(nearest_size(Some(100)), 128);
+    assert_eq!(nearest_size(Some(4096)), 256);
+}
+
+#[test]
+fn identicon_avatars() {
+    let seed = identicon_seed("Admin");
+
+    assert_eq!(seed, identicon_seed("admin"));
+    assert_ne!(seed, identicon_seed("operator"));
+
+    for size in AVATAR_SIZES {
+        let data = identicon(seed, size).unwrap();
+        let image = image::load_from_memory(&data).unwrap();
+
+        assert_eq!(image.width(), size);
+        assert_eq!(data, identicon(seed, size).unwrap());
+    }
+}
@@ -0,0 +1,37 @@
+use clap::Parser;
+
+use crate::cli::{Cli, Command};
+
+#[test]
+fn parse_admin_commands() {
+    let cli = Cli::try_parse_from(["userman", "reset-password", "jdoe"]).unwrap();
+
+    assert!(matches!(
+        cli.command,
+        Some(Command::ResetPassword { ref username, password: None }) if username == "jdoe"
+    ));
+
+    let cli = Cli::try_parse_from(["userman", "create-admin"]).unwrap();
+
+    assert!(matches!(
+        cli.command,
+        Some(Command::CreateAdmin { ref username, .. }) if username == "admin"
+    ));
+}
+
+#[test]
+fn parse_import_alias() {
+    let cli = Cli::try_parse_from(["userman", "import", "backup.ndjson", "--dry-run"]).unwrap();
+
+    assert!(matches!(
+        cli.command,
+        Some(Command::Restore { dry_run: true, .. })
+    ));
+}
+
+#[test]
+fn parse_default_command() {
+    let cli = Cli::try_parse_from(["userman"]).unwrap();
+
+    assert!(cli.command.is_none());
+}
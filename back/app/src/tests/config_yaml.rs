@@ -0,0 +1,180 @@
+use serde_yaml::Value;
+
+use crate::config_yaml::ConfigYAML;
+use crate::logger::{LogsFormat, LogsLevel};
+use crate::UsermanError;
+
+fn vars(src: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+    src.iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+#[tokio::test]
+async fn env_overrides() {
+    let value: Value = serde_yaml::from_str("port: 9000\nmongoDb:\n  dbName: users\n").unwrap();
+
+    let config = ConfigYAML::from_value(
+        value,
+        vars(&[
+            ("USERMAN_PORT", "9100"),
+            ("USERMAN_NAME", "1234"),
+            ("USERMAN_MONGO_DB__URI", "mongodb://db:27017"),
+            ("USERMAN_FRONT__PUBLIC_URL", "/userman"),
+            ("USERMAN_UNKNOWN", "ignored"),
+            ("HOME", "/root"),
+        ]),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(config.port, 9100);
+    assert_eq!(config.name, "1234");
+    assert_eq!(config.mongo_db.uri, "mongodb://db:27017");
+    assert_eq!(config.mongo_db.db_name, "users");
+    assert_eq!(config.front.public_url, "/userman");
+}
+
+#[tokio::test]
+async fn secret_files() {
+    let path = std::env::temp_dir().join(format!("userman-uri-{}", std::process::id()));
+    std::fs::write(&path, "mongodb://user:secret@db:27017\n").unwrap();
+
+    let value: Value =
+        serde_yaml::from_str(&format!("mongoDb:\n  uriFile: {}\n", path.display())).unwrap();
+
+    let config = ConfigYAML::from_value(value, vars(&[])).await.unwrap();
+
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(config.mongo_db.uri, "mongodb://user:secret@db:27017");
+}
+
+#[tokio::test]
+async fn missing_secret_file() {
+    let value: Value = serde_yaml::from_str("front:\n  publicUrl: /userman\n").unwrap();
+
+    let result = ConfigYAML::from_value(
+        value,
+        vars(&[("USERMAN_MONGO_DB__URI_FILE", "/missing/userman/uri")]),
+    )
+    .await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn invalid_config() {
+    let value: Value = serde_yaml::from_str(
+        "prot: 9000\nport: http\nmongoDb:\n  uri: localhost:27017\nfront:\n  publicUrl: /userman/\n",
+    )
+    .unwrap();
+
+    let Err(UsermanError::InvalidConfig(report)) = ConfigYAML::from_value(value, vars(&[])).await
+    else {
+        panic!("config should be invalid");
+    };
+
+    let paths: Vec<&str> = report.0.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(paths, ["prot", "port"]);
+
+    let value: Value = serde_yaml::from_str(
+        "mongoDb:\n  uri: localhost:27017\ntls:\n  enabled: true\n  clientAuthRequired: true\n  clientUsers:\n    ops: admin\nfront:\n  publicUrl: /userman/\n",
+    )
+    .unwrap();
+
+    let Err(UsermanError::InvalidConfig(report)) = ConfigYAML::from_value(value, vars(&[])).await
+    else {
+        panic!("config should be invalid");
+    };
+
+    let paths: Vec<&str> = report.0.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(
+        paths,
+        [
+            "mongoDb.uri",
+            "tls.certs",
+            "tls.key",
+            "tls.clientAuthRequired",
+            "tls.clientUsers",
+            "front.publicUrl",
+        ]
+    );
+}
+
+#[test]
+fn changes() {
+    let config = ConfigYAML::default();
+
+    let mut new = config.clone();
+    new.port += 1;
+    new.logs.level = LogsLevel::Debug;
+    new.tls.certs = String::from("certs.pem");
+    new.front.public_url = String::from("/userman");
+
+    let changes = config.changes(&new);
+
+    assert_eq!(changes.live, ["logs"]);
+    assert_eq!(changes.restart, ["port", "tls.certs", "front.publicUrl"]);
+
+    let mut enabled = config.clone();
+    enabled.tls.enabled = true;
+
+    let mut new = enabled.clone();
+    new.tls.key = String::from("key.pem");
+
+    let changes = enabled.changes(&new);
+
+    assert_eq!(changes.live, ["tls.key"]);
+    assert!(changes.restart.is_empty());
+}
+
+#[tokio::test]
+async fn logs() {
+    let value: Value = serde_yaml::from_str("logs: debug\n").unwrap();
+    let config = ConfigYAML::from_value(value, vars(&[])).await.unwrap();
+
+    assert_eq!(config.logs.level, LogsLevel::Debug);
+    assert_eq!(config.logs.directory, "logs");
+
+    let value: Value = serde_yaml::from_str(
+        "logs:\n  format: json\n  modules:\n    userman::dao: trace\n    mongodb: warn\n",
+    )
+    .unwrap();
+    let config = ConfigYAML::from_value(value, vars(&[])).await.unwrap();
+
+    assert_eq!(config.logs.format, LogsFormat::Json);
+    assert_eq!(config.logs.modules["userman::dao"], LogsLevel::Trace);
+    assert_eq!(config.logs.modules["mongodb"], LogsLevel::Warn);
+
+    let value: Value = serde_yaml::from_str("logs:\n  modules:\n    mongodb: loud\n").unwrap();
+
+    let Err(UsermanError::InvalidConfig(report)) = ConfigYAML::from_value(value, vars(&[])).await
+    else {
+        panic!("config should be invalid");
+    };
+
+    assert_eq!(report.0[0].path, "logs.modules.mongodb");
+}
+
+#[tokio::test]
+async fn logs_env() {
+    let env = vars(&[("USERMAN_LOGS", "debug")]);
+    let config = ConfigYAML::from_value(Value::Null, env).await.unwrap();
+
+    assert_eq!(config.logs.level, LogsLevel::Debug);
+
+    let value: Value = serde_yaml::from_str("logs: warn\n").unwrap();
+    let env = vars(&[("USERMAN_LOGS__FORMAT", "json")]);
+    let config = ConfigYAML::from_value(value, env).await.unwrap();
+
+    assert_eq!(config.logs.level, LogsLevel::Warn);
+    assert_eq!(config.logs.format, LogsFormat::Json);
+
+    let value: Value = serde_yaml::from_str("logs:\n  format: json\n").unwrap();
+    let env = vars(&[("USERMAN_LOGS", "trace")]);
+    let config = ConfigYAML::from_value(value, env).await.unwrap();
+
+    assert_eq!(config.logs.level, LogsLevel::Trace);
+}
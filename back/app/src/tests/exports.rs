@@ -0,0 +1,130 @@
+use chrono::Utc;
+
+use userman_auth::roles::RoleItems;
+
+use crate::configs::Config;
+use crate::dao::with_item;
+use crate::exports::{
+    Archive, ArchiveEntry, ArchiveHeader, ExportFormat, RoleRecord, UserRecord, ARCHIVE_VERSION,
+};
+use crate::imports::{parse_rows, ImportFormat};
+use crate::restore::{secrets_granted, unique_name, withhold_secrets, RestoreReport};
+
+fn archive() -> Archive {
+    Archive {
+        header: ArchiveHeader {
+            version: ARCHIVE_VERSION,
+            userman: "0.0.0".to_string(),
+            created_at: Utc::now(),
+            secrets: false,
+        },
+        apps: vec![],
+        roles: vec![RoleRecord {
+            id: "role1".to_string(),
+            app: "app1".to_string(),
+            name: "admin".to_string(),
+            items: Default::default(),
+            created_at: None,
+            updated_at: None,
+        }],
+        users: vec![UserRecord {
+            id: "user1".to_string(),
+            username: "jdoe".to_string(),
+            password: None,
+            email: "jdoe@example.com".to_string(),
+            name: "John".to_string(),
+            surname: "Doe".to_string(),
+            description: String::new(),
+            department: "IT".to_string(),
+            roles: vec!["role1".to_string()],
+            enabled: true,
+            created_at: None,
+            updated_at: None,
+        }],
+        configs: vec![],
+    }
+}
+
+#[test]
+fn export_ndjson() {
+    let raw = archive().into_string(ExportFormat::Ndjson).unwrap();
+
+    let entries: Vec<ArchiveEntry> = raw
+        .lines()
+        .map(|t| serde_json::from_str(t).unwrap())
+        .collect();
+
+    assert_eq!(entries.len(), 3);
+    assert!(matches!(entries[0], ArchiveEntry::Header(ref t) if t.version == ARCHIVE_VERSION));
+    assert!(matches!(entries[2], ArchiveEntry::User(ref t) if t.username == "jdoe"));
+}
+
+#[test]
+fn export_csv_matches_import() {
+    let raw = archive().into_string(ExportFormat::Csv).unwrap();
+
+    let rows = parse_rows(&raw, ImportFormat::Csv).unwrap();
+    let row = rows[0].as_ref().unwrap();
+
+    assert_eq!(rows.len(), 1);
+    assert_eq!(row.username, "jdoe");
+    assert_eq!(row.roles, Some(vec!["admin".to_string()]));
+    assert_eq!(row.enabled, Some(true));
+}
+
+#[test]
+fn parse_ndjson() {
+    let raw = archive().into_string(ExportFormat::Ndjson).unwrap();
+
+    let archive = Archive::parse(&raw, ExportFormat::Ndjson).unwrap();
+
+    assert_eq!(archive.roles.len(), 1);
+    assert_eq!(archive.users[0].roles, vec!["role1"]);
+}
+
+#[test]
+fn parse_rejects_newer_version() {
+    let mut archive = archive();
+    archive.header.version = ARCHIVE_VERSION + 1;
+
+    let raw = archive.into_string(ExportFormat::Json).unwrap();
+
+    assert!(Archive::parse(&raw, ExportFormat::Json).is_err());
+    assert!(Archive::parse("", ExportFormat::Csv).is_err());
+}
+
+#[test]
+fn unique_names() {
+    let taken = ["admin", "admin-2"];
+
+    assert_eq!(unique_name("admin", |t| taken.contains(&t)), "admin-3");
+}
+
+#[test]
+fn restore_withholds_secrets() {
+    let mut archive = archive();
+    archive.header.secrets = true;
+    archive.users[0].password = Some("$2b$12$hash".to_string());
+    archive.configs = vec![Config::new_token()];
+
+    let mut report = RestoreReport::default();
+
+    withhold_secrets(&mut archive, &mut report);
+
+    assert!(archive.configs.is_empty());
+    assert_eq!(archive.users[0].password, None);
+    assert_eq!(report.configs.skipped, 1);
+    assert_eq!(report.passwords.skipped, 1);
+}
+
+#[test]
+fn restore_secrets_permission() {
+    let items = RoleItems::new(vec![]);
+    let granted = with_item(&items, "secrets", &["read", "update"]).unwrap();
+
+    assert!(!secrets_granted(&items));
+    assert!(secrets_granted(&granted));
+    assert!(!secrets_granted(
+        &with_item(&items, "secrets", &["read"]).unwrap()
+    ));
+}
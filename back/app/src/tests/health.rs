@@ -0,0 +1,28 @@
+use crate::health::Health;
+
+#[test]
+fn watcher_guards() {
+    let health = Health::default();
+    assert_eq!(health.watchers(), 0);
+
+    let first = health.watcher();
+    let second = health.clone().watcher();
+    assert_eq!(health.watchers(), 2);
+
+    drop(first);
+    assert_eq!(health.watchers(), 1);
+
+    // A watcher task that ends, even by a panic, drops its guard.
+    let task = std::thread::spawn({
+        let health = health.clone();
+        move || {
+            let _guard = health.watcher();
+            panic!("watcher failed");
+        }
+    });
+    assert!(task.join().is_err());
+    assert_eq!(health.watchers(), 1);
+
+    drop(second);
+    assert_eq!(health.watchers(), 0);
+}
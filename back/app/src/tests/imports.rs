@@ -0,0 +1,211 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::Document;
+use std::collections::HashMap;
+
+use crate::imports::{parse_rows, resolve, ImportFormat, ImportOptions, ImportWrite, ResolvedRow};
+use crate::users::User;
+
+#[test]
+fn parse_csv_rows() {
+    let src = "username,email,name,surname,department,roles\n\
+               jdoe, jdoe@example.com ,John,Doe,IT,admin; viewer\n\
+               asmith,asmith@example.com,Anna,Smith,,\n";
+
+    let rows: Vec<_> = parse_rows(src, ImportFormat::Csv)
+        .unwrap()
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].email, "jdoe@example.com");
+    assert_eq!(
+        rows[0].roles,
+        Some(vec!["admin".to_string(), "viewer".to_string()])
+    );
+    assert_eq!(rows[0].description, None);
+    assert_eq!(rows[1].department, None);
+    assert_eq!(rows[1].roles, None);
+    assert_eq!(rows[1].enabled, None);
+}
+
+#[test]
+fn parse_json_rows() {
+    let src = r#"[{
+        "username": "jdoe",
+        "email": "jdoe@example.com",
+        "name": "John",
+        "surname": "Doe",
+        "roles": ["admin"],
+        "enabled": false
+    }]"#;
+
+    let rows = parse_rows(src, ImportFormat::Json).unwrap();
+    let row = rows[0].as_ref().unwrap();
+
+    assert_eq!(row.roles, Some(vec!["admin".to_string()]));
+    assert_eq!(row.enabled, Some(false));
+    assert_eq!(row.department, None);
+
+    assert!(parse_rows("username\njdoe\n", ImportFormat::Csv).is_err());
+}
+
+#[test]
+fn parse_invalid_rows() {
+    let src = "username,email,name,surname,enabled\n\
+               jdoe,jdoe@example.com,John,Doe,maybe\n\
+               asmith,asmith@example.com,Anna,Smith,true\n\
+               bwayne,bwayne@example.com\n";
+
+    let rows = parse_rows(src, ImportFormat::Csv).unwrap();
+
+    assert_eq!(rows.len(), 3);
+    assert!(matches!(rows[0], Err(ref t) if t.row == 1 && t.username == "jdoe"));
+    assert!(matches!(rows[1], Ok(ref t) if t.username == "asmith"));
+    assert!(matches!(rows[2], Err(ref t) if t.row == 3));
+
+    let src = r#"[
+        {"username": "jdoe", "email": "jdoe@example.com", "name": "John", "surname": "Doe"},
+        {"username": "asmith", "email": 42}
+    ]"#;
+
+    let rows = parse_rows(src, ImportFormat::Json).unwrap();
+
+    assert!(rows[0].is_ok());
+    assert!(matches!(rows[1], Err(ref t) if t.row == 2 && t.username == "asmith"));
+
+    assert!(parse_rows("{}", ImportFormat::Json).is_err());
+}
+
+/// Users `jdoe`, disabled, and `asmith`, and the `viewer` role.
+fn existing() -> (HashMap<String, User>, HashMap<String, ObjectId>) {
+    let users = [("jdoe", false), ("asmith", true)]
+        .into_iter()
+        .map(|(username, enabled)| {
+            let user = User {
+                id: Some(ObjectId::new()),
+                username: username.to_string(),
+                description: "Existing".to_string(),
+                enabled,
+                ..Default::default()
+            };
+
+            (username.to_string(), user)
+        })
+        .collect();
+
+    let roles = HashMap::from([("viewer".to_string(), ObjectId::new())]);
+
+    (users, roles)
+}
+
+fn fields(resolved: &ResolvedRow) -> &Document {
+    match &resolved.write {
+        ImportWrite::Update(_, t) => t,
+        ImportWrite::Create(_) => panic!("{} created", resolved.username),
+    }
+}
+
+const ROSTER: &str = "username,email,name,surname,roles\n\
+                      jdoe,jdoe@example.com,John,Doe,viewer\n\
+                      bwayne,bwayne@example.com,Bruce,Wayne,\n";
+
+#[test]
+fn resolve_conflicts() {
+    let (users, roles) = existing();
+    let rows = parse_rows(ROSTER, ImportFormat::Csv).unwrap();
+
+    let (report, resolved) = resolve(rows, &users, &roles, ImportOptions::default());
+
+    assert_eq!((report.created, report.updated), (1, 0));
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].row, 1);
+    assert_eq!(report.errors[0].error, "Username already exists.");
+
+    assert_eq!(resolved.len(), 1);
+    assert!(matches!(
+        resolved[0].write,
+        ImportWrite::Create(ref t) if t.username == "bwayne" && t.enabled && t.roles.is_empty()
+    ));
+}
+
+#[test]
+fn resolve_upsert_keeps_missing_fields() {
+    let (users, roles) = existing();
+    let rows = parse_rows(ROSTER, ImportFormat::Csv).unwrap();
+    let options = ImportOptions {
+        upsert: true,
+        ..Default::default()
+    };
+
+    let (report, resolved) = resolve(rows, &users, &roles, options);
+
+    assert_eq!((report.created, report.updated), (1, 1));
+    assert!(report.errors.is_empty());
+
+    let jdoe = fields(&resolved[0]);
+
+    assert!(matches!(resolved[0].write, ImportWrite::Update(t, _) if t == users["jdoe"].id()));
+    assert_eq!(jdoe.get_str("email").unwrap(), "jdoe@example.com");
+    assert_eq!(jdoe.get_array("roles").unwrap().len(), 1);
+    assert!(!jdoe.contains_key("enabled"));
+    assert!(!jdoe.contains_key("description"));
+    assert!(!jdoe.contains_key("department"));
+
+    let src = r#"[{
+        "username": "asmith",
+        "email": "asmith@example.com",
+        "name": "Anna",
+        "surname": "Smith",
+        "description": "",
+        "roles": [],
+        "enabled": false
+    }]"#;
+
+    let rows = parse_rows(src, ImportFormat::Json).unwrap();
+    let (_, resolved) = resolve(rows, &users, &roles, options);
+    let asmith = fields(&resolved[0]);
+
+    assert!(!asmith.get_bool("enabled").unwrap());
+    assert_eq!(asmith.get_str("description").unwrap(), "");
+    assert!(asmith.get_array("roles").unwrap().is_empty());
+}
+
+#[test]
+fn resolve_dry_run() {
+    let (users, roles) = existing();
+    let src = "username,email,name,surname,roles\n\
+               jdoe,jdoe@example.com,John,Doe,viewer\n\
+               jdoe,jdoe@example.com,John,Doe,\n\
+               ckent,ckent,Clark,Kent,\n\
+               dprince,dprince@example.com,Diana,Prince,editor\n\
+               bwayne,bwayne@example.com,Bruce,Wayne,\n";
+
+    let rows = parse_rows(src, ImportFormat::Csv).unwrap();
+    let options = ImportOptions {
+        dry_run: true,
+        upsert: true,
+    };
+
+    let (report, resolved) = resolve(rows, &users, &roles, options);
+    let value = serde_json::to_value(&report).unwrap();
+
+    assert_eq!(value["dryRun"], true);
+    assert_eq!((report.created, report.updated), (1, 1));
+    assert_eq!(resolved.len(), 2);
+
+    let errors: Vec<(usize, &str)> = report
+        .errors
+        .iter()
+        .map(|t| (t.row, t.error.as_str()))
+        .collect();
+
+    assert_eq!(
+        errors,
+        vec![
+            (2, "Duplicated username in file."),
+            (3, "Invalid email ckent."),
+            (4, "Unknown role editor."),
+        ]
+    );
+}
@@ -0,0 +1,91 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::config_yaml::Logs;
+use crate::logger::{self, Handle, LogsLevel};
+
+static HANDLE: Mutex<Option<Handle>> = Mutex::new(None);
+
+/// The global logger, installed on first use as it can only be once per
+/// process.
+pub fn handle() -> Handle {
+    let mut handle = HANDLE.lock().unwrap_or_else(|err| err.into_inner());
+
+    handle
+        .get_or_insert_with(|| {
+            let directory =
+                std::env::temp_dir().join(format!("userman-logs-{}", std::process::id()));
+
+            logger::build_with(Logs {
+                directory: directory.to_string_lossy().to_string(),
+                ..Default::default()
+            })
+        })
+        .clone()
+}
+
+/// Runtime levels revert once their delay elapses, unless a newer change
+/// replaced them. The levels are global, so a single test.
+#[tokio::test(start_paused = true)]
+async fn revert_levels() {
+    let handle = handle();
+
+    let levels = handle
+        .set_level(LogsLevel::Debug, None, Some(Duration::from_secs(60)))
+        .unwrap();
+
+    assert_eq!(levels.level, LogsLevel::Debug);
+    assert!(levels.revert_at.is_some());
+
+    tokio::time::sleep(Duration::from_secs(61)).await;
+
+    let levels = handle.levels();
+
+    assert_eq!(levels.level, LogsLevel::Info);
+    assert!(levels.revert_at.is_none());
+
+    // The first timer fires at 60s and must leave the newer change alone.
+    handle
+        .set_level(LogsLevel::Warn, None, Some(Duration::from_secs(60)))
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_secs(30)).await;
+
+    handle
+        .set_level(
+            LogsLevel::Trace,
+            Some("userman::web".to_string()),
+            Some(Duration::from_secs(60)),
+        )
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_secs(31)).await;
+
+    let levels = handle.levels();
+
+    assert_eq!(levels.level, LogsLevel::Warn);
+    assert_eq!(levels.modules.get("userman::web"), Some(&LogsLevel::Trace));
+
+    tokio::time::sleep(Duration::from_secs(30)).await;
+
+    let levels = handle.levels();
+
+    assert_eq!(levels.level, LogsLevel::Info);
+    assert!(levels.modules.is_empty());
+
+    // A reset drops the runtime levels and cancels the pending revert.
+    handle
+        .set_level(LogsLevel::Error, None, Some(Duration::from_secs(60)))
+        .unwrap();
+
+    let levels = handle.reset().unwrap();
+
+    assert_eq!(levels.level, LogsLevel::Info);
+    assert!(levels.revert_at.is_none());
+
+    handle.set_level(LogsLevel::Debug, None, None).unwrap();
+
+    tokio::time::sleep(Duration::from_secs(61)).await;
+
+    assert_eq!(handle.levels().level, LogsLevel::Debug);
+}
@@ -0,0 +1,139 @@
+use mongodb::bson::oid::ObjectId;
+
+use userman_auth::apps::App;
+use userman_auth::roles::Role;
+
+use crate::manifest::{Change, Kind, Managed, Manifest, Plan, State};
+use crate::users::User;
+
+const MANIFEST: &str = r#"
+apps:
+  - name: billing
+    version: 1
+    managed: true
+roles:
+  - name: billing-operator
+    app: billing
+    managed: true
+users:
+  - username: svc-billing
+    email: svc-billing@example.com
+    name: Billing
+    surname: Service
+    roles: [billing-operator]
+    managed: true
+"#;
+
+fn state() -> State {
+    let app = App {
+        id: Some(ObjectId::new()),
+        name: "billing".to_string(),
+        version: 1,
+        ..Default::default()
+    };
+
+    let role = Role {
+        id: Some(ObjectId::new()),
+        app: app.id(),
+        name: "billing-operator".to_string(),
+        ..Default::default()
+    };
+
+    let user = User {
+        id: Some(ObjectId::new()),
+        username: "svc-billing".to_string(),
+        password: None,
+        email: "svc-billing@example.com".to_string(),
+        name: "Billing".to_string(),
+        surname: "Service".to_string(),
+        description: String::new(),
+        department: String::new(),
+        roles: vec![role.id()],
+        ..Default::default()
+    };
+
+    let managed = vec![
+        Managed {
+            id: app.id(),
+            kind: Kind::App,
+            name: app.name.clone(),
+        },
+        Managed {
+            id: role.id(),
+            kind: Kind::Role,
+            name: role.name.clone(),
+        },
+        Managed {
+            id: user.id(),
+            kind: Kind::User,
+            name: user.username.clone(),
+        },
+    ];
+
+    State {
+        apps: vec![app],
+        roles: vec![role],
+        users: vec![user],
+        managed,
+    }
+}
+
+#[test]
+fn plan_create() {
+    let manifest = Manifest::parse(MANIFEST).unwrap();
+    let plan = Plan::new(manifest, &State::default()).unwrap();
+
+    let creates: Vec<Kind> = plan
+        .steps
+        .iter()
+        .filter(|t| t.change == Change::Create)
+        .map(|t| t.kind)
+        .collect();
+
+    assert_eq!(creates, vec![Kind::App, Kind::Role, Kind::User]);
+    assert_eq!(
+        plan.steps
+            .iter()
+            .filter(|t| t.change == Change::Manage)
+            .count(),
+        3
+    );
+}
+
+#[test]
+fn plan_no_changes() {
+    let manifest = Manifest::parse(MANIFEST).unwrap();
+    let plan = Plan::new(manifest, &state()).unwrap();
+
+    assert!(plan.is_empty());
+}
+
+#[test]
+fn plan_prune_managed_only() {
+    let mut state = state();
+    state.managed.retain(|t| t.kind != Kind::User);
+
+    let plan = Plan::new(Manifest::default(), &state);
+
+    // The unmanaged user still uses the role that would be deleted.
+    assert!(plan.is_err());
+
+    state.users.clear();
+
+    let plan = Plan::new(Manifest::default(), &state).unwrap();
+
+    let deletes: Vec<Kind> = plan.steps.iter().map(|t| t.kind).collect();
+
+    assert!(plan.steps.iter().all(|t| t.change == Change::Delete));
+    assert_eq!(deletes, vec![Kind::Role, Kind::App]);
+}
+
+#[test]
+fn plan_unknown_role() {
+    let manifest = Manifest::parse(
+        r#"{"users": [{"username": "a", "email": "a@b.c", "name": "A", "surname": "B", "roles": ["missing"]}]}"#,
+    )
+    .unwrap();
+
+    assert!(Plan::new(manifest, &State::default()).is_err());
+}
@@ -0,0 +1,29 @@
+use crate::config_yaml::ConfigReport;
+use crate::UsermanError;
+
+#[test]
+fn error_variant() {
+    assert_eq!(
+        UsermanError::InvalidCredentials.variant(),
+        "InvalidCredentials"
+    );
+    assert_eq!(
+        UsermanError::RestoreArchive(String::from("bad (archive)")).variant(),
+        "RestoreArchive"
+    );
+    assert_eq!(
+        UsermanError::InvalidConfig(ConfigReport::default()).variant(),
+        "InvalidConfig"
+    );
+}
+
+#[test]
+fn error_is_mongo() {
+    let io = std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out");
+    let err = UsermanError::MongoFindOne(io.into());
+
+    assert!(err.is_mongo());
+    assert_eq!(err.variant(), "MongoFindOne");
+    assert!(!UsermanError::GetConfig("token").is_mongo());
+    assert!(!UsermanError::StdIoError(String::from("Mongo")).is_mongo());
+}
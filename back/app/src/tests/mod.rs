@@ -1,2 +1,22 @@
+mod access;
+mod apps;
+mod audit;
+mod avatars;
+mod cli;
+mod config_yaml;
+mod exports;
+mod health;
+mod imports;
+mod logger;
+mod manifest;
+mod metrics;
+mod pages;
 mod roles;
+mod sinks;
+mod streams;
+mod telemetry;
+mod tls;
+mod tokens;
+mod users;
+mod webhooks;
 mod data;
\ No newline at end of file
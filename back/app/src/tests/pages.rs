@@ -0,0 +1,182 @@
+use mongodb::bson::DateTime;
+use serde::Serialize;
+
+use crate::pages::{PageQuery, SortOrder};
+use crate::UsermanError;
+
+const SORTS: &[&str] = &["name", "id", "rank"];
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+struct Item {
+    id: String,
+    name: String,
+    rank: i64,
+}
+
+fn items() -> Vec<Item> {
+    ["delta", "Alpha", "charlie", "bravo", "echo"]
+        .iter()
+        .enumerate()
+        .map(|(i, t)| Item {
+            id: i.to_string(),
+            name: t.to_string(),
+            rank: (i as i64 - 2).abs(),
+        })
+        .collect()
+}
+
+fn names(items: &[Item]) -> Vec<&str> {
+    items.iter().map(|t| t.name.as_str()).collect()
+}
+
+#[test]
+fn paginate_all() {
+    let page = PageQuery::default()
+        .paginate(items(), SORTS, |t| t.id.clone())
+        .unwrap();
+
+    assert_eq!(page.total, 5);
+    assert_eq!(page.next_cursor, None);
+    assert_eq!(
+        names(&page.items),
+        vec!["Alpha", "bravo", "charlie", "delta", "echo"]
+    );
+}
+
+#[test]
+fn paginate_pages() {
+    let query = PageQuery {
+        page: Some(2),
+        limit: Some(2),
+        sort: Some("name".to_string()),
+        order: Some(SortOrder::Desc),
+        ..Default::default()
+    };
+
+    let page = query
+        .paginate(items(), &["id", "name"], |t| t.id.clone())
+        .unwrap();
+
+    assert_eq!(page.total, 5);
+    assert_eq!(page.page, Some(2));
+    assert_eq!(names(&page.items), vec!["charlie", "bravo"]);
+    assert_eq!(page.next_cursor, Some("3".to_string()));
+
+    let query = PageQuery {
+        page: Some(10),
+        limit: Some(2),
+        ..Default::default()
+    };
+
+    let page = query.paginate(items(), SORTS, |t| t.id.clone()).unwrap();
+
+    assert!(page.items.is_empty());
+}
+
+#[test]
+fn paginate_cursor() {
+    let query = PageQuery {
+        limit: Some(2),
+        cursor: Some("3".to_string()),
+        sort: Some("rank".to_string()),
+        ..Default::default()
+    };
+
+    let page = query.paginate(items(), SORTS, |t| t.id.clone()).unwrap();
+
+    assert_eq!(names(&page.items), vec!["delta", "echo"]);
+    assert_eq!(page.next_cursor, None);
+
+    let query = PageQuery {
+        cursor: Some("missing".to_string()),
+        ..Default::default()
+    };
+
+    assert!(query.paginate(items(), SORTS, |t| t.id.clone()).is_err());
+}
+
+#[test]
+fn paginate_ties() {
+    let values: Vec<Item> = ["4", "1", "3", "0", "2"]
+        .iter()
+        .map(|t| Item {
+            id: t.to_string(),
+            name: "same".to_string(),
+            rank: 0,
+        })
+        .collect();
+
+    let mut seen = vec![];
+    let mut cursor = None;
+
+    loop {
+        let query = PageQuery {
+            limit: Some(2),
+            cursor: cursor.clone(),
+            ..Default::default()
+        };
+
+        let page = query
+            .paginate(values.clone(), SORTS, |t| t.id.clone())
+            .unwrap();
+
+        seen.extend(page.items.into_iter().map(|t| t.id));
+
+        match page.next_cursor {
+            Some(t) => cursor = Some(t),
+            None => break,
+        }
+    }
+
+    assert_eq!(seen, vec!["0", "1", "2", "3", "4"]);
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Dated {
+    id: String,
+    created_at: Option<DateTime>,
+}
+
+#[test]
+fn paginate_dates() {
+    // Ids in the reverse order of the dates, so a fallback on them shows.
+    let values: Vec<Dated> = [3000, 1000, 2000]
+        .iter()
+        .enumerate()
+        .map(|(i, t)| Dated {
+            id: (9 - i).to_string(),
+            created_at: Some(DateTime::from_millis(*t)),
+        })
+        .chain([Dated {
+            id: "0".to_string(),
+            created_at: None,
+        }])
+        .collect();
+
+    let query = PageQuery {
+        sort: Some("createdAt".to_string()),
+        order: Some(SortOrder::Desc),
+        ..Default::default()
+    };
+
+    let page = query
+        .paginate(values, &["id", "createdAt"], |t| t.id.clone())
+        .unwrap();
+
+    let ids: Vec<&str> = page.items.iter().map(|t| t.id.as_str()).collect();
+
+    assert_eq!(ids, vec!["9", "7", "8", "0"]);
+}
+
+#[test]
+fn paginate_unknown_sort() {
+    let query = PageQuery {
+        sort: Some("password".to_string()),
+        ..Default::default()
+    };
+
+    let result = query.paginate(items(), SORTS, |t| t.id.clone());
+
+    assert!(matches!(result, Err(UsermanError::InvalidSort(t)) if t == "password"));
+}
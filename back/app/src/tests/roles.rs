@@ -1,5 +1,12 @@
+use mongodb::bson::oid::ObjectId;
+use serde_json::json;
+
 use userman_auth::roles::{DataValue, Role};
 
+use crate::api::v1::roles::{RolesFilter, SORTS};
+use crate::dao::with_item;
+use crate::error::UsermanError;
+use crate::pages::PageQuery;
 use crate::tests::data::role_a;
 
 use super::data::role_b;
@@ -49,3 +56,116 @@ fn merge_roles() {
     assert_eq!(sub_item_4.values.inner()[1].name, "value_3");
     assert_eq!(sub_item_4.values.inner()[1].data, DataValue::Integer(2));
 }
+
+#[test]
+fn granted_item() {
+    let role = role_a();
+
+    let items = with_item(&role.items, "logs", &["update"]).unwrap();
+
+    assert!(items.find("item_1").is_some());
+    assert!(items.find("item_2").is_some());
+
+    let logs = items.find("logs").unwrap();
+
+    assert_eq!(logs.values.inner()[0].name, "update");
+    assert_eq!(logs.values.inner()[0].data, DataValue::Boolean(true));
+}
+
+fn roles(app_a: ObjectId, app_b: ObjectId) -> Vec<Role> {
+    [
+        (app_a, "Admin"),
+        (app_a, "viewer"),
+        (app_b, "admin"),
+        (app_b, "operator"),
+    ]
+    .iter()
+    .map(|(app, name)| Role {
+        id: Some(ObjectId::new()),
+        app: *app,
+        name: name.to_string(),
+        ..Default::default()
+    })
+    .collect()
+}
+
+fn filter(value: serde_json::Value) -> RolesFilter {
+    serde_json::from_value(value).unwrap()
+}
+
+#[test]
+fn filter_roles() {
+    let (app_a, app_b) = (ObjectId::new(), ObjectId::new());
+
+    let roles = filter(json!({})).apply(roles(app_a, app_b)).unwrap();
+    assert_eq!(roles.len(), 4);
+
+    let roles = filter(json!({ "app": app_b.to_hex() }))
+        .apply(self::roles(app_a, app_b))
+        .unwrap();
+    assert!(roles.iter().all(|t| t.app == app_b));
+    assert_eq!(roles.len(), 2);
+
+    let roles = filter(json!({ "name": "ADMIN" }))
+        .apply(self::roles(app_a, app_b))
+        .unwrap();
+    assert_eq!(roles.len(), 2);
+
+    let roles = filter(json!({ "app": app_a.to_hex(), "name": "admin" }))
+        .apply(self::roles(app_a, app_b))
+        .unwrap();
+    assert_eq!(roles.len(), 1);
+    assert_eq!(roles[0].name, "Admin");
+}
+
+#[test]
+fn filter_roles_invalid_app() {
+    let result = filter(json!({ "app": "invalid" })).apply(roles(ObjectId::new(), ObjectId::new()));
+
+    assert!(matches!(result, Err(UsermanError::ParseObjectId(_))));
+}
+
+#[test]
+fn filter_roles_pages() {
+    let (app_a, app_b) = (ObjectId::new(), ObjectId::new());
+
+    let roles = filter(json!({ "app": app_b.to_hex() }))
+        .apply(roles(app_a, app_b))
+        .unwrap();
+
+    let query = PageQuery {
+        limit: Some(1),
+        sort: Some("name".to_string()),
+        ..Default::default()
+    };
+
+    let page = query
+        .paginate(roles.clone(), SORTS, |t| t.id().to_hex())
+        .unwrap();
+
+    assert_eq!(page.total, 2);
+    assert_eq!(page.items[0].name, "admin");
+
+    let query = PageQuery {
+        limit: Some(1),
+        cursor: page.next_cursor,
+        ..Default::default()
+    };
+
+    let page = query
+        .paginate(roles.clone(), SORTS, |t| t.id().to_hex())
+        .unwrap();
+
+    assert_eq!(page.items[0].name, "operator");
+    assert_eq!(page.next_cursor, None);
+
+    let query = PageQuery {
+        sort: Some("username".to_string()),
+        ..Default::default()
+    };
+
+    assert!(matches!(
+        query.paginate(roles, SORTS, |t| t.id().to_hex()),
+        Err(UsermanError::InvalidSort(_))
+    ));
+}
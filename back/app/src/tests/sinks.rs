@@ -0,0 +1,209 @@
+use axum::extract::{Extension, Json};
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::Router;
+use serde_json::Value;
+use std::net::{SocketAddr, TcpListener, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+use crate::audit::AuditEvent;
+use crate::config_yaml::{Audit, AuditFile, AuditHttp, Syslog};
+use crate::sinks::{self, HttpSink, SyslogSink};
+
+/// Batches received by the stand-in SIEM, the first `failures` requests are
+/// answered with a 503.
+#[derive(Clone, Default)]
+struct Receiver {
+    batches: Arc<Mutex<Vec<Vec<Value>>>>,
+    failures: Arc<Mutex<usize>>,
+}
+
+async fn receive(
+    Extension(receiver): Extension<Receiver>,
+    Json(batch): Json<Vec<Value>>,
+) -> StatusCode {
+    let mut failures = receiver.failures.lock().unwrap();
+
+    if *failures > 0 {
+        *failures -= 1;
+        return StatusCode::SERVICE_UNAVAILABLE;
+    }
+
+    receiver.batches.lock().unwrap().push(batch);
+    StatusCode::OK
+}
+
+fn serve(failures: usize) -> (SocketAddr, Receiver) {
+    let receiver = Receiver {
+        failures: Arc::new(Mutex::new(failures)),
+        ..Default::default()
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let app = Router::new()
+        .route("/audit", post(receive))
+        .layer(Extension(receiver.clone()));
+
+    tokio::spawn(
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service()),
+    );
+
+    (address, receiver)
+}
+
+fn http(address: SocketAddr, retries: u32) -> AuditHttp {
+    AuditHttp {
+        enabled: true,
+        url: format!("http://{}/audit", address),
+        batch_size: 2,
+        retries,
+        retry_delay_ms: 10,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn http_batches() {
+    let (address, receiver) = serve(1);
+    let mut sink = HttpSink::new(&http(address, 2)).unwrap();
+
+    for action in ["users.create", "users.update", "users.delete"] {
+        sink.send(AuditEvent::new(action)).await.unwrap();
+    }
+
+    sink.flush().await.unwrap();
+
+    let batches = receiver.batches.lock().unwrap();
+    let sizes: Vec<usize> = batches.iter().map(|t| t.len()).collect();
+
+    assert_eq!(sizes, vec![2, 1]);
+    assert_eq!(batches[1][0]["action"], "users.delete");
+}
+
+#[tokio::test]
+async fn http_retries_exhausted() {
+    let (address, receiver) = serve(usize::MAX);
+    let mut sink = HttpSink::new(&http(address, 1)).unwrap();
+
+    sink.send(AuditEvent::new("users.create")).await.unwrap();
+
+    assert!(sink.flush().await.is_err());
+    assert!(sink.flush().await.is_ok());
+    assert!(receiver.batches.lock().unwrap().is_empty());
+}
+
+#[test]
+fn syslog_5424() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+    let syslog = Syslog {
+        enabled: true,
+        address: socket.local_addr().unwrap().to_string(),
+    };
+
+    let mut sink = SyslogSink::new(&syslog).unwrap();
+    sink.send(&AuditEvent::new("users.update").actor("admin"))
+        .unwrap();
+
+    let mut buffer = [0; 4096];
+    let size = socket.recv(&mut buffer).unwrap();
+    let message = String::from_utf8_lossy(&buffer[..size]);
+
+    // auth facility, notice severity, version 1.
+    assert!(message.starts_with("<37>1 "), "{}", message);
+    assert!(message.contains("[audit@32473 "), "{}", message);
+    assert!(message.contains("action=\"users.update\""), "{}", message);
+    assert!(message.contains("actor=\"admin\""), "{}", message);
+}
+
+#[tokio::test]
+async fn file_drained_on_shutdown() {
+    let path = std::env::temp_dir().join(format!("userman-audit-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let audit = Audit {
+        file: AuditFile {
+            enabled: true,
+            path: path.to_string_lossy().to_string(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let (sinks, forwarder) = sinks::channel(&audit, Duration::from_secs(5)).unwrap();
+
+    sinks.send(AuditEvent::new("users.create"));
+    sinks.send(AuditEvent::new("users.delete"));
+
+    let token = CancellationToken::new();
+    token.cancel();
+    forwarder.unwrap().run(token).await.unwrap();
+
+    let raw = std::fs::read_to_string(&path).unwrap();
+    let actions: Vec<String> = raw
+        .lines()
+        .map(|t| serde_json::from_str::<Value>(t).unwrap()["action"].to_string())
+        .collect();
+
+    assert_eq!(actions, vec!["\"users.create\"", "\"users.delete\""]);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn http_hanging() {
+    let path = std::env::temp_dir().join(format!("userman-hanging-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    // Accepts connections but never answers.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+    let audit = Audit {
+        file: AuditFile {
+            enabled: true,
+            path: path.to_string_lossy().to_string(),
+            ..Default::default()
+        },
+        http: AuditHttp {
+            batch_size: 1,
+            timeout: 60,
+            ..http(listener.local_addr().unwrap(), 3)
+        },
+        ..Default::default()
+    };
+
+    let (sinks, forwarder) = sinks::channel(&audit, Duration::from_millis(500)).unwrap();
+
+    let token = CancellationToken::new();
+    let task = tokio::spawn(forwarder.unwrap().run(token.clone()));
+
+    sinks.send(AuditEvent::new("users.create"));
+    sinks.send(AuditEvent::new("users.delete"));
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // The file sink is not held by the HTTP one.
+    let raw = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(raw.lines().count(), 2);
+
+    let start = Instant::now();
+    token.cancel();
+    task.await.unwrap().unwrap();
+
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn disabled() {
+    let (_, forwarder) = sinks::channel(&Audit::default(), Duration::from_secs(5)).unwrap();
+
+    assert!(forwarder.is_none());
+}
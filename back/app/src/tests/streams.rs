@@ -0,0 +1,179 @@
+use mongodb::bson::oid::ObjectId;
+use std::collections::HashSet;
+use tokio::time::Instant;
+
+use userman_auth::roles::Role;
+
+use crate::roles::Roles;
+use crate::streams::{PermissionEvent, Related, Subscription};
+use crate::users::{User, Users};
+use crate::watchers::{Change, Event, Operation};
+
+fn change(event: Event, operation: Option<Operation>, id: Option<ObjectId>) -> Change {
+    Change {
+        event,
+        operation,
+        id,
+        key: String::new(),
+    }
+}
+
+fn related(roles: &[ObjectId], apps: &[ObjectId]) -> Related {
+    Related {
+        roles: roles.iter().copied().collect(),
+        apps: apps.iter().copied().collect(),
+    }
+}
+
+#[test]
+fn configs_ignored() {
+    let change = change(Event::Configs, Some(Operation::Updated), None);
+
+    assert!(PermissionEvent::new(&change, Related::default()).is_none());
+}
+
+#[test]
+fn event_json() {
+    let id = ObjectId::new();
+    let change = change(Event::Roles, Some(Operation::Deleted), Some(id));
+    let event = PermissionEvent::new(&change, related(&[id], &[])).unwrap();
+    let value = serde_json::to_value(event).unwrap();
+
+    assert_eq!(value["kind"], "role");
+    assert_eq!(value["operation"], "deleted");
+    assert_eq!(value["id"], id.to_hex());
+    assert!(value.get("related").is_none());
+}
+
+#[test]
+fn requested() {
+    let role = ObjectId::new();
+    let app = ObjectId::new();
+    let other = ObjectId::new();
+
+    let subscription = Subscription {
+        roles: HashSet::from([role]),
+        apps: HashSet::from([app]),
+        ..Default::default()
+    };
+
+    let role_event = change(Event::Roles, Some(Operation::Updated), Some(role));
+    let user_event = change(Event::Users, Some(Operation::Updated), Some(other));
+    let other_event = change(Event::Roles, Some(Operation::Updated), Some(other));
+    let dropped = change(Event::Apps, None, None);
+
+    let role_event = PermissionEvent::new(&role_event, related(&[role], &[other])).unwrap();
+    let user_event = PermissionEvent::new(&user_event, related(&[other], &[app])).unwrap();
+    let other_event = PermissionEvent::new(&other_event, related(&[other], &[other])).unwrap();
+    let dropped = PermissionEvent::new(&dropped, Related::default()).unwrap();
+
+    assert!(subscription.requested(&role_event));
+    assert!(subscription.requested(&user_event));
+    assert!(!subscription.requested(&other_event));
+    assert!(subscription.requested(&dropped));
+    assert!(!Subscription::default().requested(&other_event));
+}
+
+fn role(app: ObjectId) -> Role {
+    Role {
+        id: Some(ObjectId::new()),
+        app,
+        ..Default::default()
+    }
+}
+
+fn user(username: &str, roles: &[&Role], enabled: bool) -> User {
+    User {
+        id: Some(ObjectId::new()),
+        username: username.to_string(),
+        roles: roles.iter().map(|t| t.id()).collect(),
+        enabled,
+        ..Default::default()
+    }
+}
+
+fn event(event: Event, id: ObjectId, related: Related) -> PermissionEvent {
+    let change = change(event, Some(Operation::Updated), Some(id));
+
+    PermissionEvent::new(&change, related).unwrap()
+}
+
+#[tokio::test]
+async fn matches_followed_users() {
+    let app = ObjectId::new();
+    let other_app = ObjectId::new();
+    let reports = role(app);
+    let billing = role(other_app);
+    let unrelated = role(ObjectId::new());
+
+    let jdoe = user("jdoe", &[&reports], true);
+    let rroe = user("rroe", &[&billing], true);
+    let users = Users::from(vec![jdoe.clone(), rroe.clone()]);
+    let roles = Roles::from(vec![reports.clone(), billing.clone(), unrelated.clone()]);
+
+    let own = Subscription {
+        username: Some(jdoe.username.clone()),
+        ..Default::default()
+    };
+
+    let following = Subscription {
+        username: Some(jdoe.username.clone()),
+        users: HashSet::from([rroe.id()]),
+        ..Default::default()
+    };
+
+    let jdoe_changed = event(Event::Users, jdoe.id(), Related::default());
+    let reports_changed = event(Event::Roles, reports.id(), related(&[reports.id()], &[app]));
+    let app_changed = event(Event::Apps, app, related(&[], &[app]));
+    let billing_changed = event(
+        Event::Roles,
+        billing.id(),
+        related(&[billing.id()], &[other_app]),
+    );
+    let unrelated_changed = event(
+        Event::Roles,
+        unrelated.id(),
+        related(&[unrelated.id()], &[unrelated.app]),
+    );
+
+    assert!(own.matches(&users, &roles, &jdoe_changed).await);
+    assert!(own.matches(&users, &roles, &reports_changed).await);
+    assert!(own.matches(&users, &roles, &app_changed).await);
+    assert!(!own.matches(&users, &roles, &billing_changed).await);
+    assert!(following.matches(&users, &roles, &billing_changed).await);
+    assert!(!following.matches(&users, &roles, &unrelated_changed).await);
+}
+
+#[tokio::test]
+async fn active() {
+    let users = Users::from(vec![user("jdoe", &[], true), user("rroe", &[], false)]);
+
+    let subscription = |username: Option<&str>| Subscription {
+        username: username.map(str::to_string),
+        ..Default::default()
+    };
+
+    assert!(subscription(Some("jdoe")).active(&users).await);
+    assert!(!subscription(Some("rroe")).active(&users).await);
+    assert!(!subscription(Some("missing")).active(&users).await);
+    assert!(subscription(None).active(&users).await);
+}
+
+#[test]
+fn deadline() {
+    let now = chrono::Utc::now().timestamp();
+
+    let expired = Subscription {
+        expires_at: Some(now - 60),
+        ..Default::default()
+    };
+
+    let valid = Subscription {
+        expires_at: Some(now + 60),
+        ..Default::default()
+    };
+
+    assert!(Subscription::default().deadline().is_none());
+    assert!(expired.deadline().unwrap() <= Instant::now());
+    assert!(valid.deadline().unwrap() > Instant::now());
+}
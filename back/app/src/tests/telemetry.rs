@@ -0,0 +1,21 @@
+use crate::config_yaml::{ConfigYAML, Tracing};
+use crate::telemetry::{self, Exporter};
+
+use super::logger;
+
+/// The exporter is installed next to the global logger.
+#[tokio::test]
+async fn init_after_logger() {
+    logger::handle();
+
+    let config_yaml = ConfigYAML {
+        tracing: Tracing {
+            exporter: Exporter::Stdout,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    telemetry::init(&config_yaml).unwrap();
+    telemetry::shutdown();
+}
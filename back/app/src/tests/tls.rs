@@ -0,0 +1,46 @@
+use rustls::Certificate;
+
+use crate::tls::common_name;
+
+/// Self-signed certificate of `O=Proteus, CN=reports`.
+const WITH_CN: &str = "-----BEGIN CERTIFICATE-----
+MIIBnzCCAUWgAwIBAgIUYULJW+L83Z5/O9MZvVy0z7oIFIQwCgYIKoZIzj0EAwIw
+JDEQMA4GA1UECgwHUHJvdGV1czEQMA4GA1UEAwwHcmVwb3J0czAgFw0yNjEwMTkw
+NDI5MjlaGA8yMTI2MDkyNTA0MjkyOVowJDEQMA4GA1UECgwHUHJvdGV1czEQMA4G
+A1UEAwwHcmVwb3J0czBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IABBX062m+47gs
+6yhypfYU8gu0/6udwa3EzPbnxCyW60g7t3CZZVqKefhxgWovblWwLdMHgEKzSZU3
+4PTJ3+DQ98qjUzBRMB0GA1UdDgQWBBQYlgTaPB+hrzApQ016e4aH4xzrgjAfBgNV
+HSMEGDAWgBQYlgTaPB+hrzApQ016e4aH4xzrgjAPBgNVHRMBAf8EBTADAQH/MAoG
+CCqGSM49BAMCA0gAMEUCIQC8eJZP397NlhFfnQm7MWeBHC7peYXPGgtvr19K46iz
++QIgLK3dmvALml7fjbQgjoFu32SMnZssvdilJUFqJDkX9dg=
+-----END CERTIFICATE-----
+";
+
+/// Self-signed certificate of `O=Proteus`, without common name.
+const WITHOUT_CN: &str = "-----BEGIN CERTIFICATE-----
+MIIBezCCASGgAwIBAgIUJ3aUFkrNd/T/kzCYS3HDh8tN/v8wCgYIKoZIzj0EAwIw
+EjEQMA4GA1UECgwHUHJvdGV1czAgFw0yNjEwMTkwNDI5MjlaGA8yMTI2MDkyNTA0
+MjkyOVowEjEQMA4GA1UECgwHUHJvdGV1czBZMBMGByqGSM49AgEGCCqGSM49AwEH
+A0IABGHmMfdXoHuYqXtgsG4kJNId36Zl8NNE7m4WPC+6lp9SQagk/y+MNrO621F1
+wmgFCoXfRzS18/8wfktpdZnNoHqjUzBRMB0GA1UdDgQWBBScJDkjVfi+2WzCUsTx
+ouew6g5opTAfBgNVHSMEGDAWgBScJDkjVfi+2WzCUsTxouew6g5opTAPBgNVHRMB
+Af8EBTADAQH/MAoGCCqGSM49BAMCA0gAMEUCIQCgoDVgsX8mZY5pyAWVKpCb6x+a
+mW0r7pq8lr6F8LgHXAIgdowYgYb6fAe/6txJQej2GHucWRw5yhSEstXw91W8gaQ=
+-----END CERTIFICATE-----
+";
+
+fn certificate(pem: &str) -> Certificate {
+    let mut certs = rustls_pemfile::certs(&mut pem.as_bytes()).unwrap();
+
+    Certificate(certs.remove(0))
+}
+
+#[test]
+fn subject_common_name() {
+    assert_eq!(
+        common_name(&certificate(WITH_CN)),
+        Some("reports".to_string())
+    );
+    assert_eq!(common_name(&certificate(WITHOUT_CN)), None);
+    assert_eq!(common_name(&Certificate(b"garbage".to_vec())), None);
+}
@@ -0,0 +1,97 @@
+use mongodb::bson::oid::ObjectId;
+use std::collections::BTreeMap;
+
+use userman_auth::roles::{Role, LOCAL_ROLE};
+
+use crate::roles::Roles;
+use crate::tokens::{Claims, Keys, SessionToken};
+use crate::users::{User, Users};
+
+fn role(name: &str) -> Role {
+    Role {
+        id: Some(ObjectId::new()),
+        name: name.to_string(),
+        ..Default::default()
+    }
+}
+
+fn user(username: &str, password: Option<&str>, roles: &[&Role], enabled: bool) -> User {
+    User {
+        id: Some(ObjectId::new()),
+        username: username.to_string(),
+        password: password.map(str::to_string),
+        roles: roles.iter().map(|t| t.id()).collect(),
+        enabled,
+        ..Default::default()
+    }
+}
+
+async fn role_names(common_name: &str) -> Option<Vec<String>> {
+    let reports = role("reports");
+    let local = role(LOCAL_ROLE);
+
+    let users = Users::from(vec![
+        user("reports-bot", None, &[&reports], true),
+        user("disabled-bot", None, &[&reports], false),
+        user("local-bot", None, &[&local], true),
+        user("jdoe", Some("$2b$12$hash"), &[&reports], true),
+        user("admin", Some("$2b$12$hash"), &[&local], true),
+    ]);
+    let roles = Roles::from(vec![reports, local]);
+
+    let client_users: BTreeMap<String, String> = [
+        ("reports", "reports-bot"),
+        ("disabled", "disabled-bot"),
+        ("local", "local-bot"),
+        ("jdoe", "jdoe"),
+        ("admin", "admin"),
+        ("missing", "missing-bot"),
+    ]
+    .iter()
+    .map(|(a, b)| (a.to_string(), b.to_string()))
+    .collect();
+
+    SessionToken::ClientCert(common_name.to_string())
+        .role_names(&Keys::new("secret", 60), &users, &roles, &client_users)
+        .await
+        .ok()
+}
+
+#[tokio::test]
+async fn client_cert_service_user() {
+    assert_eq!(
+        role_names("reports").await,
+        Some(vec!["reports".to_string()])
+    );
+}
+
+#[tokio::test]
+async fn client_cert_rejected() {
+    // Not mapped, even if a user has the same name.
+    assert_eq!(role_names("reports-bot").await, None);
+    assert_eq!(role_names("missing").await, None);
+    assert_eq!(role_names("disabled").await, None);
+    // Human and admin accounts.
+    assert_eq!(role_names("jdoe").await, None);
+    assert_eq!(role_names("admin").await, None);
+    assert_eq!(role_names("local").await, None);
+}
+
+#[tokio::test]
+async fn expires_at() {
+    let keys = Keys::new("secret", 60);
+    let claims = Claims::new("jdoe", vec![], 60);
+    let token = claims.encode(&keys.encoding_key().await).unwrap();
+    let now = chrono::Utc::now().timestamp();
+
+    let expires_at = SessionToken::Bearer(token)
+        .expires_at(&keys)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert!((now + 59..=now + 61).contains(&expires_at));
+
+    let client_cert = SessionToken::ClientCert("reports".to_string());
+    assert_eq!(client_cert.expires_at(&keys).await.unwrap(), None);
+}
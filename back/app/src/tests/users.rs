@@ -0,0 +1,128 @@
+use bcrypt::hash;
+
+use crate::users::{admin_password, User, ADMIN_PASSWORD_ENV, ADMIN_PASSWORD_FILE_ENV};
+use crate::UsermanError;
+
+fn user(password: Option<&str>, enabled: bool, change_password: bool) -> User {
+    User {
+        username: "jdoe".to_string(),
+        password: password.map(|t| hash(t, 4).unwrap()),
+        enabled,
+        change_password,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn check_login() {
+    assert!(user(Some("secret"), true, false)
+        .check_login("secret")
+        .is_ok());
+
+    assert!(matches!(
+        user(Some("secret"), true, false).check_login("wrong"),
+        Err(UsermanError::InvalidCredentials)
+    ));
+    assert!(matches!(
+        user(Some("secret"), false, true).check_login("secret"),
+        Err(UsermanError::DisabledUser)
+    ));
+    assert!(matches!(
+        user(None, true, false).check_login("secret"),
+        Err(UsermanError::UninitializedPassword)
+    ));
+}
+
+#[test]
+fn check_login_change_password() {
+    let err = user(Some("secret"), true, true)
+        .check_login("secret")
+        .unwrap_err();
+
+    assert!(matches!(err, UsermanError::PasswordChangeRequired));
+    assert_eq!(err.code_number(), Some(3));
+
+    // A wrong password does not tell that a change is pending.
+    assert!(matches!(
+        user(Some("secret"), true, true).check_login("wrong"),
+        Err(UsermanError::InvalidCredentials)
+    ));
+}
+
+#[test]
+fn check_refresh() {
+    assert!(user(Some("secret"), true, false).check_refresh().is_ok());
+
+    assert!(matches!(
+        user(Some("secret"), true, true).check_refresh(),
+        Err(UsermanError::PasswordChangeRequired)
+    ));
+    assert!(matches!(
+        user(Some("secret"), false, false).check_refresh(),
+        Err(UsermanError::DisabledUser)
+    ));
+}
+
+#[test]
+fn check_password_change() {
+    // The change is how a user with a pending change gets back in.
+    assert!(user(Some("secret"), true, true)
+        .check_password_change("secret", "new-secret")
+        .is_ok());
+
+    assert!(matches!(
+        user(Some("secret"), true, false).check_password_change("secret", "secret"),
+        Err(UsermanError::SamePassword)
+    ));
+    assert!(matches!(
+        user(Some("secret"), true, false).check_password_change("wrong", "new-secret"),
+        Err(UsermanError::InvalidCredentials)
+    ));
+    assert!(matches!(
+        user(None, true, false).check_password_change("secret", "new-secret"),
+        Err(UsermanError::InvalidCredentials)
+    ));
+    assert!(matches!(
+        user(Some("secret"), false, false).check_password_change("secret", "new-secret"),
+        Err(UsermanError::DisabledUser)
+    ));
+}
+
+// A single test, the variables are shared by the whole process.
+#[tokio::test]
+async fn admin_password_sources() {
+    let path = std::env::temp_dir().join(format!("userman-admin-{}", std::process::id()));
+    std::fs::write(&path, "from-file\n").unwrap();
+
+    std::env::remove_var(ADMIN_PASSWORD_ENV);
+    std::env::remove_var(ADMIN_PASSWORD_FILE_ENV);
+    assert_eq!(admin_password().await.unwrap(), None);
+
+    std::env::set_var(ADMIN_PASSWORD_FILE_ENV, &path);
+    assert_eq!(
+        admin_password().await.unwrap(),
+        Some("from-file".to_string())
+    );
+
+    // The variable wins over the file, unless it is empty.
+    std::env::set_var(ADMIN_PASSWORD_ENV, "from-env");
+    assert_eq!(
+        admin_password().await.unwrap(),
+        Some("from-env".to_string())
+    );
+
+    std::env::set_var(ADMIN_PASSWORD_ENV, "");
+    assert_eq!(
+        admin_password().await.unwrap(),
+        Some("from-file".to_string())
+    );
+
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(
+        admin_password().await,
+        Err(UsermanError::StdIoError(_))
+    ));
+
+    std::env::remove_var(ADMIN_PASSWORD_ENV);
+    std::env::remove_var(ADMIN_PASSWORD_FILE_ENV);
+}
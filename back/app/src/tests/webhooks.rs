@@ -0,0 +1,254 @@
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::{Extension, Router};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde_json::json;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config_yaml::Webhooks;
+use crate::watchers::{Change, Event, Operation};
+use crate::webhooks::{
+    attempt, backoff, sign, Delivery, DeliveryStatus, Webhook, WebhookEvent, SIGNATURE,
+};
+use crate::UsermanError;
+
+fn webhook() -> Webhook {
+    Webhook {
+        id: None,
+        url: "https://example.com/hooks".to_string(),
+        secret: Some("0123456789abcdef".to_string()),
+        events: vec![WebhookEvent::UserCreated],
+        enabled: true,
+        description: String::new(),
+        created_at: None,
+        updated_at: None,
+    }
+}
+
+fn change(event: Event, operation: Option<Operation>) -> Change {
+    Change {
+        event,
+        operation,
+        id: Some(ObjectId::new()),
+        key: String::new(),
+    }
+}
+
+#[test]
+fn signature() {
+    let body = br#"{"event":"user.created"}"#;
+    let signature = sign("0123456789abcdef", 1700000000, body).unwrap();
+
+    assert_eq!(
+        signature,
+        "t=1700000000,v1=7402c0584595de027285319669f4ad30695cf60a8af55da37a57d648768afcc7"
+    );
+}
+
+#[test]
+fn backoff_doubles() {
+    let config = Webhooks::default();
+
+    assert_eq!(backoff(&config, 1), Duration::from_secs(30));
+    assert_eq!(backoff(&config, 2), Duration::from_secs(60));
+    assert_eq!(backoff(&config, 4), Duration::from_secs(240));
+    assert_eq!(backoff(&config, 100), Duration::from_secs(86400));
+}
+
+#[test]
+fn change_events() {
+    let created = change(Event::Users, Some(Operation::Created));
+    let deleted = change(Event::Roles, Some(Operation::Deleted));
+    let updated = change(Event::Apps, Some(Operation::Updated));
+    let dropped = change(Event::Users, None);
+    let config = change(Event::Configs, Some(Operation::Updated));
+
+    assert_eq!(WebhookEvent::of(&created), Some(WebhookEvent::UserCreated));
+    assert_eq!(WebhookEvent::of(&deleted), Some(WebhookEvent::RoleDeleted));
+    assert_eq!(WebhookEvent::of(&updated), Some(WebhookEvent::AppUpdated));
+    assert_eq!(WebhookEvent::of(&dropped), None);
+    assert_eq!(WebhookEvent::of(&config), None);
+}
+
+#[test]
+fn event_names() {
+    for event in [
+        WebhookEvent::UserCreated,
+        WebhookEvent::RoleUpdated,
+        WebhookEvent::AppDeleted,
+        WebhookEvent::Login,
+        WebhookEvent::PasswordReset,
+    ] {
+        let name = serde_json::to_value(event).unwrap();
+
+        assert_eq!(name, event.as_str());
+    }
+}
+
+#[test]
+fn validate() {
+    assert!(webhook().validate(true).is_ok());
+
+    let missing_secret = Webhook {
+        secret: None,
+        ..webhook()
+    };
+
+    assert!(missing_secret.validate(true).is_err());
+    assert!(missing_secret.validate(false).is_ok());
+
+    let short_secret = Webhook {
+        secret: Some("secret".to_string()),
+        ..webhook()
+    };
+
+    assert!(short_secret.validate(false).is_err());
+
+    let url = Webhook {
+        url: "ftp://example.com".to_string(),
+        ..webhook()
+    };
+
+    assert!(url.validate(true).is_err());
+
+    let events = Webhook {
+        events: vec![],
+        ..webhook()
+    };
+
+    assert!(events.validate(true).is_err());
+}
+
+#[test]
+fn secret_hidden() {
+    let value = serde_json::to_value(webhook()).unwrap();
+
+    assert!(value.get("secret").is_none());
+    assert_eq!(value["events"][0], "user.created");
+}
+
+/// Signatures received by the stand-in endpoint, answering with `status`.
+#[derive(Clone)]
+struct Receiver {
+    signatures: Arc<Mutex<Vec<String>>>,
+    status: StatusCode,
+}
+
+async fn receive(Extension(receiver): Extension<Receiver>, headers: HeaderMap) -> StatusCode {
+    if let Some(t) = headers.get(SIGNATURE) {
+        let signature = t.to_str().unwrap().to_string();
+        receiver.signatures.lock().unwrap().push(signature);
+    }
+
+    receiver.status
+}
+
+fn serve(status: StatusCode) -> (SocketAddr, Receiver) {
+    let receiver = Receiver {
+        signatures: Arc::default(),
+        status,
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let app = Router::new()
+        .route("/hooks", post(receive))
+        .layer(Extension(receiver.clone()));
+
+    tokio::spawn(
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service()),
+    );
+
+    (address, receiver)
+}
+
+fn delivery(webhook_id: ObjectId) -> Delivery {
+    Delivery {
+        id: Some(ObjectId::new()),
+        webhook_id,
+        event: WebhookEvent::UserCreated,
+        data: json!({ "username": "jdoe" }),
+        status: DeliveryStatus::Pending,
+        attempts: 0,
+        response_status: None,
+        error: None,
+        next_attempt_at: Some(DateTime::now()),
+        created_at: DateTime::now(),
+        updated_at: None,
+    }
+}
+
+fn local(address: SocketAddr) -> Webhook {
+    Webhook {
+        id: Some(ObjectId::new()),
+        url: format!("http://{}/hooks", address),
+        ..webhook()
+    }
+}
+
+#[tokio::test]
+async fn attempt_delivered() {
+    let (address, receiver) = serve(StatusCode::NO_CONTENT);
+    let webhook = local(address);
+    let client = reqwest::Client::new();
+
+    let t = delivery(webhook.id.unwrap());
+    let t = attempt(&Webhooks::default(), &client, Ok(Some(webhook)), t).await;
+
+    assert_eq!(t.status, DeliveryStatus::Delivered);
+    assert_eq!(t.attempts, 1);
+    assert_eq!(t.response_status, Some(204));
+    assert_eq!(t.next_attempt_at, None);
+
+    let signatures = receiver.signatures.lock().unwrap();
+    assert_eq!(signatures.len(), 1);
+    assert!(signatures[0].starts_with("t="), "{}", signatures[0]);
+}
+
+#[tokio::test]
+async fn attempt_dead_lettered() {
+    let (address, receiver) = serve(StatusCode::SERVICE_UNAVAILABLE);
+    let webhook = local(address);
+    let client = reqwest::Client::new();
+
+    let config = Webhooks {
+        max_attempts: 2,
+        ..Default::default()
+    };
+
+    let t = delivery(webhook.id.unwrap());
+    let t = attempt(&config, &client, Ok(Some(webhook.clone())), t).await;
+
+    assert_eq!(t.status, DeliveryStatus::Pending);
+    assert_eq!(t.response_status, Some(503));
+    assert!(t.error.is_some());
+    assert!(t.next_attempt_at.unwrap() > DateTime::now());
+
+    let t = attempt(&config, &client, Ok(Some(webhook)), t).await;
+
+    assert_eq!(t.status, DeliveryStatus::DeadLetter);
+    assert_eq!(t.attempts, 2);
+    assert_eq!(t.next_attempt_at, None);
+    assert_eq!(receiver.signatures.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn attempt_without_webhook() {
+    let client = reqwest::Client::new();
+    let config = Webhooks {
+        max_attempts: 1,
+        ..Default::default()
+    };
+
+    let t = attempt(&config, &client, Ok(None), delivery(ObjectId::new())).await;
+
+    assert_eq!(t.status, DeliveryStatus::DeadLetter);
+    assert_eq!(t.response_status, None);
+    assert_eq!(t.error, Some(UsermanError::WebhookNotFound.to_string()));
+}
@@ -0,0 +1,140 @@
+//! Rustls server config with optional client certificate verification. The
+//! common name of a verified client certificate maps, through
+//! `tls.clientUsers`, to the service user making the requests.
+
+use axum::middleware::AddExtension;
+use axum::Extension;
+use axum_server::accept::Accept;
+use axum_server::tls_rustls::{RustlsAcceptor, RustlsConfig};
+use futures::future::BoxFuture;
+use rustls::server::{AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient};
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use rustls_pemfile::Item;
+use std::io;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tower_layer::Layer;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use crate::config_yaml::Tls;
+use crate::{Result, UsermanError};
+
+/// Common name of the verified client certificate of the connection.
+#[derive(Clone, Debug)]
+pub struct ClientCert(pub String);
+
+async fn read_certs(path: &str) -> Result<Vec<Certificate>> {
+    let pem = tokio::fs::read(path)
+        .await
+        .map_err(|err| UsermanError::PEMFile(format!("{}. {}", path, err)))?;
+
+    rustls_pemfile::certs(&mut pem.as_slice())
+        .map(|t| t.into_iter().map(Certificate).collect())
+        .map_err(|err| UsermanError::PEMFile(format!("{}. {}", path, err)))
+}
+
+async fn read_key(path: &str) -> Result<PrivateKey> {
+    let pem = tokio::fs::read(path)
+        .await
+        .map_err(|err| UsermanError::PEMFile(format!("{}. {}", path, err)))?;
+
+    match rustls_pemfile::read_one(&mut pem.as_slice()) {
+        Ok(Some(Item::RSAKey(t) | Item::PKCS8Key(t) | Item::ECKey(t))) => Ok(PrivateKey(t)),
+        Ok(_) => Err(UsermanError::PEMFile(format!(
+            "{}. Private key format not supported.",
+            path
+        ))),
+        Err(err) => Err(UsermanError::PEMFile(format!("{}. {}", path, err))),
+    }
+}
+
+async fn server_config(tls: &Tls) -> Result<ServerConfig> {
+    let builder = ServerConfig::builder().with_safe_defaults();
+
+    let builder = match tls.client_ca.is_empty() {
+        true => builder.with_no_client_auth(),
+        false => {
+            let mut roots = RootCertStore::empty();
+
+            for cert in read_certs(&tls.client_ca).await? {
+                roots
+                    .add(&cert)
+                    .map_err(|err| UsermanError::PEMFile(format!("{}. {}", tls.client_ca, err)))?;
+            }
+
+            match tls.client_auth_required {
+                true => builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots)),
+                false => builder
+                    .with_client_cert_verifier(AllowAnyAnonymousOrAuthenticatedClient::new(roots)),
+            }
+        }
+    };
+
+    let mut config = builder
+        .with_single_cert(read_certs(&tls.certs).await?, read_key(&tls.key).await?)
+        .map_err(|err| UsermanError::PEMFile(err.to_string()))?;
+
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(config)
+}
+
+/// Read the TLS files.
+pub async fn rustls(tls: &Tls) -> Result<RustlsConfig> {
+    Ok(RustlsConfig::from_config(Arc::new(
+        server_config(tls).await?,
+    )))
+}
+
+/// Swap the TLS files of a running server, the open connections keep the
+/// previous ones.
+pub async fn reload(rustls: &RustlsConfig, tls: &Tls) -> Result<()> {
+    rustls.reload_from_config(Arc::new(server_config(tls).await?));
+    Ok(())
+}
+
+/// Common name of the certificate subject.
+pub fn common_name(cert: &Certificate) -> Option<String> {
+    let (_, cert) = X509Certificate::from_der(&cert.0).ok()?;
+    let common_name = cert.subject().iter_common_name().next()?;
+    common_name.as_str().ok().map(String::from)
+}
+
+/// Rustls acceptor adding the [`ClientCert`] of the connection to every
+/// request.
+#[derive(Clone)]
+pub struct ClientCertAcceptor(RustlsAcceptor);
+
+impl ClientCertAcceptor {
+    pub fn new(rustls: RustlsConfig) -> Self {
+        Self(RustlsAcceptor::new(rustls))
+    }
+}
+
+impl<I, S> Accept<I, S> for ClientCertAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = <RustlsAcceptor as Accept<I, S>>::Stream;
+    type Service = AddExtension<S, Option<ClientCert>>;
+    type Future = BoxFuture<'static, io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let acceptor = self.0.clone();
+
+        Box::pin(async move {
+            let (stream, service) = acceptor.accept(stream, service).await?;
+
+            let client_cert = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|t| t.first())
+                .and_then(common_name)
+                .map(ClientCert);
+
+            Ok((stream, Extension(client_cert).layer(service)))
+        })
+    }
+}
@@ -1,6 +1,7 @@
 use mongodb::bson::oid::ObjectId;
 use mongodb::bson::DateTime;
 use rand::distributions::{Alphanumeric, DistString};
+use std::collections::BTreeMap;
 use std::sync::atomic::Ordering;
 use std::sync::{atomic::AtomicI64, Arc};
 
@@ -16,8 +17,14 @@ use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation}
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
+use userman_auth::roles::LOCAL_ROLE;
+
+use crate::access;
 use crate::configs::{ConfigData, TOKEN_CONFIG};
 use crate::dao::{Dao, Memory};
+use crate::roles::Roles;
+use crate::tls::ClientCert;
+use crate::users::{User, Users, ADMIN_USERNAME};
 use crate::{Result, UsermanError};
 
 #[derive(Clone)]
@@ -28,6 +35,14 @@ pub struct Keys {
 }
 
 impl Keys {
+    pub fn new(secret: &str, duration: i64) -> Self {
+        Self {
+            encoding_key: Arc::new(RwLock::new(EncodingKey::from_secret(secret.as_bytes()))),
+            decoding_key: Arc::new(RwLock::new(DecodingKey::from_secret(secret.as_bytes()))),
+            duration: Arc::new(AtomicI64::from(duration)),
+        }
+    }
+
     pub async fn encoding_key(&self) -> EncodingKey {
         self.encoding_key.read().await.clone()
     }
@@ -49,15 +64,7 @@ impl Memory<ConfigData, ObjectId> for Keys {
             _ => return Err(UsermanError::GetConfig(TOKEN_CONFIG)),
         };
 
-        let encoding_key = EncodingKey::from_secret(web_config.secret.as_bytes());
-        let decoding_key = DecodingKey::from_secret(web_config.secret.as_bytes());
-        let duration = web_config.duration;
-
-        Ok(Self {
-            encoding_key: Arc::new(RwLock::new(encoding_key)),
-            decoding_key: Arc::new(RwLock::new(decoding_key)),
-            duration: Arc::new(AtomicI64::from(duration)),
-        })
+        Ok(Self::new(&web_config.secret, web_config.duration))
     }
 
     async fn reload(&self, dao: &Dao) -> Result<()> {
@@ -117,7 +124,12 @@ impl Claims {
 }
 
 #[derive(Debug)]
-pub struct SessionToken(String);
+pub enum SessionToken {
+    /// JWT from the `Authorization: Bearer` header.
+    Bearer(String),
+    /// Common name of the verified client certificate.
+    ClientCert(String),
+}
 
 #[async_trait]
 impl<S> FromRequestParts<S> for SessionToken
@@ -130,20 +142,91 @@ where
         parts: &mut Parts,
         _state: &S,
     ) -> std::result::Result<Self, Self::Rejection> {
-        let TypedHeader(Authorization(bearer)) = parts
-            .extract::<TypedHeader<Authorization<Bearer>>>()
-            .await
-            .map_err(|_| UsermanError::InvalidToken)?;
+        if let Ok(TypedHeader(Authorization(bearer))) =
+            parts.extract::<TypedHeader<Authorization<Bearer>>>().await
+        {
+            return Ok(Self::Bearer(bearer.token().to_owned()));
+        }
 
-        Ok(Self(bearer.token().to_owned()))
+        match parts.extensions.get::<Option<ClientCert>>() {
+            Some(Some(ClientCert(username))) => Ok(Self::ClientCert(username.to_owned())),
+            _ => Err(UsermanError::InvalidToken),
+        }
     }
 }
 
+/// Whether `user` may authenticate with a client certificate. Only enabled
+/// service users qualify: they never set a password, are not the admin and do
+/// not hold the local role granting every permission.
+async fn service_user(user: &User, roles: &Roles) -> bool {
+    if !user.enabled || user.password.is_some() || user.username == ADMIN_USERNAME {
+        return false;
+    }
+
+    for role_id in &user.roles {
+        if let Some(t) = roles.get_by_id(role_id).await {
+            if t.name == LOCAL_ROLE {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
 impl SessionToken {
-    pub async fn role_names(&self, keys: &Keys) -> Result<Vec<String>> {
-        let decoding_key = keys.decoding_key().await;
-        let claims = Claims::decode(&self.0, &decoding_key)?;
-        Ok(claims.roles)
+    /// Unix time the JWT expires at, client certificates don't.
+    pub async fn expires_at(&self, keys: &Keys) -> Result<Option<i64>> {
+        match self {
+            Self::Bearer(token) => {
+                let decoding_key = keys.decoding_key().await;
+                let claims = Claims::decode(token, &decoding_key)?;
+                Ok(Some(claims.exp as i64))
+            }
+            Self::ClientCert(_) => Ok(None),
+        }
+    }
+
+    /// Role names from the JWT claims, or from the service user mapped to the
+    /// client certificate by `client_users`.
+    pub async fn role_names(
+        &self,
+        keys: &Keys,
+        users: &Users,
+        roles: &Roles,
+        client_users: &BTreeMap<String, String>,
+    ) -> Result<Vec<String>> {
+        match self {
+            Self::Bearer(token) => {
+                let decoding_key = keys.decoding_key().await;
+                let claims = Claims::decode(token, &decoding_key)?;
+                access::set_user(&claims.sub);
+                Ok(claims.roles)
+            }
+            Self::ClientCert(common_name) => {
+                let user = match client_users.get(common_name) {
+                    Some(t) => users.get(t).await,
+                    None => None,
+                };
+
+                let t = match user {
+                    Some(t) if service_user(&t, roles).await => t,
+                    _ => return Err(UsermanError::Unauthorized),
+                };
+
+                access::set_user(&t.username);
+
+                let mut role_names = vec![];
+
+                for role_id in &t.roles {
+                    if let Some(role) = roles.get_by_id(role_id).await {
+                        role_names.push(role.name);
+                    }
+                }
+
+                Ok(role_names)
+            }
+        }
     }
 }
 
@@ -3,16 +3,45 @@ use bcrypt::{hash, verify, DEFAULT_COST};
 use log::error;
 use mongodb::bson::oid::ObjectId;
 use mongodb::bson::DateTime;
+use rand::distributions::{Alphanumeric, DistString};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tracing::instrument;
 
 use crate::dao::{Dao, Memory};
-use crate::{serialize_option_oid_as_string, serialize_vec_oid_as_string, Result};
+use crate::{serialize_option_oid_as_string, serialize_vec_oid_as_string, Result, UsermanError};
 
 pub const ADMIN_USERNAME: &str = "admin";
+/// Initial password of the admin user, or the path of a file holding it.
+pub const ADMIN_PASSWORD_ENV: &str = "USERMAN_ADMIN_PASSWORD";
+pub const ADMIN_PASSWORD_FILE_ENV: &str = "USERMAN_ADMIN_PASSWORD_FILE";
+const RANDOM_PASSWORD_LEN: usize = 20;
+
+/// Password for accounts created or recovered from the command line.
+pub fn random_password() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), RANDOM_PASSWORD_LEN)
+}
+
+/// Admin password given through the environment or a secret file, if any.
+pub async fn admin_password() -> Result<Option<String>> {
+    if let Some(t) = std::env::var(ADMIN_PASSWORD_ENV)
+        .ok()
+        .filter(|t| !t.is_empty())
+    {
+        return Ok(Some(t));
+    }
+
+    match std::env::var(ADMIN_PASSWORD_FILE_ENV) {
+        Ok(path) => tokio::fs::read_to_string(&path)
+            .await
+            .map(|t| Some(t.trim_end().to_string()))
+            .map_err(|err| UsermanError::StdIoError(format!("{}: {}", path, err))),
+        Err(_) => Ok(None),
+    }
+}
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -34,6 +63,8 @@ pub struct UserDB {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub avatar: Option<String>,
     pub enabled: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub change_password: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub created_at: Option<DateTime>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -65,6 +96,9 @@ pub struct User {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub avatar: Option<String>,
     pub enabled: bool,
+    /// The password has to be changed before the next login.
+    #[serde(default)]
+    pub change_password: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub created_at: Option<DateTime>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -76,7 +110,7 @@ impl Default for User {
         Self {
             id: None,
             username: ADMIN_USERNAME.to_string(),
-            password: Some("admin".to_string()),
+            password: None,
             email: "admin@proteus.com.ar".to_string(),
             name: "admin".to_string(),
             surname: "admin".to_string(),
@@ -85,6 +119,7 @@ impl Default for User {
             roles: vec![],
             avatar: None,
             enabled: true,
+            change_password: false,
             created_at: None,
             updated_at: None,
         }
@@ -96,6 +131,7 @@ impl User {
         self.id.unwrap_or_default()
     }
 
+    #[instrument(name = "bcrypt_verify", skip_all)]
     pub fn verify<P: AsRef<[u8]>>(&self, password: P) -> Option<bool> {
         match self.password {
             Some(ref t) => match verify(password, t) {
@@ -109,6 +145,45 @@ impl User {
         }
     }
 
+    /// Checks a login, a user that must change its password only gets to
+    /// call the change password endpoint.
+    pub fn check_login<P: AsRef<[u8]>>(&self, password: P) -> Result<()> {
+        match self.verify(password) {
+            Some(true) if !self.enabled => Err(UsermanError::DisabledUser),
+            Some(true) => self.check_refresh(),
+            Some(false) => Err(UsermanError::InvalidCredentials),
+            None => Err(UsermanError::UninitializedPassword),
+        }
+    }
+
+    /// Checks that the sessions of the user can still be refreshed.
+    pub fn check_refresh(&self) -> Result<()> {
+        if !self.enabled {
+            return Err(UsermanError::DisabledUser);
+        }
+
+        if self.change_password {
+            return Err(UsermanError::PasswordChangeRequired);
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the user can replace `password` with `new_password`.
+    pub fn check_password_change(&self, password: &str, new_password: &str) -> Result<()> {
+        match self.verify(password) {
+            Some(true) if self.enabled => {}
+            Some(true) => return Err(UsermanError::DisabledUser),
+            _ => return Err(UsermanError::InvalidCredentials),
+        }
+
+        if new_password == password {
+            return Err(UsermanError::SamePassword);
+        }
+
+        Ok(())
+    }
+
     pub fn hash_password(self) -> Self {
         match self.password {
             Some(t) => Self {
@@ -146,6 +221,7 @@ impl User {
             roles: self.roles,
             avatar: self.avatar,
             enabled: self.enabled,
+            change_password: self.change_password,
             created_at: Some(DateTime::now()),
             updated_at: None,
         }
@@ -164,21 +240,34 @@ impl User {
             roles: self.roles,
             avatar: self.avatar,
             enabled: self.enabled,
+            change_password: self.change_password,
             created_at: None,
             updated_at: Some(DateTime::now()),
         }
     }
 }
 
-#[derive(Serialize, ToSchema)]
-pub struct UsersVec(pub Vec<User>);
-
 #[derive(Clone)]
 pub struct Users {
     users: Arc<RwLock<HashMap<ObjectId, User>>>,
     users_by_username: Arc<RwLock<HashMap<String, User>>>,
 }
 
+impl From<Vec<User>> for Users {
+    fn from(values: Vec<User>) -> Self {
+        let users = values.iter().map(|t| (t.id(), t.clone())).collect();
+        let users_by_username = values
+            .into_iter()
+            .map(|t| (t.username.clone(), t))
+            .collect();
+
+        Self {
+            users: Arc::new(RwLock::new(users)),
+            users_by_username: Arc::new(RwLock::new(users_by_username)),
+        }
+    }
+}
+
 #[async_trait]
 impl Memory<User> for Users {
     async fn load(dao: &Dao) -> Result<Self> {
@@ -1,9 +1,17 @@
 use log::error;
+use mongodb::bson::oid::ObjectId;
+use mongodb::change_stream::event::{ChangeStreamEvent, OperationType};
+use serde::Serialize;
 use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
 
 use crate::dao::Memory;
+use crate::metrics;
+use crate::streams;
+use crate::webhooks;
 
-use crate::{Result, Shared};
+use crate::{Result, Shared, UsermanError};
 
 #[derive(Debug)]
 pub enum Event {
@@ -13,72 +21,140 @@ pub enum Event {
     Apps,
 }
 
-pub async fn run(shared: Shared) -> Result<()> {
-    let (tx, mut rx) = mpsc::channel::<Event>(100);
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Operation {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// Document changed in a watched collection.
+#[derive(Debug)]
+pub struct Change {
+    pub event: Event,
+    /// `None` for the operations on the whole collection.
+    pub operation: Option<Operation>,
+    pub id: Option<ObjectId>,
+    /// Resume token of the change, the same on every instance.
+    pub key: String,
+}
 
-    let tx_ref = tx.clone();
-    let dao_ref = shared.dao.clone();
+impl Change {
+    pub fn new<T>(event: Event, change: &ChangeStreamEvent<T>) -> Self {
+        let operation = match change.operation_type {
+            OperationType::Insert => Some(Operation::Created),
+            OperationType::Update | OperationType::Replace => Some(Operation::Updated),
+            OperationType::Delete => Some(Operation::Deleted),
+            _ => None,
+        };
 
-    tokio::spawn(async move {
-        if let Err(err) = dao_ref.watch_configs(&tx_ref).await {
-            error!("{}", err);
+        Self {
+            event,
+            operation,
+            id: change
+                .document_key
+                .as_ref()
+                .and_then(|t| t.get_object_id("_id").ok()),
+            key: serde_json::to_string(&change.id).unwrap_or_default(),
         }
-    });
+    }
+}
 
-    let tx_ref = tx.clone();
-    let dao_ref = shared.dao.clone();
+/// Reload the caches on `change`, then notify the webhooks and the permission
+/// streams.
+async fn changed(shared: &Shared, change: Change) {
+    let event = &change.event;
+    metrics::watcher_event(event);
 
-    tokio::spawn(async move {
-        if let Err(err) = dao_ref.watch_users(&tx_ref).await {
-            error!("{}", err);
-        }
-    });
+    // Deleted entities are only left in the cache before the reload.
+    let removed = match change.operation {
+        Some(Operation::Deleted) => webhooks::snapshot(shared, &change).await,
+        _ => None,
+    };
 
-    let tx_ref = tx.clone();
-    let dao_ref = shared.dao.clone();
+    // A user or a role can lose roles or apps, keep the previous ones too.
+    let mut related = streams::related(shared, &change).await;
 
-    tokio::spawn(async move {
-        if let Err(err) = dao_ref.watch_roles(&tx_ref).await {
-            error!("{}", err);
+    let result = match event {
+        Event::Configs => {
+            let configs = shared.configs.reload(&shared.dao).await;
+            let keys = shared.keys.reload(&shared.dao).await;
+            configs.and(keys)
         }
-    });
+        Event::Users => shared.users.reload(&shared.dao).await,
+        Event::Roles => shared.roles.reload(&shared.dao).await,
+        Event::Apps => shared.apps.reload(&shared.dao).await,
+    };
 
-    let dao_ref = shared.dao.clone();
+    if let Err(err) = &result {
+        metrics::error(err);
+        error!("{}", err);
+    }
 
-    tokio::spawn(async move {
-        if let Err(err) = dao_ref.watch_apps(&tx).await {
-            error!("{}", err);
-        }
-    });
-
-    while let Some(event) = rx.recv().await {
-        match event {
-            Event::Configs => {
-                if let Err(err) = shared.configs.reload(&shared.dao).await {
-                    error!("{}", err);
-                }
-
-                if let Err(err) = shared.keys.reload(&shared.dao).await {
-                    error!("{}", err);
-                }
-            }
-            Event::Users => {
-                if let Err(err) = shared.users.reload(&shared.dao).await {
-                    error!("{}", err);
-                }
-            }
-            Event::Roles => {
-                if let Err(err) = shared.roles.reload(&shared.dao).await {
-                    error!("{}", err);
-                }
+    metrics::cache_reload(event, &result);
+
+    let data = match change.operation {
+        Some(Operation::Deleted) => removed,
+        _ => webhooks::snapshot(shared, &change).await,
+    };
+
+    webhooks::changed(shared, &change, data).await;
+
+    related.extend(streams::related(shared, &change).await);
+    shared.streams.publish(&change, related);
+}
+
+/// Watch every collection and handle the changes until `token` is cancelled
+/// and the change streams are closed. A change stream ending before fails, the
+/// caches would go stale.
+pub async fn run(shared: Shared, token: CancellationToken) -> Result<()> {
+    let (tx, mut rx) = mpsc::channel::<Change>(100);
+    let mut watchers = JoinSet::new();
+
+    for event in [Event::Configs, Event::Users, Event::Roles, Event::Apps] {
+        let tx = tx.clone();
+        let dao = shared.dao.clone();
+        let watcher = shared.health.watcher();
+        let token = token.clone();
+
+        watchers.spawn(async move {
+            let _watcher = watcher;
+
+            match event {
+                Event::Configs => dao.watch_configs(&tx, &token).await,
+                Event::Users => dao.watch_users(&tx, &token).await,
+                Event::Roles => dao.watch_roles(&tx, &token).await,
+                Event::Apps => dao.watch_apps(&tx, &token).await,
             }
-            Event::Apps => {
-                if let Err(err) = shared.apps.reload(&shared.dao).await {
-                    error!("{}", err);
-                }
+        });
+    }
+
+    // The channel closes once every watcher ended.
+    drop(tx);
+
+    loop {
+        tokio::select! {
+            t = rx.recv() => match t {
+                Some(change) => changed(&shared, change).await,
+                None => break,
+            },
+            Some(t) = watchers.join_next(), if !token.is_cancelled() => {
+                return Err(match t {
+                    Ok(Err(err)) => err,
+                    _ => UsermanError::TaskStopped,
+                });
             }
         }
     }
 
+    while let Some(t) = watchers.join_next().await {
+        match t {
+            Ok(Err(err)) => error!("{}", err),
+            Err(err) => error!("{}", err),
+            Ok(Ok(())) => {}
+        }
+    }
+
     Ok(())
 }
@@ -1,14 +1,20 @@
 use axum::http::Uri;
 use axum::response::IntoResponse;
 use axum::routing::get;
-use axum::{Extension, Router};
+use axum::{middleware, Extension, Router};
 use axum_server::tls_rustls::RustlsConfig;
+use axum_server::Handle;
+use log::info;
 use std::net::SocketAddr;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
 use crate::api::{v1, openapi::ApiV1Doc};
-use crate::{files, Result, Shared, UsermanError};
+use crate::config_yaml::ConfigYAML;
+use crate::tls::{self, ClientCertAcceptor};
+use crate::{access, files, health, metrics, telemetry, Result, Shared, UsermanError};
 
 async fn index_handler(Extension(shared): Extension<Shared>) -> impl IntoResponse {
     let uri = format!("{}/index.html", shared.config_yaml.front.public_url)
@@ -21,12 +27,47 @@ async fn static_handler(Extension(shared): Extension<Shared>, uri: Uri) -> impl
     files::StaticFile::new(uri, shared.config_yaml.front.public_url)
 }
 
-pub async fn run(shared: Shared) -> Result<()> {
+/// Read the TLS files served by [`run`], reloadable while serving.
+pub async fn rustls(config_yaml: &ConfigYAML) -> Result<Option<RustlsConfig>> {
+    match config_yaml.tls.enabled {
+        true => tls::rustls(&config_yaml.tls).await.map(Some),
+        false => Ok(None),
+    }
+}
+
+/// Serve until `token` is cancelled, then wait up to `shutdownTimeout` for the
+/// in-flight requests.
+pub async fn run(
+    shared: Shared,
+    rustls: Option<RustlsConfig>,
+    token: CancellationToken,
+) -> Result<()> {
     let config_yaml = shared.config_yaml.clone();
     let address = SocketAddr::new(config_yaml.ip, config_yaml.port);
+    let timeout = Duration::from_secs(config_yaml.shutdown_timeout);
+
+    let handle = Handle::new();
+    let handle_ref = handle.clone();
+    let streams = shared.streams.clone();
+
+    tokio::spawn(async move {
+        token.cancelled().await;
+        // The event streams never end by themselves.
+        streams.close();
+        info!(
+            "Waiting up to {}s for {} connection(s).",
+            timeout.as_secs(),
+            handle_ref.connection_count()
+        );
+        handle_ref.graceful_shutdown(Some(timeout));
+    });
 
     let app = Router::new()
         .merge(SwaggerUi::new("/swagger-ui").url("/api-doc/ApiV1.json", ApiV1Doc::openapi()))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/version", get(health::version))
+        .route("/metrics", get(metrics::handler))
         .route(
             &format!("{}/", config_yaml.front.public_url),
             get(index_handler),
@@ -52,21 +93,21 @@ pub async fn run(shared: Shared) -> Result<()> {
             v1::routes(),
         )
         .fallback(static_handler)
+        .layer(middleware::from_fn(metrics::track))
+        .layer(middleware::from_fn(telemetry::trace))
+        .layer(middleware::from_fn(access::track))
         .layer(Extension(shared));
 
-    match config_yaml.tls.enabled {
-        true => {
-            let rustls = RustlsConfig::from_pem_file(&config_yaml.tls.certs, &config_yaml.tls.key)
-                .await
-                .map_err(|err| UsermanError::PEMFile(err.to_string()))?;
-
-            axum_server::bind_rustls(address, rustls)
-                .serve(app.into_make_service())
-                .await
-                .map_err(|err| UsermanError::WebServer(err.to_string()))
-        }
-        false => axum::Server::bind(&address)
-            .serve(app.into_make_service())
+    match rustls {
+        Some(rustls) => axum_server::bind(address)
+            .acceptor(ClientCertAcceptor::new(rustls))
+            .handle(handle)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .map_err(|err| UsermanError::WebServer(err.to_string())),
+        None => axum_server::bind(address)
+            .handle(handle)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
             .await
             .map_err(|err| UsermanError::WebServer(err.to_string())),
     }
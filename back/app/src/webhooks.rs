@@ -0,0 +1,625 @@
+//! Outbound webhooks. Subscriptions are stored in the `webhooks` collection and
+//! every event they match becomes a delivery in `deliveries`, posted by [`run`]
+//! with an HMAC-SHA256 signature and retried with an exponential backoff until
+//! it succeeds or is dead-lettered.
+
+use hmac::{Hmac, Mac};
+use log::{error, warn};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::serde_helpers::serialize_bson_datetime_as_rfc3339_string;
+use mongodb::bson::{doc, DateTime, Document};
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::config_yaml::Webhooks;
+use crate::dao::{Dao, Memory};
+use crate::pages::Page;
+use crate::watchers::{Change, Event, Operation};
+use crate::{
+    serialize_oid_as_string, serialize_option_oid_as_string, Result, Shared, UsermanError,
+};
+
+/// `t=<unix timestamp>,v1=<hex HMAC-SHA256 of "<timestamp>.<body>">`.
+pub const SIGNATURE: &str = "x-userman-signature";
+pub const EVENT: &str = "x-userman-event";
+pub const DELIVERY: &str = "x-userman-delivery";
+
+const SECRET_MIN_LEN: usize = 16;
+const DELIVERIES_LIMIT: usize = 50;
+const DELIVERIES_MAX_LIMIT: usize = 500;
+/// Longest wait between two looks for due deliveries.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+const MAX_DELAY: Duration = Duration::from_secs(86400);
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub enum WebhookEvent {
+    #[serde(rename = "user.created")]
+    UserCreated,
+    #[serde(rename = "user.updated")]
+    UserUpdated,
+    #[serde(rename = "user.deleted")]
+    UserDeleted,
+    #[serde(rename = "role.created")]
+    RoleCreated,
+    #[serde(rename = "role.updated")]
+    RoleUpdated,
+    #[serde(rename = "role.deleted")]
+    RoleDeleted,
+    #[serde(rename = "app.created")]
+    AppCreated,
+    #[serde(rename = "app.updated")]
+    AppUpdated,
+    #[serde(rename = "app.deleted")]
+    AppDeleted,
+    #[serde(rename = "session.login")]
+    Login,
+    #[serde(rename = "password.reset")]
+    PasswordReset,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UserCreated => "user.created",
+            Self::UserUpdated => "user.updated",
+            Self::UserDeleted => "user.deleted",
+            Self::RoleCreated => "role.created",
+            Self::RoleUpdated => "role.updated",
+            Self::RoleDeleted => "role.deleted",
+            Self::AppCreated => "app.created",
+            Self::AppUpdated => "app.updated",
+            Self::AppDeleted => "app.deleted",
+            Self::Login => "session.login",
+            Self::PasswordReset => "password.reset",
+        }
+    }
+
+    /// Event of a change seen by the watchers, if any.
+    pub fn of(change: &Change) -> Option<Self> {
+        let event = match (&change.event, change.operation?) {
+            (Event::Users, Operation::Created) => Self::UserCreated,
+            (Event::Users, Operation::Updated) => Self::UserUpdated,
+            (Event::Users, Operation::Deleted) => Self::UserDeleted,
+            (Event::Roles, Operation::Created) => Self::RoleCreated,
+            (Event::Roles, Operation::Updated) => Self::RoleUpdated,
+            (Event::Roles, Operation::Deleted) => Self::RoleDeleted,
+            (Event::Apps, Operation::Created) => Self::AppCreated,
+            (Event::Apps, Operation::Updated) => Self::AppUpdated,
+            (Event::Apps, Operation::Deleted) => Self::AppDeleted,
+            (Event::Configs, _) => return None,
+        };
+
+        Some(event)
+    }
+}
+
+impl std::fmt::Display for WebhookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Webhook {
+    #[serde(
+        rename(serialize = "id", deserialize = "_id"),
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_option_oid_as_string"
+    )]
+    #[schema(value_type = String)]
+    pub id: Option<ObjectId>,
+    pub url: String,
+    /// Key of the signatures. Never returned, kept when missing on updates.
+    #[serde(default, skip_serializing)]
+    pub secret: Option<String>,
+    pub events: Vec<WebhookEvent>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime>,
+}
+
+impl Webhook {
+    /// Check the fields given by the client, the secret is only required on
+    /// creation.
+    pub fn validate(&self, create: bool) -> Result<()> {
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            return Err(UsermanError::InvalidWebhook(String::from(
+                "url must start with http:// or https://",
+            )));
+        }
+
+        if self.events.is_empty() {
+            return Err(UsermanError::InvalidWebhook(String::from(
+                "events must not be empty",
+            )));
+        }
+
+        match &self.secret {
+            Some(t) if t.len() < SECRET_MIN_LEN => Err(UsermanError::InvalidWebhook(format!(
+                "secret must have at least {} characters",
+                SECRET_MIN_LEN
+            ))),
+            None if create => Err(UsermanError::InvalidWebhook(String::from(
+                "secret is required",
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// [`Webhook`] as stored, with its secret.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookDB<'a> {
+    url: &'a str,
+    secret: &'a Option<String>,
+    events: &'a Vec<WebhookEvent>,
+    enabled: bool,
+    description: &'a str,
+    created_at: DateTime,
+}
+
+impl<'a> From<&'a Webhook> for WebhookDB<'a> {
+    fn from(webhook: &'a Webhook) -> Self {
+        Self {
+            url: &webhook.url,
+            secret: &webhook.secret,
+            events: &webhook.events,
+            enabled: webhook.enabled,
+            description: &webhook.description,
+            created_at: DateTime::now(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    /// Every attempt failed, only sent again on request.
+    DeadLetter,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Delivered => "delivered",
+            Self::DeadLetter => "deadLetter",
+        }
+    }
+}
+
+fn serialize_option_date<S: Serializer>(
+    date: &Option<DateTime>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match date {
+        Some(t) => serialize_bson_datetime_as_rfc3339_string(t, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Delivery {
+    #[serde(
+        rename(serialize = "id", deserialize = "_id"),
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_option_oid_as_string"
+    )]
+    #[schema(value_type = String)]
+    pub id: Option<ObjectId>,
+    #[serde(serialize_with = "serialize_oid_as_string")]
+    #[schema(value_type = String)]
+    pub webhook_id: ObjectId,
+    pub event: WebhookEvent,
+    #[schema(value_type = Object)]
+    pub data: Value,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    /// HTTP status of the last attempt, if it got a response.
+    pub response_status: Option<u16>,
+    pub error: Option<String>,
+    /// Date of the next attempt while pending.
+    #[serde(serialize_with = "serialize_option_date")]
+    #[schema(value_type = String)]
+    pub next_attempt_at: Option<DateTime>,
+    #[serde(serialize_with = "serialize_bson_datetime_as_rfc3339_string")]
+    #[schema(value_type = String)]
+    pub created_at: DateTime,
+    #[serde(serialize_with = "serialize_option_date")]
+    #[schema(value_type = String)]
+    pub updated_at: Option<DateTime>,
+}
+
+/// [`Delivery`] as stored. `key` identifies the change or the request it comes
+/// from, so an event seen by several instances is queued once by webhook.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveryDB<'a> {
+    pub webhook_id: ObjectId,
+    pub key: &'a str,
+    pub event: WebhookEvent,
+    pub data: &'a Value,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    pub next_attempt_at: DateTime,
+    pub created_at: DateTime,
+}
+
+/// Body of the requests.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Body<'a> {
+    id: String,
+    event: WebhookEvent,
+    #[serde(serialize_with = "serialize_bson_datetime_as_rfc3339_string")]
+    created_at: DateTime,
+    data: &'a Value,
+}
+
+/// Value of the signature header of `body` sent at `timestamp`.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> Result<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .map_err(|err| UsermanError::Webhook(err.to_string()))?;
+
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    Ok(format!(
+        "t={},v1={}",
+        timestamp,
+        hex::encode(mac.finalize().into_bytes())
+    ))
+}
+
+/// Delay before the attempt following `attempts` failed ones.
+pub fn backoff(config: &Webhooks, attempts: u32) -> Duration {
+    let factor = 2u64.saturating_pow(attempts.saturating_sub(1));
+
+    Duration::from_secs(config.retry_delay.saturating_mul(factor)).min(MAX_DELAY)
+}
+
+/// Wakes [`run`] up when deliveries are queued.
+#[derive(Clone, Default)]
+pub struct Notifier(Arc<Notify>);
+
+impl Notifier {
+    pub fn wake(&self) {
+        self.0.notify_one();
+    }
+}
+
+/// Queue a delivery of `event` to every enabled webhook subscribed to it.
+async fn queue(shared: &Shared, event: WebhookEvent, key: &str, data: &Value) {
+    let webhooks = match shared.dao.read_webhooks_by_event(event).await {
+        Ok(t) => t,
+        Err(err) => {
+            error!("Webhooks of {} not read. {}", event, err);
+            return;
+        }
+    };
+
+    let now = DateTime::now();
+    let mut queued = false;
+
+    for webhook_id in webhooks.iter().filter_map(|t| t.id) {
+        let delivery = DeliveryDB {
+            webhook_id,
+            key,
+            event,
+            data,
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            next_attempt_at: now,
+            created_at: now,
+        };
+
+        match shared.dao.create_delivery(&delivery).await {
+            Ok(t) => queued |= t,
+            Err(err) => error!("Delivery of {} not queued. {}", event, err),
+        }
+    }
+
+    if queued {
+        shared.webhooks.wake();
+    }
+}
+
+/// Queue the deliveries of an event raised while serving a request.
+pub async fn emit(shared: &Shared, event: WebhookEvent, data: Value) {
+    queue(shared, event, &ObjectId::new().to_hex(), &data).await;
+}
+
+/// Queue the deliveries of a change seen by the watchers. `data` is the
+/// changed entity, only its id is sent when it isn't cached.
+pub async fn changed(shared: &Shared, change: &Change, data: Option<Value>) {
+    let Some(event) = WebhookEvent::of(change) else {
+        return;
+    };
+
+    let data = match (data, change.id) {
+        (Some(t), _) => t,
+        (None, Some(t)) => json!({ "id": t.to_hex() }),
+        (None, None) => Value::Null,
+    };
+
+    queue(shared, event, &change.key, &data).await;
+}
+
+/// Cached state of the entity of `change`, without the user password.
+pub async fn snapshot(shared: &Shared, change: &Change) -> Option<Value> {
+    let id = change.id.as_ref()?;
+
+    let value = match change.event {
+        Event::Users => serde_json::to_value(shared.users.get_by_id(id).await?.none_password()),
+        Event::Roles => serde_json::to_value(shared.roles.get_by_id(id).await?),
+        Event::Apps => serde_json::to_value(shared.apps.get_by_id(id).await?),
+        Event::Configs => return None,
+    };
+
+    value.ok()
+}
+
+/// Post `delivery` to `webhook`, returns the response status if any.
+async fn post(
+    client: &reqwest::Client,
+    webhook: &Webhook,
+    delivery: &Delivery,
+) -> (Option<u16>, Result<()>) {
+    let id = delivery.id.map(|t| t.to_hex()).unwrap_or_default();
+
+    let body = Body {
+        id: id.clone(),
+        event: delivery.event,
+        created_at: delivery.created_at,
+        data: &delivery.data,
+    };
+
+    let body = match serde_json::to_vec(&body) {
+        Ok(t) => t,
+        Err(err) => return (None, Err(UsermanError::CreateJSON(err))),
+    };
+
+    let secret = webhook.secret.as_deref().unwrap_or_default();
+
+    let signature = match sign(secret, chrono::Utc::now().timestamp(), &body) {
+        Ok(t) => t,
+        Err(err) => return (None, Err(err)),
+    };
+
+    let response = client
+        .post(&webhook.url)
+        .header(CONTENT_TYPE, "application/json")
+        .header(EVENT, delivery.event.as_str())
+        .header(DELIVERY, id)
+        .header(SIGNATURE, signature)
+        .body(body)
+        .send()
+        .await;
+
+    match response {
+        Ok(t) if t.status().is_success() => (Some(t.status().as_u16()), Ok(())),
+        Ok(t) => (
+            Some(t.status().as_u16()),
+            Err(UsermanError::Webhook(format!(
+                "Response status {}.",
+                t.status()
+            ))),
+        ),
+        Err(err) => (None, Err(UsermanError::Webhook(err.to_string()))),
+    }
+}
+
+/// Post `delivery` once to `webhook`, read beforehand, and set its state from
+/// the outcome.
+pub async fn attempt(
+    config: &Webhooks,
+    client: &reqwest::Client,
+    webhook: Result<Option<Webhook>>,
+    mut delivery: Delivery,
+) -> Delivery {
+    let now = DateTime::now();
+
+    let (status, result) = match webhook {
+        Ok(Some(t)) if t.enabled => post(client, &t, &delivery).await,
+        Ok(Some(_)) => (
+            None,
+            Err(UsermanError::Webhook(String::from("Disabled webhook."))),
+        ),
+        Ok(None) => (None, Err(UsermanError::WebhookNotFound)),
+        Err(err) => (None, Err(err)),
+    };
+
+    delivery.attempts += 1;
+    delivery.response_status = status;
+    delivery.updated_at = Some(now);
+
+    match result {
+        Ok(()) => {
+            delivery.status = DeliveryStatus::Delivered;
+            delivery.error = None;
+            delivery.next_attempt_at = None;
+        }
+        Err(err) if delivery.attempts >= config.max_attempts => {
+            warn!(
+                "Delivery {} of {} dead-lettered. {}",
+                delivery.id.unwrap_or_default(),
+                delivery.event,
+                err
+            );
+
+            delivery.status = DeliveryStatus::DeadLetter;
+            delivery.error = Some(err.to_string());
+            delivery.next_attempt_at = None;
+        }
+        Err(err) => {
+            let delay = backoff(config, delivery.attempts);
+
+            delivery.status = DeliveryStatus::Pending;
+            delivery.error = Some(err.to_string());
+            delivery.next_attempt_at = Some(DateTime::from_millis(
+                now.timestamp_millis() + delay.as_millis() as i64,
+            ));
+        }
+    }
+
+    delivery
+}
+
+/// Attempt `delivery` and store the outcome, returns its webhook.
+async fn deliver(shared: Shared, client: reqwest::Client, delivery: Delivery) -> ObjectId {
+    let webhook_id = delivery.webhook_id;
+    let webhook = shared.dao.read_webhook_by_id(&webhook_id).await;
+    let delivery = attempt(&shared.config_yaml.webhooks, &client, webhook, delivery).await;
+
+    if let Err(err) = shared.dao.update_delivery(&delivery).await {
+        error!("{}", err);
+    }
+
+    webhook_id
+}
+
+/// Post every due delivery, up to `concurrency` at a time and `per_webhook` to
+/// the same webhook, so a slow endpoint only holds its own deliveries.
+async fn deliver_due(shared: &Shared, client: &reqwest::Client, token: &CancellationToken) {
+    let config = &shared.config_yaml.webhooks;
+    // Other instances skip a claimed delivery until its attempt timed out.
+    let lease = Duration::from_secs(config.timeout.saturating_mul(2));
+
+    let mut tasks = JoinSet::new();
+    let mut running: HashMap<ObjectId, usize> = HashMap::new();
+
+    loop {
+        while !token.is_cancelled() && tasks.len() < config.concurrency {
+            let busy: Vec<ObjectId> = running
+                .iter()
+                .filter(|(_, count)| **count >= config.per_webhook)
+                .map(|(id, _)| *id)
+                .collect();
+
+            let delivery = match shared.dao.claim_delivery(lease, &busy).await {
+                Ok(Some(t)) => t,
+                Ok(None) => break,
+                Err(err) => {
+                    error!("{}", err);
+                    break;
+                }
+            };
+
+            *running.entry(delivery.webhook_id).or_default() += 1;
+            tasks.spawn(deliver(shared.clone(), client.clone(), delivery));
+        }
+
+        match tasks.join_next().await {
+            Some(Ok(t)) => {
+                if let Some(count) = running.get_mut(&t) {
+                    *count -= 1;
+                }
+            }
+            Some(Err(err)) => error!("{}", err),
+            None => break,
+        }
+    }
+}
+
+/// Post the deliveries as they are queued or due, until `token` is cancelled.
+pub async fn run(shared: Shared, token: CancellationToken) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(shared.config_yaml.webhooks.timeout))
+        .build()
+        .map_err(|err| UsermanError::Webhook(err.to_string()))?;
+
+    loop {
+        deliver_due(&shared, &client, &token).await;
+
+        tokio::select! {
+            _ = token.cancelled() => break,
+            _ = shared.webhooks.0.notified() => {}
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveryQuery {
+    pub status: Option<DeliveryStatus>,
+    pub event: Option<WebhookEvent>,
+    /// Page number starting at 1.
+    pub page: Option<usize>,
+    /// Deliveries per page, 50 by default and 500 at most.
+    pub limit: Option<usize>,
+}
+
+impl DeliveryQuery {
+    pub fn limit(&self) -> usize {
+        self.limit
+            .unwrap_or(DELIVERIES_LIMIT)
+            .clamp(1, DELIVERIES_MAX_LIMIT)
+    }
+
+    /// MongoDB filter of the deliveries of `webhook_id`.
+    pub fn filter(&self, webhook_id: ObjectId) -> Document {
+        let mut filter = doc! { "webhookId": webhook_id };
+
+        if let Some(t) = &self.status {
+            filter.insert("status", t.as_str());
+        }
+
+        if let Some(t) = &self.event {
+            filter.insert("event", t.as_str());
+        }
+
+        filter
+    }
+
+    /// Read the requested page, newest deliveries first.
+    pub async fn read(&self, dao: &Dao, webhook_id: ObjectId) -> Result<Page<Delivery>> {
+        let filter = self.filter(webhook_id);
+        let limit = self.limit();
+        let page = self.page.unwrap_or(1).max(1);
+
+        let total = dao.count_deliveries(filter.clone()).await?;
+        let items = dao
+            .read_deliveries(
+                filter,
+                (page - 1).saturating_mul(limit) as u64,
+                limit as i64,
+            )
+            .await?;
+
+        Ok(Page {
+            items,
+            total: total as usize,
+            page: Some(page),
+            limit: Some(limit),
+            next_cursor: None,
+        })
+    }
+}
@@ -14,6 +14,7 @@ export interface User {
   roles: string[];
   password?: string;
   avatar?: string;
+  changePassword?: boolean;
 }
 
 export interface Department {
@@ -78,14 +79,22 @@ export interface PostRefresh {
   accessToken: string;
 }
 
-export type GetUsers = User[];
+export interface Page<T> {
+  items: T[];
+  total: number;
+  page?: number;
+  limit?: number;
+  nextCursor?: string;
+}
+
+export type GetUsers = Page<User>;
 
 export type GetUsername = User;
 
-export type GetApps = App[];
+export type GetApps = Page<App>;
 
-export type GetRoles = Role[];
+export type GetRoles = Page<Role>;
 
 export type GetRoleName = Role;
 
-export type GetRoleNames = RoleName[];
\ No newline at end of file
+export type GetRoleNames = Page<RoleName>;
\ No newline at end of file